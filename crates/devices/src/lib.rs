@@ -15,7 +15,7 @@ pub mod utils;
 pub use fdt_parser as fdt;
 
 use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
-use device::{BlkDriver, DeviceSet, Driver, IntDriver, NetDriver, UartDriver};
+use device::{BlkDriver, DeviceSet, Driver, IntDriver, NetDriver, RtcDriver, UartDriver};
 use fdt_parser::Node;
 pub use linkme::{self, distributed_slice as linker_use};
 pub use polyhal::{consts::VIRT_ADDR_START, pagetable::PAGE_SIZE};
@@ -58,6 +58,11 @@ pub fn get_main_uart() -> Option<Arc<dyn UartDriver>> {
     MAIN_UART.try_get().cloned()
 }
 
+#[inline]
+pub fn get_rtc_device() -> Option<Arc<dyn RtcDriver>> {
+    ALL_DEVICES.lock().rtc.first().cloned()
+}
+
 #[inline]
 pub fn get_net_device(id: usize) -> Arc<dyn NetDriver> {
     ALL_DEVICES
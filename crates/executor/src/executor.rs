@@ -1,33 +1,68 @@
 use alloc::{
-    collections::VecDeque,
+    collections::{BTreeMap, BTreeSet},
     sync::{Arc, Weak},
     task::Wake,
     vec::Vec,
 };
 use core::{
     hint::spin_loop,
-    sync::atomic::{AtomicBool, Ordering},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
     task::{Context, Poll},
 };
 use hashbrown::HashMap;
 use log::info;
-use polyhal::{hart_id, PageTable};
+use polyhal::{hart_id, timer::get_ticks, PageTable};
 use sync::{LazyInit, Mutex};
 
-use crate::task::{AsyncTask, AsyncTaskItem, PinedFuture};
+use crate::{
+    sched::{self, RunQueue},
+    task::{AsyncTask, AsyncTaskItem, PinedFuture},
+    timer, WaitQueue,
+};
 
 pub type TaskId = usize;
 
 pub static TASK_MAP: LazyInit<Mutex<HashMap<usize, Weak<dyn AsyncTask>>>> = LazyInit::new();
-/// FIFO task queue, Items will be pushed to the end of the queue after being called.
-pub(crate) static TASK_QUEUE: Mutex<VecDeque<AsyncTaskItem>> = Mutex::new(VecDeque::new());
+/// Tasks returned `Poll::Pending` and are waiting for a waker to move them back.
+static PARKED_TASKS: Mutex<ParkedTasks> = Mutex::new(ParkedTasks::new());
+/// Timer interrupts counted by the interrupt handler, consumed by the executor loop.
+static TIMER_TICKS: AtomicUsize = AtomicUsize::new(0);
+/// Wakers waiting for the next timer tick.
+static TICK_QUEUE: WaitQueue = WaitQueue::new();
 
 pub static DEFAULT_EXECUTOR: Executor = Executor::new();
 
 static BOOT_PAGE: LazyInit<PageTable> = LazyInit::new();
 
+/// Parked tasks and the wake-ups that arrived while they were running.
+struct ParkedTasks {
+    tasks: BTreeMap<TaskId, AsyncTaskItem>,
+    woken: BTreeSet<TaskId>,
+}
+
+impl ParkedTasks {
+    const fn new() -> Self {
+        Self {
+            tasks: BTreeMap::new(),
+            woken: BTreeSet::new(),
+        }
+    }
+}
+
+/// The executor state of a core.
+struct Core {
+    /// The task running on this core.
+    current: Mutex<Option<Arc<dyn AsyncTask>>>,
+    /// Ready tasks of this core, ordered by their scheduling classes.
+    queue: Mutex<RunQueue>,
+    /// Timer ticks left in the time slice of the running task.
+    slice: AtomicUsize,
+    /// The running task should go back to the executor as soon as possible.
+    need_resched: AtomicBool,
+}
+
 pub struct Executor {
-    cores: LazyInit<Vec<Mutex<Option<Arc<dyn AsyncTask>>>>>,
+    cores: LazyInit<Vec<Core>>,
     inited: AtomicBool,
 }
 
@@ -41,8 +76,16 @@ impl Executor {
 
     pub fn init(&self, cores: usize) {
         let mut core_container = Vec::with_capacity(cores);
-        (0..cores).for_each(|_| core_container.push(Mutex::new(None)));
+        (0..cores).for_each(|_| {
+            core_container.push(Core {
+                current: Mutex::new(None),
+                queue: Mutex::new(RunQueue::new()),
+                slice: AtomicUsize::new(usize::MAX),
+                need_resched: AtomicBool::new(false),
+            })
+        });
         self.cores.init_by(core_container);
+        timer::init(cores);
 
         // Init TaskMAP with new empty hash map
         TASK_MAP.init_by(Mutex::new(HashMap::new()));
@@ -55,7 +98,80 @@ impl Executor {
     }
 
     pub fn spawn(&mut self, task: Arc<dyn AsyncTask>, future: PinedFuture) {
-        TASK_QUEUE.lock().push_back(AsyncTaskItem { future, task })
+        self.push_ready(AsyncTaskItem::new(task, future))
+    }
+
+    /// Push a ready task to the run queue of a core it is allowed to run on.
+    ///
+    /// The current core is preferred, idle cores will steal it later. The running task
+    /// of the target core is asked to reschedule if the new task should preempt it.
+    pub(crate) fn push_ready(&self, task_item: AsyncTaskItem) {
+        let mask = task_item.task.cpu_mask();
+        let current = hart_id();
+        let target = match mask & (1 << current) != 0 {
+            true => current,
+            false => (0..self.cores.len())
+                .find(|id| mask & (1 << id) != 0)
+                .unwrap_or(current),
+        };
+        let core = &self.cores[target];
+        let attr = task_item.task.sched_attr();
+        core.queue.lock().push(task_item);
+        if core
+            .current
+            .lock()
+            .as_ref()
+            .is_some_and(|running| attr.preempts(&running.sched_attr()))
+        {
+            core.need_resched.store(true, Ordering::Release);
+        }
+    }
+
+    /// Get the next task for the current core.
+    ///
+    /// Real-time tasks on other cores are stolen if they have a higher priority than the
+    /// local ones, fair tasks are only stolen if the local queue is empty.
+    fn pop_ready(&self) -> Option<AsyncTaskItem> {
+        let current = hart_id();
+        let allowed = |x: &AsyncTaskItem| x.task.cpu_mask() & (1 << current) != 0;
+        let local = self.cores[current]
+            .queue
+            .lock()
+            .highest_rt_priority(|_| true);
+        let remote = (1..self.cores.len())
+            .map(|i| (current + i) % self.cores.len())
+            .filter_map(|id| {
+                Some((
+                    self.cores[id].queue.lock().highest_rt_priority(allowed)?,
+                    id,
+                ))
+            })
+            .max_by_key(|(priority, id)| (*priority, core::cmp::Reverse(*id)));
+        if let Some((priority, id)) = remote {
+            if local.is_none_or(|local| local < priority) {
+                if let Some(task_item) = self.cores[id].queue.lock().pop(allowed) {
+                    return Some(task_item);
+                }
+            }
+        }
+        if let Some(task_item) = self.cores[current].queue.lock().pop(|_| true) {
+            return Some(task_item);
+        }
+        (1..self.cores.len())
+            .map(|i| &self.cores[(current + i) % self.cores.len()])
+            .find_map(|core| core.queue.lock().pop(allowed))
+    }
+
+    /// Check whether there is a task that the current core can run.
+    fn has_ready(&self) -> bool {
+        let current = hart_id();
+        self.cores.iter().enumerate().any(|(id, core)| {
+            let queue = core.queue.lock();
+            match id == current {
+                true => queue.any(|_| true),
+                false => queue.any(|x| x.task.cpu_mask() & (1 << current) != 0),
+            }
+        })
     }
 
     pub fn run(&self) {
@@ -68,35 +184,104 @@ impl Executor {
         while !self.inited.load(Ordering::SeqCst) {
             spin_loop();
         }
+        let mut last_tick = TIMER_TICKS.load(Ordering::Acquire);
         loop {
+            let tick = TIMER_TICKS.load(Ordering::Acquire);
+            if tick != last_tick {
+                last_tick = tick;
+                timer::expire_timers();
+                TICK_QUEUE.wake_all();
+            }
             self.run_ready_task();
-            self.hlt_if_idle();
+            self.hlt_if_idle(last_tick);
         }
     }
 
     fn run_ready_task(&self) {
-        let task = TASK_QUEUE.lock().pop_front();
+        let task = self.pop_ready();
         if let Some(task_item) = task {
-            let AsyncTaskItem { task, mut future } = task_item;
+            let AsyncTaskItem {
+                task,
+                mut future,
+                mut vruntime,
+                waker,
+            } = task_item;
+            let task_id = task.get_task_id();
             task.before_run();
-            *self.cores[hart_id()].lock() = Some(task.clone());
-            // Create Waker
-            let waker = Arc::new(Waker {
-                task_id: task.get_task_id(),
-            })
-            .into();
+            let core = &self.cores[hart_id()];
+            *core.current.lock() = Some(task.clone());
+            core.slice
+                .store(task.sched_attr().time_slice(), Ordering::Relaxed);
+            core.need_resched.store(false, Ordering::Release);
+            // The task is polled right now, earlier wake-ups are consumed by this poll.
+            PARKED_TASKS.lock().woken.remove(&task_id);
             let mut context = Context::from_waker(&waker);
 
-            match future.as_mut().poll(&mut context) {
-                Poll::Ready(()) => {} // task done
-                Poll::Pending => TASK_QUEUE.lock().push_back(AsyncTaskItem { future, task }),
+            let start = get_ticks();
+            let res = future.as_mut().poll(&mut context);
+            vruntime += sched::weighted_runtime(&task.sched_attr(), (get_ticks() - start) as u64);
+            match res {
+                Poll::Ready(()) => {
+                    // task done
+                    PARKED_TASKS.lock().woken.remove(&task_id);
+                }
+                Poll::Pending => {
+                    let mut parked = PARKED_TASKS.lock();
+                    // Re-queue the task if it was woken up while it was running.
+                    match parked.woken.remove(&task_id) {
+                        true => self.push_ready(AsyncTaskItem {
+                            future,
+                            task,
+                            vruntime,
+                            waker,
+                        }),
+                        false => {
+                            parked.tasks.insert(
+                                task_id,
+                                AsyncTaskItem {
+                                    future,
+                                    task,
+                                    vruntime,
+                                    waker,
+                                },
+                            );
+                        }
+                    }
+                }
             }
         }
     }
 
+    /// Check whether the task is running on any core.
+    fn is_running(&self, task_id: TaskId) -> bool {
+        self.cores.iter().any(|core| {
+            core.current
+                .lock()
+                .as_ref()
+                .is_some_and(|task| task.get_task_id() == task_id)
+        })
+    }
+
     /// Executes the `hlt` instruction if there are no ready tasks
-    fn hlt_if_idle(&self) {
-        // arch::wfi();
+    fn hlt_if_idle(&self, last_tick: usize) {
+        if self.has_ready() || TIMER_TICKS.load(Ordering::Acquire) != last_tick {
+            return;
+        }
+        // The next timer interrupt will wake up this core.
+        wait_for_interrupt();
+    }
+}
+
+/// Stop the current core until the next interrupt arrives.
+#[inline]
+fn wait_for_interrupt() {
+    unsafe {
+        #[cfg(any(target_arch = "riscv64", target_arch = "aarch64"))]
+        core::arch::asm!("wfi");
+        #[cfg(target_arch = "x86_64")]
+        core::arch::asm!("hlt");
+        #[cfg(target_arch = "loongarch64")]
+        core::arch::asm!("idle 0");
     }
 }
 
@@ -106,9 +291,8 @@ impl Default for Executor {
     }
 }
 
-#[allow(dead_code)]
 pub struct Waker {
-    task_id: TaskId,
+    pub(crate) task_id: TaskId,
 }
 
 impl Wake for Waker {
@@ -116,7 +300,61 @@ impl Wake for Waker {
         self.wake_by_ref();
     }
 
-    fn wake_by_ref(self: &Arc<Self>) {}
+    fn wake_by_ref(self: &Arc<Self>) {
+        wake_task(self.task_id);
+    }
+}
+
+/// Move a parked task back to the run queue.
+///
+/// If the task is running now, it will be polled again after it returns `Poll::Pending`.
+pub fn wake_task(task_id: TaskId) {
+    let mut parked = PARKED_TASKS.lock();
+    if let Some(task_item) = parked.tasks.remove(&task_id) {
+        DEFAULT_EXECUTOR.push_ready(task_item);
+    } else if DEFAULT_EXECUTOR.is_running(task_id) {
+        parked.woken.insert(task_id);
+    }
+}
+
+/// Handle the timer interrupt, called in the timer interrupt handler.
+///
+/// The next timer interrupt is programmed for the nearest deadline or scheduler tick.
+/// Expired timers and wakers registered by [wake_on_next_tick] will be woken by the
+/// executor loop. Scheduler ticks are charged to the time slice of the task running
+/// on this core.
+#[inline]
+pub fn timer_tick() {
+    TIMER_TICKS.fetch_add(1, Ordering::Release);
+    if !timer::handle_interrupt() || !DEFAULT_EXECUTOR.cores.is_init() {
+        return;
+    }
+    let core = &DEFAULT_EXECUTOR.cores[hart_id()];
+    match core.slice.load(Ordering::Relaxed) {
+        usize::MAX => {}
+        0 | 1 => core.need_resched.store(true, Ordering::Release),
+        slice => core.slice.store(slice - 1, Ordering::Relaxed),
+    }
+}
+
+/// Check whether the task running on this core used up its time slice or was
+/// preempted by a task with a higher priority.
+///
+/// Long running tasks should check this and yield to the executor if it returns true.
+#[inline]
+pub fn need_resched() -> bool {
+    DEFAULT_EXECUTOR.cores[hart_id()]
+        .need_resched
+        .load(Ordering::Acquire)
+}
+
+/// Wake the task when the next timer interrupt arrives.
+///
+/// This is used by futures which can't be woken by an event, such as devices without
+/// interrupt. Use [crate::timer::Sleep] to wait for a deadline.
+#[inline]
+pub fn wake_on_next_tick(waker: &core::task::Waker) {
+    TICK_QUEUE.register(waker);
 }
 
 /// Alloc a task id.
@@ -142,12 +380,25 @@ pub fn release_task(tid: usize) {
 pub fn current_task() -> Arc<dyn AsyncTask> {
     // CURRENT_TASK.lock().as_ref().map(|x| x.clone()).unwrap()
     DEFAULT_EXECUTOR.cores[hart_id()]
+        .current
         .lock()
         .as_ref()
         .map(|x| x.clone())
         .unwrap()
 }
 
+/// Get the id of the core which runs the current task.
+#[inline]
+pub fn current_cpu() -> usize {
+    hart_id()
+}
+
+/// Get the number of cores which run the executor.
+#[inline]
+pub fn cpu_count() -> usize {
+    DEFAULT_EXECUTOR.cores.len()
+}
+
 pub fn boot_page_table() -> PageTable {
     *BOOT_PAGE
 }
@@ -4,8 +4,11 @@ extern crate alloc;
 
 mod executor;
 mod ops;
+pub mod sched;
 pub mod task;
 pub mod thread;
+pub mod timer;
+mod wait_queue;
 
 use core::task::Poll;
 use core::{future::Future, pin::Pin, task::Context};
@@ -13,7 +16,9 @@ use core::{future::Future, pin::Pin, task::Context};
 use alloc::boxed::Box;
 pub use executor::*;
 pub use ops::*;
+pub use sched::{SchedAttr, SchedPolicy};
 pub use task::AsyncTask;
+pub use wait_queue::WaitQueue;
 
 pub struct Select<A, B> {
     inner: Option<(A, B)>,
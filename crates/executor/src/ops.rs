@@ -1,3 +1,4 @@
+use crate::wake_on_next_tick;
 use core::{
     future::Future,
     pin::Pin,
@@ -21,11 +22,12 @@ impl Default for Yield {
 impl Future for Yield {
     type Output = ();
 
-    fn poll(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Self::Output> {
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         match self.0 {
             true => Poll::Ready(()),
             false => {
                 self.0 = true;
+                cx.waker().wake_by_ref();
                 Poll::Pending
             }
         }
@@ -35,3 +37,37 @@ impl Future for Yield {
 pub async fn yield_now() {
     Yield::new().await;
 }
+
+/// Park the task until the next timer tick or another wake-up.
+pub struct WaitTick(bool);
+
+impl WaitTick {
+    pub const fn new() -> Self {
+        Self(false)
+    }
+}
+
+impl Default for WaitTick {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Future for WaitTick {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.0 {
+            true => Poll::Ready(()),
+            false => {
+                self.0 = true;
+                wake_on_next_tick(cx.waker());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+pub async fn wait_tick() {
+    WaitTick::new().await;
+}
@@ -0,0 +1,203 @@
+//! Scheduling classes of the executor.
+//!
+//! Every core owns a [RunQueue]. Real-time tasks (`SCHED_FIFO` and `SCHED_RR`) are
+//! kept in per-priority FIFO lists and always run before the fair tasks. Fair tasks
+//! (`SCHED_OTHER`, `SCHED_BATCH` and `SCHED_IDLE`) are picked by the smallest virtual
+//! runtime, which grows slower for tasks with a lower nice value.
+
+use alloc::collections::{BTreeMap, VecDeque};
+
+use crate::task::AsyncTaskItem;
+
+/// The highest real-time priority.
+pub const MAX_RT_PRIO: u8 = 99;
+/// The lowest real-time priority.
+pub const MIN_RT_PRIO: u8 = 1;
+/// The lowest nice value (highest priority).
+pub const MIN_NICE: i8 = -20;
+/// The highest nice value (lowest priority).
+pub const MAX_NICE: i8 = 19;
+
+/// The time slice of a `SCHED_RR` task in timer ticks.
+pub const RR_TIME_SLICE: usize = 10;
+/// The time slice of a fair task with nice 0 in timer ticks.
+const FAIR_TIME_SLICE: usize = 4;
+/// The longest time slice of a fair task in timer ticks.
+const MAX_FAIR_TIME_SLICE: usize = 20;
+
+/// The weight of a task with nice 0.
+const NICE_0_WEIGHT: u64 = 1024;
+/// The weight of a `SCHED_IDLE` task.
+const IDLE_WEIGHT: u64 = 3;
+
+/// Weights of nice values from -20 to 19, each level is about 1.25 times of the next one.
+///
+/// Linux: <https://elixir.bootlin.com/linux/v6.6/source/kernel/sched/core.c#L11542>
+const NICE_TO_WEIGHT: [u64; 40] = [
+    88761, 71755, 56483, 46273, 36291, 29154, 23254, 18705, 14949, 11916, 9548, 7620, 6100, 4904,
+    3906, 3121, 2501, 1991, 1586, 1277, 1024, 820, 655, 526, 423, 335, 272, 215, 172, 137, 110, 87,
+    70, 56, 45, 36, 29, 23, 18, 15,
+];
+
+/// Scheduling policy, the values are the same as linux.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SchedPolicy {
+    /// SCHED_OTHER, the default time sharing policy.
+    #[default]
+    Normal = 0,
+    /// SCHED_FIFO, real-time task runs until it blocks or yields.
+    Fifo = 1,
+    /// SCHED_RR, real-time task with a time slice.
+    RoundRobin = 2,
+    /// SCHED_BATCH, scheduled like `Normal`.
+    Batch = 3,
+    /// SCHED_IDLE, runs only when nothing else wants the core.
+    Idle = 5,
+}
+
+impl SchedPolicy {
+    /// Parse the policy passed by `sched_setscheduler`.
+    pub fn from_usize(policy: usize) -> Option<Self> {
+        match policy {
+            0 => Some(Self::Normal),
+            1 => Some(Self::Fifo),
+            2 => Some(Self::RoundRobin),
+            3 => Some(Self::Batch),
+            5 => Some(Self::Idle),
+            _ => None,
+        }
+    }
+
+    /// Check if this is a real-time policy.
+    #[inline]
+    pub fn is_realtime(&self) -> bool {
+        matches!(self, Self::Fifo | Self::RoundRobin)
+    }
+
+    /// Get the valid static priority range of the policy.
+    pub fn priority_range(&self) -> (u8, u8) {
+        match self.is_realtime() {
+            true => (MIN_RT_PRIO, MAX_RT_PRIO),
+            false => (0, 0),
+        }
+    }
+}
+
+/// The scheduling attributes of a task.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SchedAttr {
+    /// Scheduling policy.
+    pub policy: SchedPolicy,
+    /// Static priority, 1..=99 for real-time policies, 0 for others.
+    pub priority: u8,
+    /// Nice value, only used by the fair policies.
+    pub nice: i8,
+}
+
+impl SchedAttr {
+    /// Get the load weight of a fair task.
+    fn weight(&self) -> u64 {
+        match self.policy {
+            SchedPolicy::Idle => IDLE_WEIGHT,
+            _ => NICE_TO_WEIGHT[(self.nice.clamp(MIN_NICE, MAX_NICE) - MIN_NICE) as usize],
+        }
+    }
+
+    /// Get the number of timer ticks the task may run before it is preempted.
+    ///
+    /// `SCHED_FIFO` tasks are never preempted by the time slice, fair tasks get a
+    /// slice proportional to their weight.
+    pub fn time_slice(&self) -> usize {
+        match self.policy {
+            SchedPolicy::Fifo => usize::MAX,
+            SchedPolicy::RoundRobin => RR_TIME_SLICE,
+            _ => (FAIR_TIME_SLICE as u64 * self.weight() / NICE_0_WEIGHT)
+                .clamp(1, MAX_FAIR_TIME_SLICE as u64) as usize,
+        }
+    }
+
+    /// Check if a task with this attribute should preempt a running task.
+    pub fn preempts(&self, running: &SchedAttr) -> bool {
+        self.policy.is_realtime()
+            && (!running.policy.is_realtime() || self.priority > running.priority)
+    }
+}
+
+/// Convert the ticks a task ran to virtual runtime, weighted by its nice value.
+pub(crate) fn weighted_runtime(attr: &SchedAttr, ticks: u64) -> u64 {
+    ticks * NICE_0_WEIGHT / attr.weight()
+}
+
+/// The run queue of a core.
+pub(crate) struct RunQueue {
+    /// Real-time tasks grouped by priority, every priority is a FIFO list.
+    rt: BTreeMap<u8, VecDeque<AsyncTaskItem>>,
+    /// Fair tasks.
+    fair: VecDeque<AsyncTaskItem>,
+    /// The smallest virtual runtime picked, tasks woken up will start from here
+    /// so that a long sleeper can't starve the others.
+    min_vruntime: u64,
+}
+
+impl RunQueue {
+    pub const fn new() -> Self {
+        Self {
+            rt: BTreeMap::new(),
+            fair: VecDeque::new(),
+            min_vruntime: 0,
+        }
+    }
+
+    /// Push a task to the end of its class.
+    pub fn push(&mut self, mut task_item: AsyncTaskItem) {
+        let attr = task_item.task.sched_attr();
+        match attr.policy.is_realtime() {
+            true => self
+                .rt
+                .entry(attr.priority)
+                .or_default()
+                .push_back(task_item),
+            false => {
+                task_item.vruntime = task_item.vruntime.max(self.min_vruntime);
+                self.fair.push_back(task_item);
+            }
+        }
+    }
+
+    /// Pick the task which should run next and is allowed by the filter.
+    ///
+    /// Real-time tasks with the highest priority come first, then the fair task
+    /// with the smallest virtual runtime.
+    pub fn pop(&mut self, filter: impl Fn(&AsyncTaskItem) -> bool) -> Option<AsyncTaskItem> {
+        for (_, queue) in self.rt.iter_mut().rev() {
+            if let Some(index) = queue.iter().position(&filter) {
+                return queue.remove(index);
+            }
+        }
+        self.rt.retain(|_, queue| !queue.is_empty());
+        let index = self
+            .fair
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| filter(x))
+            .min_by_key(|(_, x)| x.vruntime)
+            .map(|(index, _)| index)?;
+        let task_item = self.fair.remove(index)?;
+        self.min_vruntime = self.min_vruntime.max(task_item.vruntime);
+        Some(task_item)
+    }
+
+    /// Check if there is a task allowed by the filter.
+    pub fn any(&self, filter: impl Fn(&AsyncTaskItem) -> bool) -> bool {
+        self.rt.values().flatten().any(&filter) || self.fair.iter().any(&filter)
+    }
+
+    /// Get the highest priority of the real-time tasks allowed by the filter.
+    pub fn highest_rt_priority(&self, filter: impl Fn(&AsyncTaskItem) -> bool) -> Option<u8> {
+        self.rt
+            .iter()
+            .rev()
+            .find(|(_, queue)| queue.iter().any(&filter))
+            .map(|(priority, _)| *priority)
+    }
+}
@@ -1,9 +1,9 @@
-use core::{future::Future, pin::Pin};
+use core::{future::Future, pin::Pin, task::Waker};
 
 use alloc::{boxed::Box, sync::Arc};
 use downcast_rs::{impl_downcast, DowncastSync};
 
-use crate::{boot_page_table, TaskId};
+use crate::{boot_page_table, executor, sched::SchedAttr, TaskId};
 
 /// Default is kernel task
 pub const TYPE_KERNEL_TASK: u8 = 0;
@@ -20,6 +20,14 @@ pub trait AsyncTask: DowncastSync {
     fn exit(&self, exit_code: usize);
     /// Check if the task was exited successfully
     fn exit_code(&self) -> Option<usize>;
+    /// Get the mask of cores the task is allowed to run on, bit n is core n.
+    fn cpu_mask(&self) -> usize {
+        usize::MAX
+    }
+    /// Get the scheduling policy, priority and nice value of the task.
+    fn sched_attr(&self) -> SchedAttr {
+        SchedAttr::default()
+    }
 }
 
 /// This is a enum that indicates the task type.
@@ -48,6 +56,26 @@ pub type PinedFuture = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
 pub struct AsyncTaskItem {
     pub future: PinedFuture,
     pub task: Arc<dyn AsyncTask>,
+    /// The weighted time the task ran, used by the fair scheduling class.
+    pub vruntime: u64,
+    /// The waker passed to every poll of the task. Wait queues recognize it when the
+    /// task registers again, so a task waiting in a loop is only stored once.
+    pub waker: Waker,
+}
+
+impl AsyncTaskItem {
+    pub fn new(task: Arc<dyn AsyncTask>, future: PinedFuture) -> Self {
+        let waker = Arc::new(executor::Waker {
+            task_id: task.get_task_id(),
+        })
+        .into();
+        Self {
+            future,
+            task,
+            vruntime: 0,
+            waker,
+        }
+    }
 }
 
 /// This is a blank kernel task.
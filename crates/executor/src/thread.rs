@@ -4,7 +4,7 @@ use alloc::{boxed::Box, sync::Arc};
 
 use crate::{
     task::{AsyncTask, AsyncTaskItem, BlankKernelTask},
-    task_id_alloc, TASK_MAP, TASK_QUEUE,
+    task_id_alloc, DEFAULT_EXECUTOR, TASK_MAP,
 };
 
 #[inline]
@@ -12,10 +12,7 @@ pub fn spawn(task: Arc<dyn AsyncTask>, future: impl Future<Output = ()> + Send +
     TASK_MAP
         .lock()
         .insert(task.get_task_id(), Arc::downgrade(&task));
-    TASK_QUEUE.lock().push_back(AsyncTaskItem {
-        future: Box::pin(future),
-        task,
-    });
+    DEFAULT_EXECUTOR.push_ready(AsyncTaskItem::new(task, Box::pin(future)));
 }
 
 #[inline]
@@ -24,8 +21,5 @@ pub fn spawn_blank(future: impl Future<Output = ()> + Send + 'static) {
     TASK_MAP
         .lock()
         .insert(task.get_task_id(), Arc::downgrade(&task));
-    TASK_QUEUE.lock().push_back(AsyncTaskItem {
-        future: Box::pin(future),
-        task,
-    })
+    DEFAULT_EXECUTOR.push_ready(AsyncTaskItem::new(task, Box::pin(future)))
 }
@@ -0,0 +1,225 @@
+//! Deadline timers of the executor.
+//!
+//! Timers are kept in a queue ordered by their deadlines. The timer interrupt of every
+//! core is programmed for the nearest deadline or the next scheduler tick, whichever
+//! comes first. The interrupt handler only touches atomics, expired timers are woken
+//! by the executor loop.
+
+use alloc::{collections::BTreeMap, vec::Vec};
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicU64, Ordering},
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+use polyhal::{
+    hart_id,
+    timer::{current_time, set_next_timer},
+};
+use sync::{LazyInit, Mutex};
+
+/// The interval of the scheduler tick.
+pub const TICK_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The identifier of a timer in the queue.
+pub type TimerId = usize;
+
+/// Registered timers ordered by their deadlines.
+struct TimerQueue {
+    timers: BTreeMap<(Duration, TimerId), Waker>,
+    next_id: TimerId,
+}
+
+static TIMERS: Mutex<TimerQueue> = Mutex::new(TimerQueue {
+    timers: BTreeMap::new(),
+    next_id: 0,
+});
+
+/// The nearest deadline in nanoseconds, read by the interrupt handler without the lock.
+static NEAREST: AtomicU64 = AtomicU64::new(u64::MAX);
+
+/// The timer interrupt state of a core, in nanoseconds.
+struct HartTimer {
+    /// When the next scheduler tick is due.
+    next_tick: AtomicU64,
+    /// When the timer interrupt of this core is programmed to fire.
+    next_event: AtomicU64,
+}
+
+static HART_TIMERS: LazyInit<Vec<HartTimer>> = LazyInit::new();
+
+pub(crate) fn init(cores: usize) {
+    HART_TIMERS.init_by(
+        (0..cores)
+            .map(|_| HartTimer {
+                next_tick: AtomicU64::new(0),
+                next_event: AtomicU64::new(0),
+            })
+            .collect(),
+    );
+}
+
+#[inline]
+fn as_nanos(time: Duration) -> u64 {
+    time.as_nanos().min(u64::MAX as u128) as u64
+}
+
+impl TimerQueue {
+    fn update_nearest(&self) {
+        let nearest = self
+            .timers
+            .first_key_value()
+            .map_or(u64::MAX, |((deadline, _), _)| as_nanos(*deadline));
+        NEAREST.store(nearest, Ordering::Release);
+    }
+}
+
+/// Wake the waker when the deadline arrives.
+///
+/// The returned id is used to update or cancel the timer.
+pub fn add_timer(deadline: Duration, waker: &Waker) -> TimerId {
+    let mut queue = TIMERS.lock();
+    let id = queue.next_id;
+    queue.next_id += 1;
+    queue.timers.insert((deadline, id), waker.clone());
+    queue.update_nearest();
+    drop(queue);
+    program_deadline(deadline);
+    id
+}
+
+/// Replace the waker of a timer, add it again if it was expired.
+pub fn update_timer(deadline: Duration, id: TimerId, waker: &Waker) {
+    let mut queue = TIMERS.lock();
+    match queue.timers.get_mut(&(deadline, id)) {
+        Some(old) if old.will_wake(waker) => {}
+        Some(old) => *old = waker.clone(),
+        None => {
+            queue.timers.insert((deadline, id), waker.clone());
+            queue.update_nearest();
+            drop(queue);
+            program_deadline(deadline);
+        }
+    }
+}
+
+/// Remove a timer from the queue.
+pub fn cancel_timer(deadline: Duration, id: TimerId) {
+    let mut queue = TIMERS.lock();
+    if queue.timers.remove(&(deadline, id)).is_some() {
+        queue.update_nearest();
+    }
+}
+
+/// Wake all expired timers, called by the executor loop.
+pub(crate) fn expire_timers() {
+    let now = current_time();
+    if as_nanos(now) < NEAREST.load(Ordering::Acquire) {
+        return;
+    }
+    let mut queue = TIMERS.lock();
+    let pending = queue.timers.split_off(&(now, TimerId::MAX));
+    let expired = core::mem::replace(&mut queue.timers, pending);
+    queue.update_nearest();
+    drop(queue);
+    expired.into_values().for_each(Waker::wake);
+}
+
+/// Fire the timer interrupt of the current core no later than the deadline.
+fn program_deadline(deadline: Duration) {
+    if !HART_TIMERS.is_init() {
+        return;
+    }
+    let hart = &HART_TIMERS[hart_id()];
+    let deadline = as_nanos(deadline);
+    if deadline < hart.next_event.load(Ordering::Acquire) {
+        hart.next_event.store(deadline, Ordering::Release);
+        set_next_timer(Duration::from_nanos(deadline));
+    }
+}
+
+/// Program the next timer interrupt of the current core, called in the timer
+/// interrupt handler.
+///
+/// Returns true if a scheduler tick is due, other interrupts are only for deadlines.
+pub(crate) fn handle_interrupt() -> bool {
+    let now = current_time();
+    if !HART_TIMERS.is_init() {
+        set_next_timer(now + TICK_INTERVAL);
+        return true;
+    }
+    let hart = &HART_TIMERS[hart_id()];
+    let now_ns = as_nanos(now);
+    let mut next_tick = hart.next_tick.load(Ordering::Relaxed);
+    let tick_due = now_ns >= next_tick;
+    if tick_due {
+        next_tick = as_nanos(now + TICK_INTERVAL);
+        hart.next_tick.store(next_tick, Ordering::Relaxed);
+    }
+    // Expired deadlines are handled by the executor loop soon, don't fire again for them.
+    let next_event = match NEAREST.load(Ordering::Acquire) {
+        nearest if nearest > now_ns => nearest.min(next_tick),
+        _ => next_tick,
+    };
+    hart.next_event.store(next_event, Ordering::Release);
+    set_next_timer(Duration::from_nanos(next_event));
+    tick_due
+}
+
+/// A future which is ready when the deadline arrives.
+pub struct Sleep {
+    deadline: Duration,
+    timer: Option<TimerId>,
+}
+
+impl Sleep {
+    /// Sleep until the deadline, the time is measured by `current_time`.
+    pub const fn until(deadline: Duration) -> Self {
+        Self {
+            deadline,
+            timer: None,
+        }
+    }
+
+    /// Sleep for the duration from now.
+    pub fn new(duration: Duration) -> Self {
+        Self::until(current_time() + duration)
+    }
+
+    /// Get the deadline of the sleep.
+    pub const fn deadline(&self) -> Duration {
+        self.deadline
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if current_time() >= self.deadline {
+            if let Some(id) = self.timer.take() {
+                cancel_timer(self.deadline, id);
+            }
+            return Poll::Ready(());
+        }
+        match self.timer {
+            Some(id) => update_timer(self.deadline, id, cx.waker()),
+            None => self.timer = Some(add_timer(self.deadline, cx.waker())),
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        if let Some(id) = self.timer.take() {
+            cancel_timer(self.deadline, id);
+        }
+    }
+}
+
+/// Sleep for the duration.
+pub async fn sleep(duration: Duration) {
+    Sleep::new(duration).await
+}
@@ -0,0 +1,35 @@
+use alloc::vec::Vec;
+use core::task::Waker;
+use sync::Mutex;
+
+/// A queue of wakers waiting for the same event.
+///
+/// Futures register their waker before returning `Poll::Pending`,
+/// the event producer wakes them up after the state changed.
+pub struct WaitQueue(Mutex<Vec<Waker>>);
+
+impl WaitQueue {
+    pub const fn new() -> Self {
+        Self(Mutex::new(Vec::new()))
+    }
+
+    /// Register a waker, the same waker is only stored once.
+    pub fn register(&self, waker: &Waker) {
+        let mut wakers = self.0.lock();
+        if !wakers.iter().any(|x| x.will_wake(waker)) {
+            wakers.push(waker.clone());
+        }
+    }
+
+    /// Wake all wakers in the queue.
+    pub fn wake_all(&self) {
+        let wakers: Vec<Waker> = self.0.lock().drain(..).collect();
+        wakers.into_iter().for_each(Waker::wake);
+    }
+}
+
+impl Default for WaitQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
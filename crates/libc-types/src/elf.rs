@@ -66,4 +66,6 @@ pub enum AuxType {
     SysInfo = 32,
     /// 指向 VDSO ELF 映射的起始地址
     SysInfoEhdr = 33,
+    /// 信号处理栈的最小大小（字节）
+    MinSigStkSz = 51,
 }
@@ -42,6 +42,10 @@ pub enum FcntlCmd {
     SETLKW = 7,
     /// like F_DUPFD, but additionally set the close-on-exec flag
     DUPFDCLOEXEC = 0x406,
+    /// add seals to a memfd
+    ADDSEALS = 0x409,
+    /// get the seals of a memfd
+    GETSEALS = 0x40a,
 }
 
 #[cfg(any(
@@ -152,3 +156,22 @@ bitflags! {
         const TMPFILE = 0o20040000;
     }
 }
+
+bitflags! {
+    /// memfd 的封印（seal），通过 fcntl 的 F_ADD_SEALS 添加，封印后不能再移除
+    ///
+    /// MUSL: <https://github.com/bminor/musl/blob/c47ad25ea3b484e10326f933e927c0bc8cded3da/include/fcntl.h#L178>
+    #[derive(Debug, Clone, Copy)]
+    pub struct SealFlags: u32 {
+        /// 不能再添加封印
+        const SEAL = 0x0001;
+        /// 不能缩小文件
+        const SHRINK = 0x0002;
+        /// 不能扩大文件
+        const GROW = 0x0004;
+        /// 不能写入文件，也不能以可写的共享方式映射
+        const WRITE = 0x0008;
+        /// 与 WRITE 相同，但已经存在的可写映射仍然可以写入
+        const FUTURE_WRITE = 0x0010;
+    }
+}
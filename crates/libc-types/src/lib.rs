@@ -25,7 +25,9 @@ pub mod others;
 pub mod poll;
 pub mod resource;
 pub mod sched;
+pub mod shm;
 pub mod signal;
+pub mod socket;
 pub mod termios;
 pub mod time;
 pub mod times;
@@ -2,6 +2,8 @@
 //!
 //! MUSL: <https://github.com/bminor/musl/blob/c47ad25ea3b484e10326f933e927c0bc8cded3da/include/sys/mman.h>
 
+use num_enum::TryFromPrimitive;
+
 bitflags! {
     /// MAP 标志位（用于 mmap 等内存映射操作）
     ///
@@ -72,5 +74,88 @@ bitflags! {
         const SYNC = 1 << 2;
     }
 
+    #[derive(Debug)]
+    /// mremap 标志
+    ///
+    /// MUSL: <https://github.com/bminor/musl/blob/c47ad25ea3b484e10326f933e927c0bc8cded3da/include/sys/mman.h#L71>
+    pub struct MremapFlags: u32 {
+        /// 无法原地扩展时允许移动映射
+        const MAYMOVE = 1 << 0;
+        /// 移动到 new_address 指定的地址
+        const FIXED = 1 << 1;
+        /// 移动后保留原来的映射
+        const DONTUNMAP = 1 << 2;
+    }
+
+    #[derive(Debug)]
+    /// swapon 标志
+    ///
+    /// MUSL: <https://github.com/bminor/musl/blob/c47ad25ea3b484e10326f933e927c0bc8cded3da/include/sys/swap.h>
+    pub struct SwapFlags: u32 {
+        /// 交换区优先级的掩码
+        const PRIO_MASK = 0x7fff;
+        /// 使用指定的优先级
+        const PREFER = 0x8000;
+        /// 丢弃交换区中释放的页
+        const DISCARD = 0x10000;
+        /// 启用交换区时丢弃整个交换区
+        const DISCARD_ONCE = 0x20000;
+        /// 丢弃交换区中释放的页簇
+        const DISCARD_PAGES = 0x40000;
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    /// mlockall 标志
+    ///
+    /// MUSL: <https://github.com/bminor/musl/blob/c47ad25ea3b484e10326f933e927c0bc8cded3da/include/sys/mman.h#L75>
+    pub struct MlockAllFlags: u32 {
+        /// 锁定当前已经映射的页
+        const CURRENT = 1;
+        /// 锁定之后映射的页
+        const FUTURE = 2;
+        /// 页在第一次访问时才被锁定，不预先加载
+        const ONFAULT = 4;
+    }
+
+    #[derive(Debug)]
+    /// memfd_create 标志
+    ///
+    /// MUSL: <https://github.com/bminor/musl/blob/c47ad25ea3b484e10326f933e927c0bc8cded3da/include/sys/mman.h#L103>
+    pub struct MemfdFlags: u32 {
+        /// 执行 exec 时关闭
+        const CLOEXEC = 0x0001;
+        /// 允许通过 fcntl 添加封印
+        const ALLOW_SEALING = 0x0002;
+        /// 使用大页（HugeTLB）
+        const HUGETLB = 0x0004;
+    }
+
+    #[derive(Debug)]
+    /// mlock2 标志
+    ///
+    /// MUSL: <https://github.com/bminor/musl/blob/c47ad25ea3b484e10326f933e927c0bc8cded3da/include/sys/mman.h#L99>
+    pub struct Mlock2Flags: u32 {
+        /// 页在第一次访问时才被锁定，不预先加载
+        const ONFAULT = 1;
+    }
+}
 
+/// madvise 的建议类型
+///
+/// MUSL: <https://github.com/bminor/musl/blob/c47ad25ea3b484e10326f933e927c0bc8cded3da/include/sys/mman.h#L80>
+#[repr(usize)]
+#[derive(Debug, Clone, Copy, PartialEq, TryFromPrimitive)]
+pub enum MadviseAdvice {
+    /// 没有特别的建议
+    Normal = 0,
+    /// 随机访问
+    Random = 1,
+    /// 顺序访问
+    Sequential = 2,
+    /// 即将访问，预先加载页面
+    WillNeed = 3,
+    /// 不再需要，释放页面，再次访问时重新填充
+    DontNeed = 4,
+    /// 可以在内存紧张时释放页面
+    Free = 8,
 }
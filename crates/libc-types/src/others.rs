@@ -17,3 +17,20 @@ pub enum ArchPrctlCmd {
     /// Get Per-CPU base
     GetGS = 0x1004,
 }
+
+/// `personality` 的参数，只查询当前的执行域而不修改
+pub const PERSONALITY_QUERY: u32 = 0xffff_ffff;
+
+/// `personality` 标志：关闭地址空间布局随机化（ASLR）
+///
+/// MUSL: <https://github.com/bminor/musl/blob/c47ad25ea3b484e10326f933e927c0bc8cded3da/include/sys/personality.h#L8>
+pub const ADDR_NO_RANDOMIZE: u32 = 0x0040000;
+
+/// `oom_score_adj` 的最小值，进程不会被 OOM killer 杀死
+pub const OOM_SCORE_ADJ_MIN: isize = -1000;
+
+/// `oom_score_adj` 的最大值，进程总是最先被 OOM killer 杀死
+pub const OOM_SCORE_ADJ_MAX: isize = 1000;
+
+/// 路径名的最大长度（单位字节），包括结尾的 `\0`
+pub const PATH_MAX: usize = 4096;
@@ -4,6 +4,31 @@
 
 use crate::types::TimeVal;
 
+/// 资源限制：进程可以使用的 CPU 时间（秒）
+pub const RLIMIT_CPU: usize = 0;
+/// 资源限制：进程可以写入的文件的最大大小（字节）
+pub const RLIMIT_FSIZE: usize = 1;
+/// 资源限制：数据段（堆和私有可写映射）的最大大小（字节）
+pub const RLIMIT_DATA: usize = 2;
+/// 资源限制：栈的最大大小（字节）
+pub const RLIMIT_STACK: usize = 3;
+/// 资源限制：core 文件的最大大小（字节）
+pub const RLIMIT_CORE: usize = 4;
+/// 资源限制：常驻内存的最大大小（字节）
+pub const RLIMIT_RSS: usize = 5;
+/// 资源限制：同一用户可以创建的最大进程数量
+pub const RLIMIT_NPROC: usize = 6;
+/// 资源限制：最大的文件描述符数量 + 1
+pub const RLIMIT_NOFILE: usize = 7;
+/// 资源限制：可以锁定在内存中的最大大小（字节）
+pub const RLIMIT_MEMLOCK: usize = 8;
+/// 资源限制：地址空间的最大大小（字节）
+pub const RLIMIT_AS: usize = 9;
+/// 资源限制的种类数量
+pub const RLIM_NLIMITS: usize = 16;
+/// 资源限制：不限制
+pub const RLIM_INFINITY: usize = usize::MAX;
+
 /// 资源限制结构体（对应 C 的 `struct rlimit`）
 /// 用于描述进程对某种资源的当前限制和最大限制
 #[repr(C)]
@@ -55,3 +80,17 @@ pub struct Rusage {
     /// 非自愿上下文切换次数（被内核抢占）
     pub nivcsw: i64,
 }
+
+/// `getrusage(2)` 的 who 参数：调用进程的资源使用情况
+pub const RUSAGE_SELF: isize = 0;
+/// `getrusage(2)` 的 who 参数：已经被等待回收的子进程的资源使用情况
+pub const RUSAGE_CHILDREN: isize = -1;
+/// `getrusage(2)` 的 who 参数：调用线程的资源使用情况
+pub const RUSAGE_THREAD: isize = 1;
+
+/// `setpriority(2)` / `getpriority(2)` 的 which 参数：按进程（线程）ID 设置
+pub const PRIO_PROCESS: usize = 0;
+/// `setpriority(2)` / `getpriority(2)` 的 which 参数：按进程组 ID 设置
+pub const PRIO_PGRP: usize = 1;
+/// `setpriority(2)` / `getpriority(2)` 的 which 参数：按用户 ID 设置
+pub const PRIO_USER: usize = 2;
@@ -0,0 +1,143 @@
+//! This module provides the `libc` types for Shm (System V shared memory).
+//!
+//! MUSL: <https://github.com/bminor/musl/blob/c47ad25ea3b484e10326f933e927c0bc8cded3da/include/sys/shm.h>
+
+/// 私有的 IPC 键，每次都创建新的对象（对应 `IPC_PRIVATE`）
+pub const IPC_PRIVATE: usize = 0;
+/// 键对应的对象不存在时创建（对应 `IPC_CREAT`）
+pub const IPC_CREAT: usize = 0o1000;
+/// 和 `IPC_CREAT` 一起使用，对象已经存在时失败（对应 `IPC_EXCL`）
+pub const IPC_EXCL: usize = 0o2000;
+/// MUSL 在 64 位下给 ctl 命令加上的标志，表示使用 64 位的结构体（对应 `IPC_64`）
+pub const IPC_64: usize = 0x100;
+
+/// shmctl 命令：标记删除共享内存段（对应 `IPC_RMID`）
+pub const IPC_RMID: usize = 0;
+/// shmctl 命令：设置共享内存段的属主和权限（对应 `IPC_SET`）
+pub const IPC_SET: usize = 1;
+/// shmctl 命令：读取共享内存段的信息（对应 `IPC_STAT`）
+pub const IPC_STAT: usize = 2;
+/// shmctl 命令：读取系统的共享内存限制（对应 `IPC_INFO`）
+pub const IPC_INFO: usize = 3;
+/// shmctl 命令：禁止换出共享内存段（对应 `SHM_LOCK`）
+pub const SHM_LOCK: usize = 11;
+/// shmctl 命令：允许换出共享内存段（对应 `SHM_UNLOCK`）
+pub const SHM_UNLOCK: usize = 12;
+/// shmctl 命令：按索引读取共享内存段的信息（对应 `SHM_STAT`）
+pub const SHM_STAT: usize = 13;
+/// shmctl 命令：读取系统的共享内存使用情况（对应 `SHM_INFO`）
+pub const SHM_INFO: usize = 14;
+/// shmctl 命令：按索引读取共享内存段的信息，不检查读权限（对应 `SHM_STAT_ANY`）
+pub const SHM_STAT_ANY: usize = 15;
+
+/// 共享内存段的模式标志：已经标记删除，最后一次分离时释放（对应 `SHM_DEST`）
+pub const SHM_DEST: u32 = 0o1000;
+/// 共享内存段的模式标志：已经被 `SHM_LOCK` 锁定（对应 `SHM_LOCKED`）
+pub const SHM_LOCKED: u32 = 0o2000;
+
+bitflags! {
+    /// shmat 的标志
+    #[derive(Debug, Clone, Copy)]
+    pub struct ShmAtFlags: usize {
+        /// 只读映射共享内存段
+        const RDONLY = 0o10000;
+        /// 把附加地址向下对齐到 `SHMLBA`
+        const RND = 0o20000;
+        /// 替换附加地址上已有的映射
+        const REMAP = 0o40000;
+        /// 允许执行共享内存段中的代码
+        const EXEC = 0o100000;
+    }
+}
+
+/// IPC 对象的属主和权限（对应 Linux 的 `struct ipc64_perm`）
+///
+/// MUSL: <https://github.com/bminor/musl/blob/c47ad25ea3b484e10326f933e927c0bc8cded3da/arch/generic/bits/ipc.h>
+#[repr(C)]
+#[derive(Debug, Clone, Default)]
+pub struct IpcPerm {
+    /// 创建对象时使用的键
+    pub key: i32,
+    /// 属主的用户 ID
+    pub uid: u32,
+    /// 属主的组 ID
+    pub gid: u32,
+    /// 创建者的用户 ID
+    pub cuid: u32,
+    /// 创建者的组 ID
+    pub cgid: u32,
+    /// 访问权限，低 9 位和文件权限相同
+    pub mode: u32,
+    /// 序列号
+    pub seq: u16,
+    /// 填充字段
+    pub __pad: u16,
+    /// 保留字段
+    pub __unused: [usize; 2],
+}
+
+/// 共享内存段的信息（对应 Linux 的 `struct shmid64_ds`）
+///
+/// MUSL: <https://github.com/bminor/musl/blob/c47ad25ea3b484e10326f933e927c0bc8cded3da/arch/generic/bits/shm.h>
+#[repr(C)]
+#[derive(Debug, Clone, Default)]
+pub struct ShmidDs {
+    /// 属主和权限
+    pub perm: IpcPerm,
+    /// 共享内存段的大小（字节）
+    pub segsz: usize,
+    /// 最后一次附加的时间（秒）
+    pub atime: usize,
+    /// 最后一次分离的时间（秒）
+    pub dtime: usize,
+    /// 最后一次修改的时间（秒）
+    pub ctime: usize,
+    /// 创建者的进程 ID
+    pub cpid: u32,
+    /// 最后一次附加或分离的进程 ID
+    pub lpid: u32,
+    /// 当前附加的次数
+    pub nattch: usize,
+    /// 保留字段
+    pub __unused: [usize; 2],
+}
+
+/// 系统的共享内存限制，由 `IPC_INFO` 返回（对应 `struct shminfo`）
+///
+/// MUSL: <https://github.com/bminor/musl/blob/c47ad25ea3b484e10326f933e927c0bc8cded3da/include/sys/shm.h#L68>
+#[repr(C)]
+#[derive(Debug, Clone, Default)]
+pub struct ShmLimits {
+    /// 单个共享内存段的最大大小（字节）
+    pub shmmax: usize,
+    /// 单个共享内存段的最小大小（字节）
+    pub shmmin: usize,
+    /// 共享内存段的最大数量
+    pub shmmni: usize,
+    /// 一个进程可以附加的最大段数
+    pub shmseg: usize,
+    /// 所有共享内存段的最大总页数
+    pub shmall: usize,
+    /// 保留字段
+    pub __unused: [usize; 4],
+}
+
+/// 系统的共享内存使用情况，由 `SHM_INFO` 返回（对应 `struct shm_info`）
+///
+/// MUSL: <https://github.com/bminor/musl/blob/c47ad25ea3b484e10326f933e927c0bc8cded3da/include/sys/shm.h#L73>
+#[repr(C)]
+#[derive(Debug, Clone, Default)]
+pub struct ShmInfo {
+    /// 当前存在的共享内存段数量
+    pub used_ids: i32,
+    /// 所有共享内存段的总页数
+    pub shm_tot: usize,
+    /// 驻留在内存中的页数
+    pub shm_rss: usize,
+    /// 被换出的页数
+    pub shm_swp: usize,
+    /// 未使用
+    pub swap_attempts: usize,
+    /// 未使用
+    pub swap_successes: usize,
+}
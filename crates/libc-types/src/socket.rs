@@ -0,0 +1,56 @@
+//! This module provides the `libc` types for Socket messages.
+//!
+//! MUSL: <https://github.com/bminor/musl/blob/c47ad25ea3b484e10326f933e927c0bc8cded3da/include/sys/socket.h>
+
+/// 套接字层级的选项和控制消息（对应 `SOL_SOCKET`）
+pub const SOL_SOCKET: i32 = 1;
+
+/// 控制消息携带文件描述符（对应 `SCM_RIGHTS`）
+pub const SCM_RIGHTS: i32 = 1;
+
+/// 控制消息缓冲区太小，部分控制消息被丢弃（对应 `MSG_CTRUNC`）
+pub const MSG_CTRUNC: u32 = 0x8;
+
+/// sendmsg/recvmsg 使用的消息头（对应 Linux 的 `struct user_msghdr`）
+///
+/// MUSL: <https://github.com/bminor/musl/blob/c47ad25ea3b484e10326f933e927c0bc8cded3da/include/sys/socket.h#L22>
+/// NOTE: 使用内核的布局，MUSL 在 64 位下用填充字段补齐 `msg_iovlen` 和 `msg_controllen`
+#[repr(C)]
+#[derive(Clone, Debug)]
+pub struct MsgHdr {
+    /// 对端地址（可以为空）
+    pub name: usize,
+    /// 对端地址的长度
+    pub name_len: u32,
+    /// 数据缓冲区数组（`struct iovec *`）
+    pub iov: usize,
+    /// 数据缓冲区的数量
+    pub iov_len: usize,
+    /// 控制消息缓冲区
+    pub control: usize,
+    /// 控制消息缓冲区的长度
+    pub control_len: usize,
+    /// 接收到的消息标志（如 `MSG_CTRUNC`）
+    pub flags: u32,
+}
+
+/// 控制消息头（对应 `struct cmsghdr`），后面紧跟消息数据
+///
+/// MUSL: <https://github.com/bminor/musl/blob/c47ad25ea3b484e10326f933e927c0bc8cded3da/include/sys/socket.h#L44>
+#[repr(C)]
+#[derive(Clone, Debug)]
+pub struct CmsgHdr {
+    /// 包含消息头在内的控制消息长度
+    pub len: usize,
+    /// 控制消息的协议层级（如 `SOL_SOCKET`）
+    pub level: i32,
+    /// 控制消息的类型（如 `SCM_RIGHTS`）
+    pub ty: i32,
+}
+
+impl CmsgHdr {
+    /// 把控制消息长度对齐到下一个控制消息的起始位置（对应 `CMSG_ALIGN`）
+    pub const fn align(len: usize) -> usize {
+        (len + size_of::<usize>() - 1) & !(size_of::<usize>() - 1)
+    }
+}
@@ -1,10 +1,15 @@
-use core::ops::Deref;
+use core::{
+    hint::spin_loop,
+    ops::Deref,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
 use alloc::vec::Vec;
-use bit_field::{BitArray, BitField};
 use log::info;
-use polyhal::{consts::VIRT_ADDR_START, pa, pagetable::PAGE_SIZE, PhysAddr};
-use sync::Mutex;
+use polyhal::{
+    common::get_cpu_num, consts::VIRT_ADDR_START, hart_id, pa, pagetable::PAGE_SIZE, PhysAddr,
+};
+use sync::{LazyInit, Mutex};
 
 pub const fn alignup(a: usize, b: usize) -> usize {
     a.div_ceil(b) * b
@@ -34,7 +39,7 @@ impl FrameTracker {
 impl Drop for FrameTracker {
     fn drop(&mut self) {
         self.clear();
-        FRAME_ALLOCATOR.lock().dealloc(self.0);
+        cache_dealloc(self.0);
     }
 }
 
@@ -46,11 +51,33 @@ impl Deref for FrameTracker {
     }
 }
 
+/// 伙伴系统的最大阶数, 最大的空闲块有 2^MAX_ORDER 个页
+///
+/// 阶数为 k 的块的物理页号按 2^k 对齐, 所以 2MiB 的大页可以直接从阶数为 9 的块中申请
+pub const MAX_ORDER: usize = 10;
+
+/// 空闲链表的结尾
+const LIST_END: usize = usize::MAX;
+
+/// 不是空闲块第一个页的页
+const NOT_FREE: u8 = u8::MAX;
+
+/// 空闲链表的节点
+///
+/// 节点直接保存在空闲块的第一个页中, 所以页帧分配器本身不会申请堆内存
+struct FreeNode {
+    prev: usize,
+    next: usize,
+}
+
 /// 页帧分布图
 ///
-/// 利用页帧分布图保存页帧分配器中的空闲内存，并且利用 bitArray 记录页帧使用情况
+/// 利用伙伴系统管理一块连续的空闲内存, 每个阶数有一个空闲块的双向链表,
+/// orders 记录每个页作为空闲块第一个页时的阶数, 用来在释放时查找并合并伙伴
 pub struct FrameRegionMap {
-    bits: Vec<usize>,
+    orders: Vec<u8>,
+    free_lists: [usize; MAX_ORDER + 1],
+    free: usize,
     paddr: PhysAddr,
     paddr_end: PhysAddr,
 }
@@ -62,96 +89,151 @@ impl FrameRegionMap {
     /// end_addr: usize 空闲页帧结束地址
     #[inline]
     pub fn new(start_addr: usize, end_addr: usize) -> Self {
-        let mut bits = vec![0usize; ((end_addr - start_addr) / PAGE_SIZE).div_ceil(64)];
-
-        // set non-exists memory bit as 1
-        for i in (end_addr - start_addr) / PAGE_SIZE..bits.len() * 64 {
-            bits.set_bit(i, true);
-        }
-
-        Self {
-            bits,
+        let count = (end_addr - start_addr) / PAGE_SIZE;
+        let mut frm = Self {
+            orders: vec![NOT_FREE; count],
+            free_lists: [LIST_END; MAX_ORDER + 1],
+            free: 0,
             paddr: pa!(start_addr),
             paddr_end: pa!(end_addr),
-        }
+        };
+        frm.free_range(0, count);
+        frm
+    }
+
+    /// 获取页帧分布图中的页帧总数
+    #[inline]
+    pub fn get_page_count(&self) -> usize {
+        self.orders.len()
     }
 
     /// 获取页帧分布图中没有使用的页帧数量
     #[inline]
     pub fn get_free_page_count(&self) -> usize {
-        self.bits.iter().fold(0, |mut sum, x| {
-            if *x == 0 {
-                sum + 64
-            } else {
-                for i in 0..64 {
-                    sum += match (*x).get_bit(i) {
-                        true => 0,
-                        false => 1,
-                    };
-                }
-                sum
-            }
-        })
+        self.free
     }
 
-    /// 在 `bitArray` 指定位置获取一个空闲的页
-    ///
-    /// index: usize 指定的位置 self.bits[index]
+    /// 页帧分布图是否包含这个地址
     #[inline]
-    fn alloc_in_pos(&mut self, index: usize) -> Option<PhysAddr> {
-        for bit_index in 0..64 {
-            if !self.bits[index].get_bit(bit_index) {
-                self.bits[index].set_bit(bit_index, true);
-                return Some(pa!(self.paddr.raw() + (index * 64 + bit_index) * PAGE_SIZE));
-            }
+    pub fn contains(&self, paddr: PhysAddr) -> bool {
+        paddr >= self.paddr && paddr < self.paddr_end
+    }
+
+    /// 获取第 index 个页中保存的空闲链表节点
+    #[inline]
+    fn node(&self, index: usize) -> &'static mut FreeNode {
+        let vaddr = (self.paddr.raw() + index * PAGE_SIZE) | VIRT_ADDR_START;
+        unsafe { &mut *(vaddr as *mut FreeNode) }
+    }
+
+    /// 第 index 个页的物理地址, 清空页中残留的空闲链表节点
+    #[inline]
+    fn take_page(&self, index: usize) -> PhysAddr {
+        let paddr = pa!(self.paddr.raw() + index * PAGE_SIZE);
+        paddr.clear_len(size_of::<FreeNode>());
+        paddr
+    }
+
+    /// 把第 index 个页开始的空闲块放到阶数为 order 的空闲链表中
+    fn push(&mut self, index: usize, order: usize) {
+        let head = self.free_lists[order];
+        *self.node(index) = FreeNode {
+            prev: LIST_END,
+            next: head,
+        };
+        if head != LIST_END {
+            self.node(head).prev = index;
         }
-        None
+        self.free_lists[order] = index;
+        self.orders[index] = order as u8;
     }
 
-    /// 申请一个空闲页
+    /// 把第 index 个页开始的空闲块从阶数为 order 的空闲链表中移除
+    fn remove(&mut self, index: usize, order: usize) {
+        let FreeNode { prev, next } = *self.node(index);
+        match prev {
+            LIST_END => self.free_lists[order] = next,
+            _ => self.node(prev).next = next,
+        }
+        if next != LIST_END {
+            self.node(next).prev = prev;
+        }
+        self.orders[index] = NOT_FREE;
+    }
+
+    /// 获取阶数为 order 的块的伙伴, 伙伴不在页帧分布图中时返回 None
     #[inline]
-    pub fn alloc(&mut self) -> Option<PhysAddr> {
-        for i in 0..self.bits.len() {
-            if self.bits[i] != usize::MAX {
-                return self.alloc_in_pos(i);
+    fn buddy(&self, index: usize, order: usize) -> Option<usize> {
+        let start_ppn = self.paddr.raw() / PAGE_SIZE;
+        let buddy = ((start_ppn + index) ^ (1 << order)).checked_sub(start_ppn)?;
+        (buddy < self.orders.len()).then_some(buddy)
+    }
+
+    /// 申请一个阶数为 order 的块, 返回块的第一个页的序号
+    ///
+    /// 从满足要求的最小的空闲块中申请, 多余的部分拆分后放回空闲链表
+    fn alloc_order(&mut self, order: usize) -> Option<usize> {
+        let mut current = (order..=MAX_ORDER).find(|x| self.free_lists[*x] != LIST_END)?;
+        let index = self.free_lists[current];
+        self.remove(index, current);
+        while current > order {
+            current -= 1;
+            self.push(index + (1 << current), current);
+        }
+        self.free -= 1 << order;
+        Some(index)
+    }
+
+    /// 释放一个阶数为 order 的块, 并且和空闲的伙伴合并
+    fn dealloc_order(&mut self, mut index: usize, mut order: usize) {
+        self.free += 1 << order;
+        while order < MAX_ORDER {
+            match self.buddy(index, order) {
+                Some(buddy) if self.orders[buddy] == order as u8 => {
+                    self.remove(buddy, order);
+                    index = index.min(buddy);
+                    order += 1;
+                }
+                _ => break,
             }
         }
-        None
+        self.push(index, order);
     }
 
-    /// 申请多个空闲页, 空闲页是连续的
-    ///
-    /// pages: usize 要申请的页表数量
-    #[allow(unused_assignments)]
-    pub fn alloc_much(&mut self, pages: usize) -> Option<Vec<FrameTracker>> {
-        // TODO: alloc more than 64?;
-        // 优化本函数
+    /// 释放 [start, end) 中的页, 拆分成尽可能大的对齐的块
+    fn free_range(&mut self, mut start: usize, end: usize) {
         let start_ppn = self.paddr.raw() / PAGE_SIZE;
-        let end_ppn = self.paddr_end.raw() / PAGE_SIZE;
-        if pages > end_ppn - start_ppn {
-            return None;
+        while start < end {
+            let order = (start_ppn + start)
+                .trailing_zeros()
+                .min((end - start).ilog2())
+                .min(MAX_ORDER as u32) as usize;
+            self.dealloc_order(start, order);
+            start += 1 << order;
         }
-        for mut i in 0..(end_ppn - start_ppn - pages + 1) {
-            let mut j = i;
-            loop {
-                if j - i >= pages {
-                    let mut ans = Vec::new();
-                    (i..j).for_each(|x| {
-                        self.bits.set_bit(x, true);
-                        ans.push(FrameTracker::new(pa!((start_ppn + x) * PAGE_SIZE)));
-                    });
-                    return Some(ans);
-                }
+    }
 
-                if self.bits.get_bit(j) {
-                    i = j + 1;
-                    break;
-                }
+    /// 申请一个空闲页
+    #[inline]
+    pub fn alloc(&mut self) -> Option<PhysAddr> {
+        self.alloc_order(0).map(|index| self.take_page(index))
+    }
 
-                j += 1;
-            }
+    /// 申请多个空闲页, 空闲页是连续的, 返回第一个页的地址
+    ///
+    /// pages: usize 要申请的页表数量
+    /// 页数是 2 的幂时, 起始的物理页号按页数对齐
+    pub fn alloc_much(&mut self, pages: usize) -> Option<PhysAddr> {
+        let order = pages.next_power_of_two().trailing_zeros() as usize;
+        if pages == 0 || order > MAX_ORDER {
+            return None;
         }
-        None
+        let index = self.alloc_order(order)?;
+        self.free_range(index + pages, index + (1 << order));
+        (index..index + pages).for_each(|x| {
+            self.take_page(x);
+        });
+        Some(pa!(self.paddr.raw() + index * PAGE_SIZE))
     }
 
     /// 释放一个已经使用的页
@@ -159,9 +241,8 @@ impl FrameRegionMap {
     /// ppn: PhysPage 要释放的页的地址
     #[inline]
     pub fn dealloc(&mut self, paddr: PhysAddr) {
-        let ppn = paddr.raw() / PAGE_SIZE;
-        let start_ppn = self.paddr.raw() / PAGE_SIZE;
-        self.bits.set_bit(ppn - start_ppn, false);
+        let index = (paddr.raw() - self.paddr.raw()) / PAGE_SIZE;
+        self.dealloc_order(index, 0);
     }
 }
 
@@ -184,6 +265,12 @@ impl FrameAllocator {
         self.0.push(FrameRegionMap::new(start, end));
     }
 
+    /// 获取页帧分配器中页表的总数
+    #[inline]
+    pub fn get_page_count(&self) -> usize {
+        self.0.iter().fold(0, |sum, x| sum + x.get_page_count())
+    }
+
     /// 获取页帧分配器中空闲页表的数量
     ///
     /// 也就是对所有的页帧分布图中的内存进行和运算
@@ -205,24 +292,15 @@ impl FrameAllocator {
     /// pages: usize 要申请的页表数量
     /// 在多个页表分布图里查找
     #[inline]
-    pub fn alloc_much(&mut self, pages: usize) -> Option<Vec<FrameTracker>> {
-        for frm in &mut self.0 {
-            let frame = frm.alloc_much(pages);
-            if frame.is_some() {
-                return frame;
-            }
-        }
-        None
+    pub fn alloc_much(&mut self, pages: usize) -> Option<PhysAddr> {
+        self.0.iter_mut().find_map(|frm| frm.alloc_much(pages))
     }
 
     /// 释放一个页
     #[inline]
     pub fn dealloc(&mut self, paddr: PhysAddr) {
-        for frm in &mut self.0 {
-            if paddr >= frm.paddr && paddr < frm.paddr_end {
-                frm.dealloc(paddr);
-                break;
-            }
+        if let Some(frm) = self.0.iter_mut().find(|frm| frm.contains(paddr)) {
+            frm.dealloc(paddr);
         }
     }
 }
@@ -236,6 +314,99 @@ impl Default for FrameAllocator {
 /// 一个总的页帧分配器
 pub static FRAME_ALLOCATOR: Mutex<FrameAllocator> = Mutex::new(FrameAllocator::new());
 
+/// 每个 CPU 缓存的最大页数
+const CPU_CACHE_HIGH: usize = 64;
+/// 每次从页帧分配器补充或者归还给页帧分配器的页数
+const CPU_CACHE_BATCH: usize = 32;
+
+/// 每个 CPU 的空闲页缓存
+///
+/// 单个页的申请和释放先经过当前 CPU 的缓存, 减少对页帧分配器的锁竞争
+struct CpuCache {
+    len: usize,
+    pages: [usize; CPU_CACHE_HIGH],
+}
+
+impl CpuCache {
+    const fn new() -> Self {
+        Self {
+            len: 0,
+            pages: [0; CPU_CACHE_HIGH],
+        }
+    }
+
+    /// 把最后 count 个页还给页帧分配器
+    fn flush(&mut self, count: usize) -> usize {
+        let count = count.min(self.len);
+        let mut allocator = FRAME_ALLOCATOR.lock();
+        for _ in 0..count {
+            self.len -= 1;
+            allocator.dealloc(pa!(self.pages[self.len]));
+        }
+        count
+    }
+}
+
+static CPU_CACHES: LazyInit<Vec<Mutex<CpuCache>>> = LazyInit::new();
+
+/// 获取当前 CPU 的空闲页缓存
+#[inline]
+fn cpu_cache() -> Option<&'static Mutex<CpuCache>> {
+    CPU_CACHES.try_get()?.get(hart_id())
+}
+
+/// 从当前 CPU 的缓存中申请一个页, 缓存为空时从页帧分配器补充
+fn cache_alloc() -> Option<PhysAddr> {
+    let Some(cache) = cpu_cache() else {
+        return FRAME_ALLOCATOR.lock().alloc();
+    };
+    let mut cache = cache.lock();
+    if cache.len == 0 {
+        let mut allocator = FRAME_ALLOCATOR.lock();
+        while cache.len < CPU_CACHE_BATCH {
+            let Some(paddr) = allocator.alloc() else {
+                break;
+            };
+            let len = cache.len;
+            cache.pages[len] = paddr.raw();
+            cache.len += 1;
+        }
+    }
+    if cache.len == 0 {
+        return None;
+    }
+    cache.len -= 1;
+    Some(pa!(cache.pages[cache.len]))
+}
+
+/// 把一个页放回当前 CPU 的缓存中, 缓存满时归还一批给页帧分配器
+fn cache_dealloc(paddr: PhysAddr) {
+    let Some(cache) = cpu_cache() else {
+        FRAME_ALLOCATOR.lock().dealloc(paddr);
+        return;
+    };
+    let mut cache = cache.lock();
+    if cache.len == CPU_CACHE_HIGH {
+        cache.flush(CPU_CACHE_BATCH);
+    }
+    let len = cache.len;
+    cache.pages[len] = paddr.raw();
+    cache.len += 1;
+}
+
+/// 把所有 CPU 缓存的页还给页帧分配器, 返回归还的页数
+///
+/// 正在被使用的缓存会被跳过
+fn drain_cpu_caches() -> usize {
+    CPU_CACHES.try_get().map_or(0, |caches| {
+        caches
+            .iter()
+            .filter_map(|cache| cache.try_lock())
+            .map(|mut cache| cache.flush(CPU_CACHE_HIGH))
+            .sum()
+    })
+}
+
 pub fn add_frame_map(mut mm_start: usize, mut mm_end: usize) {
     mm_start = alignup(mm_start, PAGE_SIZE);
     mm_end = aligndown(mm_end, PAGE_SIZE);
@@ -247,6 +418,8 @@ pub fn add_frame_map(mut mm_start: usize, mut mm_end: usize) {
 }
 
 /// 页帧分配器初始化
+///
+/// 在添加所有的内存区域之后调用, 之后单个页的申请和释放会经过每个 CPU 的缓存
 pub fn init() {
     info!("initialize frame allocator");
 
@@ -255,29 +428,131 @@ pub fn init() {
         !FRAME_ALLOCATOR.lock().0.is_empty(),
         "can't find frame to alloc"
     );
+    CPU_CACHES.init_by(
+        (0..get_cpu_num())
+            .map(|_| Mutex::new(CpuCache::new()))
+            .collect(),
+    );
+}
+
+/// 页帧不足时回收内存的函数, 参数是需要的页数, 返回回收的页数
+static RECLAIMER: LazyInit<fn(usize) -> usize> = LazyInit::new();
+/// 正在回收内存的 CPU 编号加一, 0 表示没有在回收
+///
+/// 回收过程中申请页帧失败不会再次回收, 其他 CPU 等待回收结束后重试
+static RECLAIMING: AtomicUsize = AtomicUsize::new(0);
+/// 申请页帧失败后最多回收并重试的次数
+const RECLAIM_RETRIES: usize = 4;
+
+/// 交换区的总页数, 由内核在 swapon 和 swapoff 时维护
+pub static SWAP_TOTAL_PAGES: AtomicUsize = AtomicUsize::new(0);
+/// 交换区中空闲的页数, 由内核在换入换出时维护
+pub static SWAP_FREE_PAGES: AtomicUsize = AtomicUsize::new(0);
+
+/// 设置页帧不足时回收内存的函数
+pub fn set_reclaimer(reclaimer: fn(usize) -> usize) {
+    RECLAIMER.init_by(reclaimer);
+}
+
+/// 回收至少 pages 个页帧, 返回是否可以重试申请
+///
+/// 其他 CPU 正在回收时等待它结束, 之后重试申请
+fn reclaim(pages: usize) -> bool {
+    let Some(reclaimer) = RECLAIMER.try_get() else {
+        return false;
+    };
+    let hart = hart_id() + 1;
+    match RECLAIMING.compare_exchange(0, hart, Ordering::AcqRel, Ordering::Acquire) {
+        Ok(_) => {}
+        Err(curr) if curr == hart => return false,
+        Err(_) => {
+            while RECLAIMING.load(Ordering::Acquire) != 0 {
+                spin_loop();
+            }
+            return true;
+        }
+    }
+    let reclaimed = reclaimer(pages);
+    RECLAIMING.store(0, Ordering::Release);
+    reclaimed > 0
+}
+
+/// 利用 f 申请 pages 个页帧, 失败时先收回每个 CPU 缓存的页, 再回收内存后重试
+fn alloc_or_reclaim<T>(pages: usize, f: impl Fn() -> Option<T>) -> Option<T> {
+    if let Some(frames) = f() {
+        return Some(frames);
+    }
+    if drain_cpu_caches() > 0 {
+        if let Some(frames) = f() {
+            return Some(frames);
+        }
+    }
+    for _ in 0..RECLAIM_RETRIES {
+        if !reclaim(pages) {
+            return None;
+        }
+        if let Some(frames) = f() {
+            return Some(frames);
+        }
+    }
+    None
 }
 
 /// 申请一个持久化存在的页表，需要手动释放
 pub unsafe fn frame_alloc_persist() -> Option<PhysAddr> {
-    FRAME_ALLOCATOR.lock().alloc()
+    alloc_or_reclaim(1, cache_alloc)
 }
 
-/// 手动释放一个页表
+/// 手动释放一个页表, 页表需要在释放之前清空
 pub unsafe fn frame_unalloc(paddr: PhysAddr) {
-    FRAME_ALLOCATOR.lock().dealloc(paddr)
+    cache_dealloc(paddr)
 }
 
 /// 申请一个空闲页表
 pub fn frame_alloc() -> Option<FrameTracker> {
-    FRAME_ALLOCATOR.lock().alloc().map(FrameTracker)
+    alloc_or_reclaim(1, cache_alloc).map(FrameTracker)
 }
 
 /// 申请多个空闲连续页表
+///
+/// 页数是 2 的幂时, 起始的物理地址按页数对齐, 最多申请 2^MAX_ORDER 个页
 pub fn frame_alloc_much(pages: usize) -> Option<Vec<FrameTracker>> {
-    FRAME_ALLOCATOR.lock().alloc_much(pages)
+    let paddr = alloc_or_reclaim(pages, || FRAME_ALLOCATOR.lock().alloc_much(pages))?;
+    Some(frame_trackers(paddr, pages))
 }
 
-/// 获取空闲页表数量
+/// 申请多个空闲连续页表, 失败时不回收内存
+///
+/// 用于失败后可以退回到申请单个页的情况, 比如透明大页
+pub fn try_frame_alloc_much(pages: usize) -> Option<Vec<FrameTracker>> {
+    frame_alloc_much_persist(pages).map(|paddr| frame_trackers(paddr, pages))
+}
+
+/// 为从 paddr 开始的 pages 个连续页创建页帧
+fn frame_trackers(paddr: PhysAddr, pages: usize) -> Vec<FrameTracker> {
+    (0..pages)
+        .map(|i| FrameTracker::new(pa!(paddr.raw() + i * PAGE_SIZE)))
+        .collect()
+}
+
+/// 申请多个持久化存在的连续页表, 不会回收内存, 供内核堆扩充使用
+pub(crate) fn frame_alloc_much_persist(pages: usize) -> Option<PhysAddr> {
+    let alloc = || FRAME_ALLOCATOR.lock().alloc_much(pages);
+    alloc().or_else(|| {
+        drain_cpu_caches();
+        alloc()
+    })
+}
+
+/// 获取空闲页表数量, 包括每个 CPU 缓存的页
 pub fn get_free_pages() -> usize {
-    FRAME_ALLOCATOR.lock().get_free_page_count()
+    let cached = CPU_CACHES.try_get().map_or(0, |caches| {
+        caches.iter().map(|cache| cache.lock().len).sum::<usize>()
+    });
+    FRAME_ALLOCATOR.lock().get_free_page_count() + cached
+}
+
+/// 获取页表总数
+pub fn get_total_pages() -> usize {
+    FRAME_ALLOCATOR.lock().get_page_count()
 }
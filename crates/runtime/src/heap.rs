@@ -1,7 +1,15 @@
 extern crate alloc;
 
-use buddy_system_allocator::LockedHeap;
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use buddy_system_allocator::{Heap, LockedHeapWithRescue};
 use log::info;
+use polyhal::{consts::VIRT_ADDR_START, pagetable::PAGE_SIZE};
+
+use crate::frame::{frame_alloc_much_persist, MAX_ORDER};
 
 include!(concat!(env!("OUT_DIR"), "/consts.rs"));
 
@@ -9,18 +17,122 @@ include!(concat!(env!("OUT_DIR"), "/consts.rs"));
 // const HEAP_SIZE: usize = 0x0180_0000;
 // pub const HEAP_SIZE: usize = 0x0180_0000;
 
+/// 堆分配器的最大阶数
+const HEAP_ORDER: usize = 30;
+
+/// 堆空间不足时每次至少扩充的页数
+const HEAP_GROW_PAGES: usize = 256;
+
 // 堆空间
 #[link_section = ".bss.heap"]
 static mut HEAP: [u8; HEAP_SIZE] = [0; HEAP_SIZE];
 
 /// 堆内存分配器
+///
+/// 初始的堆空间是 HEAP, 空间不足时从页帧分配器申请连续的页帧扩充
 #[global_allocator]
-static HEAP_ALLOCATOR: LockedHeap<30> = LockedHeap::empty();
+static HEAP_ALLOCATOR: KernelHeap = KernelHeap {
+    heap: LockedHeapWithRescue::new(grow),
+    grown_pages: AtomicUsize::new(0),
+    objects: [const { AtomicUsize::new(0) }; HEAP_ORDER],
+};
+
+/// 内核堆, 记录每种大小的对象数量
+struct KernelHeap {
+    heap: LockedHeapWithRescue<HEAP_ORDER>,
+    /// 从页帧分配器申请的页数, 这些页不会归还
+    grown_pages: AtomicUsize,
+    /// 每种大小的对象数量, 第 i 项是大小为 2^i 的对象
+    objects: [AtomicUsize; HEAP_ORDER],
+}
+
+/// 对象在堆分配器中占用的块的阶数
+#[inline]
+fn size_class(layout: &Layout) -> usize {
+    layout
+        .size()
+        .max(layout.align())
+        .max(size_of::<usize>())
+        .next_power_of_two()
+        .trailing_zeros() as usize
+}
+
+unsafe impl GlobalAlloc for KernelHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.heap.alloc(layout);
+        if !ptr.is_null() {
+            self.objects[size_class(&layout)].fetch_add(1, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.objects[size_class(&layout)].fetch_sub(1, Ordering::Relaxed);
+        self.heap.dealloc(ptr, layout)
+    }
+}
+
+/// 堆空间不足时扩充堆
+///
+/// 在持有堆的锁时调用, 所以只能从页帧分配器直接申请, 不能回收内存.
+/// 连续的页帧不足时减少申请的页数, 直到不能放下 layout
+fn grow(heap: &mut Heap<HEAP_ORDER>, layout: &Layout) {
+    let size = 1 << size_class(layout);
+    let min_pages = size.div_ceil(PAGE_SIZE);
+    let mut pages = min_pages.max(HEAP_GROW_PAGES).min(1 << MAX_ORDER);
+    while pages >= min_pages {
+        if let Some(paddr) = frame_alloc_much_persist(pages) {
+            let start = paddr.raw() | VIRT_ADDR_START;
+            unsafe {
+                heap.add_to_heap(start, start + pages * PAGE_SIZE);
+            }
+            HEAP_ALLOCATOR
+                .grown_pages
+                .fetch_add(pages, Ordering::Relaxed);
+            return;
+        }
+        pages /= 2;
+    }
+}
+
+/// 内核堆的使用情况
+pub struct HeapStats {
+    /// 堆的总大小, 包括扩充的部分
+    pub total: usize,
+    /// 申请的内存大小
+    pub user: usize,
+    /// 实际占用的内存大小, 包括对齐到 2 的幂的部分
+    pub actual: usize,
+    /// 从页帧分配器扩充的页数
+    pub grown_pages: usize,
+}
+
+/// 获取内核堆的使用情况
+pub fn heap_stats() -> HeapStats {
+    let heap = HEAP_ALLOCATOR.heap.lock();
+    HeapStats {
+        total: heap.stats_total_bytes(),
+        user: heap.stats_alloc_user(),
+        actual: heap.stats_alloc_actual(),
+        grown_pages: HEAP_ALLOCATOR.grown_pages.load(Ordering::Relaxed),
+    }
+}
+
+/// 获取每种大小的对象数量, 返回 (对象大小, 对象数量), 跳过数量为 0 的大小
+pub fn heap_objects() -> impl Iterator<Item = (usize, usize)> {
+    HEAP_ALLOCATOR
+        .objects
+        .iter()
+        .enumerate()
+        .map(|(order, count)| (1 << order, count.load(Ordering::Relaxed)))
+        .filter(|(_, count)| *count > 0)
+}
 
 /// 初始化堆内存分配器
 pub fn init() {
     unsafe {
         HEAP_ALLOCATOR
+            .heap
             .lock()
             .init(&raw mut HEAP as usize, HEAP_SIZE);
 
@@ -24,6 +24,11 @@ impl Sdx {
             mount_paths: Mutex::new(Vec::new()),
         }
     }
+
+    /// Get the id of the block device in the device set.
+    pub fn device_id(&self) -> usize {
+        self.device_id
+    }
 }
 
 impl INodeInterface for Sdx {
@@ -45,7 +50,7 @@ impl INodeInterface for Sdx {
     fn stat(&self, stat: &mut Stat) -> vfscore::VfsResult<()> {
         stat.dev = 0;
         stat.ino = 1; // TODO: convert path to number(ino)
-        stat.mode = StatMode::CHAR; // TODO: add access mode
+        stat.mode = StatMode::BLOCK; // TODO: add access mode
         stat.nlink = 1;
         stat.uid = 1000;
         stat.gid = 1000;
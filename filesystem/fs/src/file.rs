@@ -68,6 +68,16 @@ impl File {
         })
     }
 
+    /// 创建不属于任何文件系统的文件，path 只是展示给用户的名字
+    pub fn new_anonymous(inner: Arc<dyn INodeInterface>, path: &str) -> Arc<Self> {
+        Arc::new(Self {
+            inner,
+            offset: Mutex::new(0),
+            path_buf: PathBuf::from(path),
+            flags: Mutex::new(OpenFlags::RDWR),
+        })
+    }
+
     /// 打开真正的文件，如果 path 是 link 文件，会打开真正的文件
     pub fn open_link<T: Into<PathBuf>>(path: T, flags: OpenFlags) -> Result<File, Errno> {
         let mut file = Self::open(path, flags)?;
@@ -22,6 +22,7 @@ use core::{
     task::{Context, Poll},
 };
 use dentry::mount_fs;
+pub use devfs::Sdx;
 use devfs::{DevDir, DevFS};
 use devices::get_blk_devices;
 use procfs::ProcFS;
@@ -30,8 +31,24 @@ use syscalls::Errno;
 use vfscore::VfsResult;
 pub use vfscore::{FileType, INodeInterface, SeekFrom};
 
+/// The names of the block devices in the devfs, `sda` is the first block device.
+const SDX_NAMES: [&str; 8] = ["sda", "sdb", "sdc", "sdd", "sde", "sdf", "sdg", "sdh"];
+
 pub fn build_devfs() -> Arc<DevFS> {
-    let dev_dir = DevDir::new();
+    let mut dev_dir = DevDir::new();
+    // Block devices can't be mounted yet, they are used by swapon.
+    (0..get_blk_devices().len())
+        .zip(SDX_NAMES)
+        .for_each(|(id, name)| {
+            dev_dir.add(
+                name,
+                Arc::new(Sdx::new(
+                    id,
+                    |_, _| Err(Errno::EINVAL),
+                    |_, _| Err(Errno::EINVAL),
+                )),
+            )
+        });
 
     DevFS::new_with_dir(dev_dir)
 }
@@ -58,25 +75,39 @@ pub fn init() {
     // filesystems.push((RamFs::new(), "/bin"));
 }
 
+/// Poll a blocking operation, park the task if it would block.
+///
+/// The waker is registered before retrying, so a wake-up between the two
+/// attempts is not lost.
+fn poll_blocking(
+    file: &Arc<dyn INodeInterface>,
+    cx: &mut Context<'_>,
+    mut f: impl FnMut() -> VfsResult<usize>,
+) -> Poll<VfsResult<usize>> {
+    match f() {
+        Err(Errno::EWOULDBLOCK) => {
+            if file.register_waker(cx.waker()).is_err() {
+                executor::wake_on_next_tick(cx.waker());
+            }
+            match f() {
+                Err(Errno::EWOULDBLOCK) => Poll::Pending,
+                res => Poll::Ready(res),
+            }
+        }
+        res => Poll::Ready(res),
+    }
+}
+
 pub struct WaitBlockingRead<'a>(pub Arc<dyn INodeInterface>, pub &'a mut [u8], pub usize);
 
 impl Future for WaitBlockingRead<'_> {
     type Output = VfsResult<usize>;
 
-    fn poll(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Self::Output> {
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let offset = self.2;
         let file = self.0.clone();
         let buffer = &mut self.1;
-        match file.readat(offset, buffer) {
-            Ok(rsize) => Poll::Ready(Ok(rsize)),
-            Err(err) => {
-                if let Errno::EWOULDBLOCK = err {
-                    Poll::Pending
-                } else {
-                    Poll::Ready(Err(err))
-                }
-            }
-        }
+        poll_blocking(&file, cx, || file.readat(offset, buffer))
     }
 }
 
@@ -85,20 +116,10 @@ pub struct WaitBlockingWrite<'a>(pub Arc<dyn INodeInterface>, pub &'a [u8], pub
 impl Future for WaitBlockingWrite<'_> {
     type Output = VfsResult<usize>;
 
-    fn poll(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Self::Output> {
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let offset = self.2;
         let file = self.0.clone();
         let buffer = &self.1;
-
-        match file.writeat(offset, buffer) {
-            Ok(wsize) => Poll::Ready(Ok(wsize)),
-            Err(err) => {
-                if let Errno::EWOULDBLOCK = err {
-                    Poll::Pending
-                } else {
-                    Poll::Ready(Err(err))
-                }
-            }
-        }
+        poll_blocking(&file, cx, || file.writeat(offset, buffer))
     }
 }
@@ -1,9 +1,10 @@
-use core::cmp;
+use core::{cmp, task::Waker};
 
 use alloc::{
     collections::VecDeque,
     sync::{Arc, Weak},
 };
+use executor::WaitQueue;
 use libc_types::{
     poll::PollEvent,
     types::{Stat, StatMode},
@@ -12,24 +13,35 @@ use sync::Mutex;
 use syscalls::Errno;
 use vfscore::{INodeInterface, VfsResult};
 
-pub struct PipeSender(Arc<Mutex<VecDeque<u8>>>);
+/// The buffer shared by the two ends of a pipe.
+struct PipeBuffer {
+    queue: Mutex<VecDeque<u8>>,
+    /// Readers waiting for data or the sender closing.
+    readers: WaitQueue,
+    /// Writers waiting for free space.
+    writers: WaitQueue,
+}
+
+pub struct PipeSender(Arc<PipeBuffer>);
 
 impl INodeInterface for PipeSender {
     fn writeat(&self, _offset: usize, buffer: &[u8]) -> VfsResult<usize> {
         log::warn!("write pipe:");
-        let mut queue = self.0.lock();
+        let mut queue = self.0.queue.lock();
         if queue.len() > 0x50000 {
             Err(Errno::EWOULDBLOCK)
         } else {
             let wlen = buffer.len();
             queue.extend(buffer.iter());
+            drop(queue);
+            self.0.readers.wake_all();
             Ok(wlen)
         }
     }
 
     fn poll(&self, events: PollEvent) -> VfsResult<PollEvent> {
         let mut res = PollEvent::NONE;
-        if events.contains(PollEvent::OUT) && self.0.lock().len() <= 0x50000 {
+        if events.contains(PollEvent::OUT) && self.0.queue.lock().len() <= 0x50000 {
             res |= PollEvent::OUT;
         }
         Ok(res)
@@ -39,40 +51,55 @@ impl INodeInterface for PipeSender {
         stat.mode = StatMode::FIFO;
         Ok(())
     }
+
+    fn register_waker(&self, waker: &Waker) -> VfsResult<()> {
+        self.0.writers.register(waker);
+        Ok(())
+    }
+}
+
+impl Drop for PipeSender {
+    fn drop(&mut self) {
+        // Readers will get EOF.
+        self.0.readers.wake_all();
+    }
 }
 
 // pipe reader, just can read.
 pub struct PipeReceiver {
-    queue: Arc<Mutex<VecDeque<u8>>>,
+    buffer: Arc<PipeBuffer>,
     sender: Weak<PipeSender>,
 }
 
 impl INodeInterface for PipeReceiver {
     fn readat(&self, _offset: usize, buffer: &mut [u8]) -> VfsResult<usize> {
-        let mut queue = self.queue.lock();
+        let mut queue = self.buffer.queue.lock();
         let rlen = cmp::min(queue.len(), buffer.len());
         queue
             .drain(..rlen)
             .zip(buffer.iter_mut())
             .for_each(|(src, dst)| *dst = src);
+        drop(queue);
         if rlen == 0 && Weak::strong_count(&self.sender) > 0 {
             Err(Errno::EWOULDBLOCK)
         } else {
+            self.buffer.writers.wake_all();
             Ok(rlen)
         }
     }
 
     fn poll(&self, events: PollEvent) -> VfsResult<PollEvent> {
         let mut res = PollEvent::NONE;
+        let queue = self.buffer.queue.lock();
         if events.contains(PollEvent::IN) {
-            if !self.queue.lock().is_empty() {
+            if !queue.is_empty() {
                 res |= PollEvent::IN;
             } else if Weak::strong_count(&self.sender) == 0 {
                 res |= PollEvent::ERR;
             }
         }
         if events.contains(PollEvent::ERR)
-            && self.queue.lock().is_empty()
+            && queue.is_empty()
             && Weak::strong_count(&self.sender) == 0
         {
             res |= PollEvent::ERR;
@@ -84,14 +111,23 @@ impl INodeInterface for PipeReceiver {
         stat.mode = StatMode::FIFO;
         Ok(())
     }
+
+    fn register_waker(&self, waker: &Waker) -> VfsResult<()> {
+        self.buffer.readers.register(waker);
+        Ok(())
+    }
 }
 
 pub fn create_pipe() -> (Arc<PipeReceiver>, Arc<PipeSender>) {
-    let queue = Arc::new(Mutex::new(VecDeque::new()));
-    let sender = Arc::new(PipeSender(queue.clone()));
+    let buffer = Arc::new(PipeBuffer {
+        queue: Mutex::new(VecDeque::new()),
+        readers: WaitQueue::new(),
+        writers: WaitQueue::new(),
+    });
+    let sender = Arc::new(PipeSender(buffer.clone()));
     (
         Arc::new(PipeReceiver {
-            queue: queue.clone(),
+            buffer,
             sender: Arc::downgrade(&sender),
         }),
         sender,
@@ -5,15 +5,33 @@ extern crate alloc;
 mod interrupts;
 mod meminfo;
 mod mounts;
+mod slabinfo;
 
 use alloc::{collections::BTreeMap, string::ToString, sync::Arc, vec::Vec};
 use interrupts::Interrupts;
 use libc_types::types::{Stat, StatMode};
 use meminfo::MemInfo;
 use mounts::Mounts;
+use slabinfo::SlabInfo;
+use sync::LazyInit;
 use syscalls::Errno;
 use vfscore::{DirEntry, FileSystem, FileType, INodeInterface, VfsResult};
 
+/// The hooks of the kernel to show the processes in the procfs.
+pub struct ProcessHooks {
+    /// Get the ids of all the processes.
+    pub pids: fn() -> Vec<usize>,
+    /// Get the directory of a process by its name, a pid or `self`.
+    pub lookup: fn(&str) -> Option<Arc<dyn INodeInterface>>,
+}
+
+static PROCESS_HOOKS: LazyInit<ProcessHooks> = LazyInit::new();
+
+/// Set the hooks to show the processes, the kernel calls it once at boot.
+pub fn set_process_hooks(hooks: ProcessHooks) {
+    PROCESS_HOOKS.init_by(hooks);
+}
+
 pub struct ProcFS {
     root: Arc<ProcDir>,
 }
@@ -48,6 +66,7 @@ impl ProcDir {
         map.insert("mounts", Arc::new(Mounts::new()));
         map.insert("meminfo", Arc::new(MemInfo::new()));
         map.insert("interrupts", Arc::new(Interrupts::new()));
+        map.insert("slabinfo", Arc::new(SlabInfo::new()));
         Arc::new(ProcDir { map })
     }
 }
@@ -58,10 +77,19 @@ pub struct DevDirContainer {
 
 impl INodeInterface for DevDirContainer {
     fn lookup(&self, name: &str) -> VfsResult<Arc<dyn INodeInterface>> {
-        self.inner.map.get(name).cloned().ok_or(Errno::ENOENT)
+        self.inner
+            .map
+            .get(name)
+            .cloned()
+            .or_else(|| (PROCESS_HOOKS.try_get()?.lookup)(name))
+            .ok_or(Errno::ENOENT)
     }
 
     fn read_dir(&self) -> VfsResult<Vec<DirEntry>> {
+        let pids = PROCESS_HOOKS
+            .try_get()
+            .map(|x| (x.pids)())
+            .unwrap_or_default();
         Ok(self
             .inner
             .map
@@ -71,6 +99,11 @@ impl INodeInterface for DevDirContainer {
                 len: 0,
                 file_type: FileType::Device,
             })
+            .chain(pids.iter().map(|pid| DirEntry {
+                filename: pid.to_string(),
+                len: 0,
+                file_type: FileType::Directory,
+            }))
             .collect())
     }
 
@@ -1,6 +1,16 @@
+use core::{cmp, fmt::Write, sync::atomic::Ordering};
+
+use alloc::string::String;
 use libc_types::types::{Stat, StatMode};
+use runtime::{
+    frame::{get_free_pages, get_total_pages, SWAP_FREE_PAGES, SWAP_TOTAL_PAGES},
+    heap::heap_stats,
+};
 use vfscore::{INodeInterface, VfsResult};
 
+/// The size of a page in KiB.
+const PAGE_KB: usize = 4;
+
 pub struct MemInfo {}
 
 impl MemInfo {
@@ -10,8 +20,33 @@ impl MemInfo {
 }
 
 impl INodeInterface for MemInfo {
-    fn readat(&self, _offset: usize, _buffer: &mut [u8]) -> VfsResult<usize> {
-        Ok(0)
+    fn readat(&self, offset: usize, buffer: &mut [u8]) -> VfsResult<usize> {
+        let free = get_free_pages();
+        let swap_total = SWAP_TOTAL_PAGES.load(Ordering::Acquire);
+        let swap_free = SWAP_FREE_PAGES.load(Ordering::Acquire);
+        // The kernel heap never shrinks, all of it is unreclaimable.
+        let slab = heap_stats().actual / 1024;
+        let mut str = String::new();
+        [
+            ("MemTotal:", get_total_pages() * PAGE_KB),
+            ("MemFree:", free * PAGE_KB),
+            ("MemAvailable:", free * PAGE_KB),
+            ("SwapTotal:", swap_total * PAGE_KB),
+            ("SwapFree:", swap_free * PAGE_KB),
+            ("Slab:", slab),
+            ("SUnreclaim:", slab),
+        ]
+        .iter()
+        .for_each(|(name, kb)| {
+            writeln!(str, "{:<16}{:>8} kB", name, kb).unwrap();
+        });
+        let bytes = str.as_bytes();
+        if offset >= bytes.len() {
+            return Ok(0);
+        }
+        let rsize = cmp::min(bytes.len() - offset, buffer.len());
+        buffer[..rsize].copy_from_slice(&bytes[offset..offset + rsize]);
+        Ok(rsize)
     }
 
     fn stat(&self, stat: &mut Stat) -> vfscore::VfsResult<()> {
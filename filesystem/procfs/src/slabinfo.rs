@@ -0,0 +1,58 @@
+use core::{cmp, fmt::Write};
+
+use alloc::string::String;
+use libc_types::types::{Stat, StatMode};
+use runtime::heap::{heap_objects, heap_stats};
+use vfscore::{INodeInterface, VfsResult};
+
+/// The objects of the kernel heap grouped by the size class, in the format of slabinfo.
+pub struct SlabInfo {}
+
+impl SlabInfo {
+    pub const fn new() -> Self {
+        Self {}
+    }
+}
+
+impl INodeInterface for SlabInfo {
+    fn readat(&self, offset: usize, buffer: &mut [u8]) -> VfsResult<usize> {
+        let stats = heap_stats();
+        let mut str = String::new();
+        writeln!(str, "slabinfo - version: 2.1").unwrap();
+        writeln!(
+            str,
+            "# heap total: {} kB, used: {} kB, allocated: {} kB, grown: {} pages",
+            stats.total / 1024,
+            stats.actual / 1024,
+            stats.user / 1024,
+            stats.grown_pages
+        )
+        .unwrap();
+        writeln!(str, "# name            <active_objs> <num_objs> <objsize>").unwrap();
+        heap_objects().for_each(|(size, count)| {
+            let name = alloc::format!("kmalloc-{}", size);
+            writeln!(str, "{:<17} {:>13} {:>10} {:>9}", name, count, count, size).unwrap();
+        });
+        let bytes = str.as_bytes();
+        if offset >= bytes.len() {
+            return Ok(0);
+        }
+        let rsize = cmp::min(bytes.len() - offset, buffer.len());
+        buffer[..rsize].copy_from_slice(&bytes[offset..offset + rsize]);
+        Ok(rsize)
+    }
+
+    fn stat(&self, stat: &mut Stat) -> vfscore::VfsResult<()> {
+        stat.dev = 0;
+        stat.ino = 1; // TODO: convert path to number(ino)
+        stat.mode = StatMode::CHAR; // TODO: add access mode
+        stat.nlink = 1;
+        stat.uid = 1000;
+        stat.gid = 1000;
+        stat.size = 0;
+        stat.blksize = 512;
+        stat.blocks = 0;
+        stat.rdev = 0; // TODO: add device id
+        Ok(())
+    }
+}
@@ -6,6 +6,7 @@ extern crate bitflags;
 use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
+use core::task::Waker;
 use downcast_rs::{impl_downcast, DowncastSync};
 use libc_types::{
     poll::PollEvent,
@@ -150,6 +151,12 @@ pub trait INodeInterface: DowncastSync + Send + Sync {
     fn poll(&self, _events: PollEvent) -> VfsResult<PollEvent> {
         Err(Errno::EPERM)
     }
+
+    /// Register a waker which will be woken when a blocked read or write
+    /// can make progress. Returns `Err` if the inode can't notify waiters.
+    fn register_waker(&self, _waker: &Waker) -> VfsResult<()> {
+        Err(Errno::EPERM)
+    }
 }
 
 impl_downcast!(sync INodeInterface);
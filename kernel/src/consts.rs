@@ -6,8 +6,35 @@ pub const USER_WORK_DIR: PathBuf = PathBuf::empty();
 /// 用户态动态链接用户程序的偏移
 pub const USER_DYN_ADDR: usize = 0x20000000;
 
+/// 用户态动态链接器（PT_INTERP）的加载地址
+pub const USER_INTERP_ADDR: usize = 0x1_0000_0000;
+
+/// 用户态 mmap 区域的起始地址，sv39 中高 25 位需要与第 38 位相同
+pub const USER_MMAP_ADDR: usize = 0x2_0000_0000;
+
+/// 地址空间布局随机化时 PIE 程序和动态链接器加载地址的最大偏移
+pub const USER_LOAD_RANDOM_RANGE: usize = 0x1000_0000;
+
+/// 地址空间布局随机化时 mmap 起始地址的最大偏移
+pub const USER_MMAP_RANDOM_RANGE: usize = 0x1_0000_0000;
+
+/// 地址空间布局随机化时堆起始地址的最大偏移
+pub const USER_BRK_RANDOM_RANGE: usize = 0x200_0000;
+
+/// 地址空间布局随机化时栈顶的最大偏移
+pub const USER_STACK_RANDOM_RANGE: usize = 0x100_0000;
+
 /// 用户态栈顶
 pub const USER_STACK_TOP: usize = 0x8000_0000;
 
+/// 用户栈默认的最大大小（RLIMIT_STACK）
+pub const USER_STACK_LIMIT: usize = 0x80_0000;
+
+/// 向下增长的栈与下方映射之间至少保留的间隔，与 linux 的 stack_guard_gap 相同
+pub const STACK_GUARD_GAP: usize = 0x10_0000;
+
 /// 用户栈初始大小
 pub const USER_STACK_INIT_SIZE: usize = 0x20000;
+
+/// 大页的大小，按大页对齐的整块匿名内存用一个 2MiB 的映射
+pub const HUGE_PAGE_SIZE: usize = 0x20_0000;
@@ -30,17 +30,15 @@ mod utils;
 
 use crate::tasks::current_user_task;
 use crate::user::task_ilegal;
-use core::hint::spin_loop;
-use core::time::Duration;
 use devices::{self, get_int_device, PAGE_SIZE, VIRT_ADDR_START};
-use executor::current_task;
+use executor::{current_task, timer::TICK_INTERVAL};
 use fs::file::File;
 use libc_types::fcntl::OpenFlags;
 use polyhal::common::PageAlloc;
 use polyhal::irq::IRQ;
 use polyhal::mem::{get_fdt, get_mem_areas};
 use polyhal::timer::{current_time, set_next_timer};
-use polyhal::{va, PhysAddr};
+use polyhal::{va, MappingFlags, PhysAddr};
 use polyhal_trap::trap::TrapType;
 use polyhal_trap::trapframe::{TrapFrame, TrapFrameArgs};
 use runtime::frame::{frame_alloc_persist, frame_unalloc};
@@ -58,8 +56,8 @@ impl PageAlloc for PageAllocImpl {
     #[inline]
     fn dealloc(&self, paddr: PhysAddr) {
         unsafe {
-            frame_unalloc(paddr);
             paddr.clear_len(PAGE_SIZE);
+            frame_unalloc(paddr);
         }
     }
 }
@@ -71,6 +69,11 @@ fn kernel_interrupt(cx_ref: &mut TrapFrame, trap_type: TrapType) {
         TrapType::StorePageFault(addr)
         | TrapType::InstructionPageFault(addr)
         | TrapType::LoadPageFault(addr) => {
+            let access = match trap_type {
+                TrapType::StorePageFault(_) => MappingFlags::W,
+                TrapType::InstructionPageFault(_) => MappingFlags::X,
+                _ => MappingFlags::R,
+            };
             if addr > VIRT_ADDR_START {
                 panic!(
                     "kernel page error: {:#x} sepc: {:#x}",
@@ -79,21 +82,18 @@ fn kernel_interrupt(cx_ref: &mut TrapFrame, trap_type: TrapType) {
                 );
             }
             // judge whether it is trigger by a user_task handler.
+            // The kernel never accesses user memory while holding the pcb lock. If it
+            // does by mistake, the fault gives up the lock after a while and the access
+            // fails with EFAULT.
             if let Some(task) = current_task().downcast_arc::<UserTask>().ok() {
-                let cx_ref = task.force_cx_ref();
-                if task.pcb.is_locked() {
-                    // task.pcb.force_unlock();
-                    unsafe {
-                        task.pcb.force_unlock();
-                    }
-                }
-                user_cow_int(task, cx_ref, va!(addr));
+                user_cow_int(task, cx_ref, va!(addr), access);
             } else {
                 panic!("page fault: {:#x?}", trap_type);
             }
         }
         TrapType::Timer => {
-            set_next_timer(current_time() + Duration::from_millis(10));
+            tasks::tlb::handle_shootdown();
+            executor::timer_tick();
         }
         TrapType::IllegalInstruction(addr) => {
             if addr > VIRT_ADDR_START {
@@ -152,6 +152,7 @@ fn main(hart_id: usize) {
         info!("memory area: {:#x} - {:#x}", start, start + size);
         runtime::frame::add_frame_map(start, start + size);
     });
+    runtime::frame::init();
 
     println!("run kernel @ hart {}", hart_id);
 
@@ -165,7 +166,8 @@ fn main(hart_id: usize) {
     );
 
     // Boot all application core.
-    // polyhal::multicore::MultiCore::boot_all();
+    // They wait in the executor until the tasks are initialized.
+    polyhal::multicore::MultiCore::boot_all();
 
     devices::prepare_drivers();
 
@@ -204,11 +206,9 @@ fn main(hart_id: usize) {
 
 fn secondary(hart_id: usize) {
     println!("run kernel @ hart {}", hart_id);
-    // loop { arch::wfi() }
-    // tasks::run_tasks();
-    loop {
-        spin_loop();
-    }
+    IRQ::int_enable();
+    set_next_timer(current_time() + TICK_INTERVAL);
+    tasks::run_tasks();
 }
 
 polyhal_boot::define_entry!(main, secondary);
@@ -1,12 +1,13 @@
 use super::types::poll::EpollFile;
 use super::SysResult;
+use crate::tasks::{MemFd, PageCache, WaitTickOrEvent};
 use crate::user::UserTaskContainer;
 use crate::utils::useref::UserRef;
 use alloc::sync::Arc;
 use bit_field::BitArray;
 use core::cmp;
 use core::time::Duration;
-use executor::yield_now;
+use executor::{select, timer::Sleep};
 use fs::dentry::umount;
 use fs::file::File;
 use fs::{pipe::create_pipe, SeekFrom};
@@ -14,8 +15,11 @@ use libc_types::consts::UTIME_NOW;
 use libc_types::epoll::{EpollCtl, EpollEvent};
 #[cfg(target_arch = "x86_64")]
 use libc_types::fcntl::AT_FDCWD;
-use libc_types::fcntl::{FcntlCmd, OpenFlags, AT_SYMLINK_NOFOLLOW};
+use libc_types::fcntl::{FcntlCmd, OpenFlags, SealFlags, AT_SYMLINK_NOFOLLOW};
+use libc_types::mman::MemfdFlags;
 use libc_types::poll::{PollEvent, PollFd};
+use libc_types::resource::{RLIMIT_FSIZE, RLIM_INFINITY};
+use libc_types::signal::SignalNum;
 use libc_types::types::{IoVec, Stat, StatFS, StatMode, TimeSpec};
 use log::debug;
 use polyhal::timer::current_time;
@@ -48,11 +52,25 @@ impl UserTaskContainer {
             self.tid, fd as isize, buf_ptr, count
         );
         let buffer = buf_ptr.slice_mut_with_len(count);
-        self.task
-            .get_fd(fd)
-            .ok_or(Errno::EBADF)?
-            .async_read(buffer)
-            .await
+        let file = self.task.get_fd(fd).ok_or(Errno::EBADF)?;
+        PageCache::file_read(&file, *file.offset.lock(), count)?;
+        file.async_read(buffer).await
+    }
+
+    /// Limit a write of `len` bytes at `offset` of `file` by RLIMIT_FSIZE.
+    ///
+    /// Only regular files are limited. A write starting at the limit raises SIGXFSZ and
+    /// fails with EFBIG, a write crossing it is shortened.
+    fn fsize_limit(&self, file: &File, offset: usize, len: usize) -> Result<usize, Errno> {
+        let limit = self.task.pcb.lock().rlimits[RLIMIT_FSIZE].curr;
+        if limit == RLIM_INFINITY || len == 0 || file.file_type()? != FileType::File {
+            return Ok(len);
+        }
+        if offset >= limit {
+            self.task.tcb.write().signal.insert(SignalNum::XFSZ);
+            return Err(Errno::EFBIG);
+        }
+        Ok(len.min(limit - offset))
     }
 
     pub async fn sys_write(&self, fd: usize, buf_ptr: VirtAddr, count: usize) -> SysResult {
@@ -60,9 +78,13 @@ impl UserTaskContainer {
             "[task {}] sys_write @ fd: {} buf_ptr: {:?} count: {}",
             self.tid, fd as isize, buf_ptr, count
         );
-        let buffer = buf_ptr.slice_with_len(count);
         let file = self.task.get_fd(fd).ok_or(Errno::EBADF)?;
-        file.async_write(buffer).await
+        let offset = *file.offset.lock();
+        let count = self.fsize_limit(&file, offset, count)?;
+        let buffer = buf_ptr.slice_with_len(count);
+        let wsize = file.async_write(buffer).await?;
+        PageCache::file_written(&file, offset, &buffer[..wsize]);
+        Ok(wsize)
     }
 
     pub fn sys_readv(&self, fd: usize, iov: UserRef<IoVec>, iocnt: usize) -> SysResult {
@@ -73,6 +95,7 @@ impl UserTaskContainer {
 
         for io in iov {
             let buffer = UserRef::<u8>::from(io.base).slice_mut_with_len(io.len);
+            PageCache::file_read(&file, *file.offset.lock(), io.len)?;
             rsize += file.read(buffer)?;
         }
 
@@ -84,10 +107,17 @@ impl UserTaskContainer {
         let mut wsize = 0;
         let iov = iov.slice_mut_with_len(iocnt);
         let file = self.task.get_fd(fd).ok_or(Errno::EBADF)?;
+        let total = iov.iter().map(|x| x.len).sum();
+        let mut remaining = self.fsize_limit(&file, *file.offset.lock(), total)?;
 
         for io in iov {
-            let buffer = UserRef::<u8>::from(io.base).slice_mut_with_len(io.len);
-            wsize += file.write(buffer)?;
+            let len = io.len.min(remaining);
+            let buffer = UserRef::<u8>::from(io.base).slice_mut_with_len(len);
+            let offset = *file.offset.lock();
+            let written = file.write(buffer)?;
+            PageCache::file_written(&file, offset, &buffer[..written]);
+            wsize += written;
+            remaining -= len;
         }
 
         Ok(wsize)
@@ -136,9 +166,12 @@ impl UserTaskContainer {
                 .fd_open(newdir_fd, new_path, OpenFlags::CREAT | flags)?;
             let file_size = old_file.file_size()?;
             let mut buffer = vec![0u8; file_size];
+            PageCache::file_read(&old_file, 0, file_size)?;
             old_file.read(&mut buffer)?;
             new_file.write(&buffer)?;
             new_file.truncate(buffer.len())?;
+            PageCache::file_written(&new_file, 0, &buffer);
+            PageCache::file_truncated(&new_file, buffer.len());
         } else if old_file_type == FileType::Directory {
             self.task.fd_open(
                 newdir_fd,
@@ -172,6 +205,7 @@ impl UserTaskContainer {
         let file = self.task.fd_open(dir_fd, path, flags)?;
 
         file.remove_self()?;
+        PageCache::file_removed(&file);
         Ok(0)
     }
 
@@ -316,6 +350,7 @@ impl UserTaskContainer {
         let buffer = ptr.slice_mut_with_len(len);
 
         let file = self.task.get_fd(fd).ok_or(Errno::EBADF)?;
+        PageCache::file_read(&file, offset, len)?;
         file.readat(offset, buffer)
     }
 
@@ -330,11 +365,12 @@ impl UserTaskContainer {
             "sys_write @ fd: {} buf_ptr: {:?} count: {}",
             fd as isize, buf_ptr, count
         );
+        let file = self.task.get_fd(fd).ok_or(Errno::EBADF)?;
+        let count = self.fsize_limit(&file, offset, count)?;
         let buffer = buf_ptr.slice_with_len(count);
-        self.task
-            .get_fd(fd)
-            .ok_or(Errno::EBADF)?
-            .writeat(offset, buffer)
+        let wsize = file.writeat(offset, buffer)?;
+        PageCache::file_written(&file, offset, &buffer[..wsize]);
+        Ok(wsize)
     }
 
     pub fn sys_mount(
@@ -437,6 +473,31 @@ impl UserTaskContainer {
                 self.task.set_fd(fd, file);
                 Ok(0)
             }
+            FcntlCmd::ADDSEALS => {
+                let seals = SealFlags::from_bits(arg as _).ok_or(Errno::EINVAL)?;
+                let memfd = file
+                    .inner
+                    .clone()
+                    .downcast_arc::<MemFd>()
+                    .map_err(|_| Errno::EINVAL)?;
+                if !file
+                    .flags
+                    .lock()
+                    .intersects(OpenFlags::WRONLY | OpenFlags::RDWR)
+                {
+                    return Err(Errno::EPERM);
+                }
+                memfd.add_seals(seals)?;
+                Ok(0)
+            }
+            FcntlCmd::GETSEALS => {
+                let memfd = file
+                    .inner
+                    .clone()
+                    .downcast_arc::<MemFd>()
+                    .map_err(|_| Errno::EINVAL)?;
+                Ok(memfd.seals().bits() as _)
+            }
             _ => Ok(0),
         }
     }
@@ -572,16 +633,21 @@ impl UserTaskContainer {
             in_file.seek(SeekFrom::CURRENT(0))?
         };
         let rlen = cmp::min(in_file.file_size()? - curr_off, count);
+        let rlen = self.fsize_limit(&out_file, *out_file.offset.lock(), rlen)?;
 
         let mut buffer = vec![0u8; rlen];
 
+        PageCache::file_read(&in_file, curr_off, rlen)?;
         if offset == 0 {
             in_file.read(&mut buffer)?;
             self.task.set_fd(in_fd, in_file);
         } else {
             in_file.readat(offset, &mut buffer)?;
         }
-        out_file.write(&buffer)
+        let out_off = *out_file.offset.lock();
+        let wsize = out_file.write(&buffer)?;
+        PageCache::file_written(&out_file, out_off, &buffer[..wsize]);
+        Ok(wsize)
     }
 
     /// TODO: improve it.
@@ -602,6 +668,7 @@ impl UserTaskContainer {
         } else {
             Duration::MAX
         };
+        let mut deadline = Sleep::until(etime);
         let n = loop {
             let mut num = 0;
             for i in 0..nfds {
@@ -619,7 +686,7 @@ impl UserTaskContainer {
             if current_time() >= etime || num > 0 {
                 break num;
             }
-            yield_now().await;
+            select(WaitTickOrEvent::new(self.task.clone()), &mut deadline).await;
         };
         Ok(n)
     }
@@ -636,7 +703,12 @@ impl UserTaskContainer {
             poll_fds_ptr, nfds, timeout
         );
         let poll_fds = poll_fds_ptr.slice_mut_with_len(nfds);
-        let etime = current_time() + Duration::from_millis(timeout as _);
+        // A negative timeout means an infinite timeout.
+        let etime = match timeout < 0 {
+            true => Duration::MAX,
+            false => current_time() + Duration::from_millis(timeout as _),
+        };
+        let mut deadline = Sleep::until(etime);
         let n = loop {
             let mut num = 0;
             for i in 0..nfds {
@@ -651,10 +723,10 @@ impl UserTaskContainer {
                 }
             }
 
-            if (timeout > 0 && current_time() >= etime) || num > 0 {
+            if current_time() >= etime || num > 0 {
                 break num;
             }
-            yield_now().await;
+            select(WaitTickOrEvent::new(self.task.clone()), &mut deadline).await;
         };
         Ok(n)
     }
@@ -684,13 +756,12 @@ impl UserTaskContainer {
         } else {
             Duration::MAX
         };
+        let mut deadline = Sleep::until(timeout);
         let mut rfds_r = [0usize; 4];
         let mut wfds_r = [0usize; 4];
         let mut efds_r = [0usize; 4];
         loop {
-            yield_now().await;
             let mut num = 0;
-            let inner = self.task.pcb.lock();
             if readfds.is_valid() {
                 let rfds = readfds.slice_mut_with_len(4);
                 for i in 0..max_fdp1 {
@@ -699,11 +770,10 @@ impl UserTaskContainer {
                         rfds_r.set_bit(i, false);
                         continue;
                     }
-                    if inner.fd_table[i].is_none() {
+                    let Some(file) = self.task.get_fd(i) else {
                         rfds_r.set_bit(i, false);
                         continue;
-                    }
-                    let file = inner.fd_table[i].clone().unwrap();
+                    };
                     match file.poll(PollEvent::IN) {
                         Ok(res) => {
                             if res.contains(PollEvent::IN) {
@@ -723,11 +793,10 @@ impl UserTaskContainer {
                     if !wfds.get_bit(i) {
                         continue;
                     }
-                    if inner.fd_table[i].is_none() {
+                    let Some(file) = self.task.get_fd(i) else {
                         wfds_r.set_bit(i, false);
                         continue;
-                    }
-                    let file = inner.fd_table[i].clone().unwrap();
+                    };
                     match file.poll(PollEvent::OUT) {
                         Ok(res) => {
                             if res.contains(PollEvent::OUT) {
@@ -748,11 +817,10 @@ impl UserTaskContainer {
                     if !efds.get_bit(i) {
                         continue;
                     }
-                    if inner.fd_table[i].is_none() {
+                    let Some(file) = self.task.get_fd(i) else {
                         efds_r.set_bit(i, false);
                         continue;
-                    }
-                    let file = inner.fd_table[i].clone().unwrap();
+                    };
                     match file.poll(PollEvent::ERR) {
                         Ok(res) => {
                             if res.contains(PollEvent::ERR) {
@@ -766,7 +834,6 @@ impl UserTaskContainer {
                     }
                 }
             }
-            drop(inner);
             if num != 0 {
                 if readfds.is_valid() {
                     readfds.slice_mut_with_len(4).copy_from_slice(&rfds_r);
@@ -780,7 +847,7 @@ impl UserTaskContainer {
                 return Ok(num);
             }
 
-            if current_time() > timeout {
+            if current_time() >= timeout {
                 if readfds.is_valid() {
                     readfds.slice_mut_with_len(4).copy_from_slice(&rfds_r);
                 }
@@ -792,6 +859,7 @@ impl UserTaskContainer {
                 }
                 return Ok(0);
             }
+            select(WaitTickOrEvent::new(self.task.clone()), &mut deadline).await;
         }
     }
 
@@ -815,10 +883,34 @@ impl UserTaskContainer {
             return Err(Errno::EPERM);
         }
         let file = self.task.get_fd(fields).ok_or(Errno::EINVAL)?;
+        // The file can't grow past RLIMIT_FSIZE, the last byte is checked as a write.
+        if len > file.file_size()? {
+            self.fsize_limit(&file, len - 1, 1)?;
+        }
         file.truncate(len)?;
+        PageCache::file_truncated(&file, len);
         Ok(0)
     }
 
+    pub fn sys_memfd_create(&self, name: UserRef<i8>, flags: u32) -> SysResult {
+        let name = name.get_cstr().map_err(|_| Errno::EINVAL)?;
+        let flags = MemfdFlags::from_bits(flags).ok_or(Errno::EINVAL)?;
+        debug!(
+            "[task {}] sys_memfd_create @ name: {}, flags: {:?}",
+            self.tid, name, flags
+        );
+        // The name with the "memfd:" prefix must fit in NAME_MAX. There is no hugetlbfs
+        // to back a MFD_HUGETLB memfd.
+        if name.len() > 249 || flags.contains(MemfdFlags::HUGETLB) {
+            return Err(Errno::EINVAL);
+        }
+        let fd = self.task.alloc_fd().ok_or(Errno::EMFILE)?;
+        let memfd = MemFd::new(flags.contains(MemfdFlags::ALLOW_SEALING));
+        let path = format!("/memfd:{} (deleted)", name);
+        self.task.set_fd(fd, File::new_anonymous(memfd, &path));
+        Ok(fd)
+    }
+
     pub fn sys_epoll_create1(&self, flags: usize) -> SysResult {
         debug!("sys_epoll_create @ flags: {:#x}", flags);
         let file = Arc::new(EpollFile::new(flags));
@@ -874,10 +966,10 @@ impl UserTaskContainer {
         } else {
             current_time() + Duration::from_millis(timeout as _)
         };
+        let mut deadline = Sleep::until(end);
         let buffer = events.slice_mut_with_len(max_events);
         debug!("epoll_wait:{:#x?}", epfile.data.lock());
         let n = loop {
-            yield_now().await;
             let mut num = 0;
             for (fd, ev) in epfile.data.lock().iter() {
                 if let Some(file) = self.task.get_fd(*fd) {
@@ -893,6 +985,7 @@ impl UserTaskContainer {
             if current_time() >= end || num > 0 {
                 break num;
             }
+            select(WaitTickOrEvent::new(self.task.clone()), &mut deadline).await;
         };
 
         Ok(n)
@@ -914,12 +1007,19 @@ impl UserTaskContainer {
         );
         let in_file = self.task.get_fd(fd_in).ok_or(Errno::EBADF)?;
         let out_file = self.task.get_fd(fd_out).ok_or(Errno::EBADF)?;
+        let out_off = match off_out.is_valid() {
+            true => off_out.read(),
+            false => *out_file.offset.lock(),
+        };
+        let len = self.fsize_limit(&out_file, out_off, len)?;
         let mut buffer = vec![0u8; len];
         let rsize = if off_in.is_valid() {
+            PageCache::file_read(&in_file, off_in.read(), len)?;
             let rsize = in_file.readat(off_in.read(), &mut buffer)?;
             off_in.with_mut(|off| *off += rsize);
             rsize
         } else {
+            PageCache::file_read(&in_file, *in_file.offset.lock(), len)?;
             in_file.read(&mut buffer)?
         };
 
@@ -927,12 +1027,14 @@ impl UserTaskContainer {
             return Ok(0);
         }
 
-        if off_out.is_valid() {
-            let wsize = out_file.writeat(off_out.read(), &mut buffer[..rsize])?;
+        let wsize = if off_out.is_valid() {
+            let wsize = out_file.writeat(out_off, &mut buffer[..rsize])?;
             off_out.with_mut(|off| *off += wsize);
+            wsize
         } else {
-            out_file.write(&buffer[..rsize])?;
-        }
+            out_file.write(&buffer[..rsize])?
+        };
+        PageCache::file_written(&out_file, out_off, &buffer[..wsize]);
 
         Ok(rsize)
     }
@@ -1,18 +1,24 @@
 use super::SysResult;
+use crate::consts::HUGE_PAGE_SIZE;
 use crate::syscall::types::mm::map_mprot_to_flags;
-use crate::tasks::{MemArea, MemType};
+use crate::tasks::swap::{swapoff, swapon};
+use crate::tasks::{tlb, MemArea, MemFd, MemType, PageCache};
 use crate::user::UserTaskContainer;
 use crate::utils::useref::UserRef;
-use devices::PAGE_SIZE;
-use libc_types::mman::{MSyncFlags, MapFlags, MmapProt};
-use log::debug;
-use polyhal::VirtAddr;
-use runtime::frame::alignup;
+use alloc::vec::Vec;
+use core::sync::atomic::Ordering;
+use devices::{get_blk_device, PAGE_SIZE};
+use fs::Sdx;
+use libc_types::fcntl::{OpenFlags, AT_FDCWD};
+use libc_types::mman::{
+    MSyncFlags, MadviseAdvice, MapFlags, Mlock2Flags, MlockAllFlags, MmapProt, MremapFlags,
+    SwapFlags,
+};
+use log::{debug, warn};
+use polyhal::{MappingFlags, VirtAddr};
+use runtime::frame::{alignup, get_total_pages, SWAP_TOTAL_PAGES};
 use syscalls::Errno;
 
-// The high 25bits in sv39 should be the same as bit 38.
-const MAP_AREA_START: usize = 0x2_0000_0000;
-
 impl UserTaskContainer {
     pub fn sys_brk(&self, addr: usize) -> SysResult {
         let heap = self.task.pcb.lock().heap;
@@ -34,103 +40,320 @@ impl UserTaskContainer {
     ) -> SysResult {
         let flags = MapFlags::from_bits_truncate(flags as _);
         let prot = MmapProt::from_bits_truncate(prot as _);
-        len = alignup(len, PAGE_SIZE);
+        let hugetlb = flags.contains(MapFlags::HUGETLB);
+        len = match hugetlb {
+            true => alignup(len, HUGE_PAGE_SIZE),
+            false => alignup(len, PAGE_SIZE),
+        };
         debug!(
             "[task {}] sys_mmap @ start: {:#x}, len: {:#x}, prot: {:?}, flags: {:?}, fd: {}, offset: {}",
             self.tid, start, len, prot, flags, fd as isize, off
         );
-        let file = self.task.get_fd(fd);
-
-        let addr = self.task.get_last_free_addr();
+        if off % PAGE_SIZE != 0 {
+            return Err(Errno::EINVAL);
+        }
+        // Huge pages only back private anonymous memory, there is no hugetlbfs.
+        if hugetlb && !flags.contains(MapFlags::ANONYMOUS) {
+            return Err(Errno::EINVAL);
+        }
+        let file = match flags.contains(MapFlags::ANONYMOUS) {
+            true => None,
+            false => self.task.get_fd(fd),
+        };
+        // A shared mapping writes to the file, it can be writable only if the file is
+        // opened for reading and writing. mprotect can't make it writable later either.
+        let writable = file
+            .as_ref()
+            .is_some_and(|x| x.flags.lock().contains(OpenFlags::RDWR));
+        if file.is_some()
+            && !writable
+            && flags.contains(MapFlags::SHARED)
+            && prot.contains(MmapProt::WRITE)
+        {
+            return Err(Errno::EACCES);
+        }
+        // A write sealed memfd can't be mapped shared and writable, nor made writable
+        // later by mprotect.
+        let sealed = file
+            .as_ref()
+            .and_then(|x| x.inner.downcast_ref::<MemFd>())
+            .is_some_and(|x| x.write_sealed());
+        if sealed && flags.contains(MapFlags::SHARED) && prot.contains(MmapProt::WRITE) {
+            return Err(Errno::EPERM);
+        }
 
-        let addr = if start == 0 {
-            if usize::from(addr) >= MAP_AREA_START {
-                addr
-            } else {
-                VirtAddr::from(MAP_AREA_START)
-            }
-        } else {
-            VirtAddr::new(start)
+        let addr = self.free_map_addr(len);
+        let fixed = flags.intersects(MapFlags::FIXED | MapFlags::FIXED_NOREPLACE);
+        let align = match hugetlb {
+            true => HUGE_PAGE_SIZE,
+            false => PAGE_SIZE,
+        };
+        let addr = match start {
+            0 if !fixed => addr,
+            _ if fixed && start % align != 0 => return Err(Errno::EINVAL),
+            _ if hugetlb && start % align != 0 => addr,
+            _ => VirtAddr::new(start / PAGE_SIZE * PAGE_SIZE),
         };
 
         if len == 0 {
             return Ok(addr.into());
         }
+        let end = addr.raw().checked_add(len).ok_or(Errno::ENOMEM)?;
+
+        let overlaped = self.task.pcb.lock().memset.overlapping(addr.raw(), end);
+        let (addr, replace) = match overlaped {
+            false => (addr, false),
+            true if flags.contains(MapFlags::FIXED_NOREPLACE) => return Err(Errno::EEXIST),
+            true if flags.contains(MapFlags::FIXED) => (addr, true),
+            // The hint is taken, place the mapping at a free address instead.
+            true => (self.free_map_addr(len), false),
+        };
+        let end = addr.raw() + len;
 
-        if flags.contains(MapFlags::FIXED) {
-            let overlaped = self
-                .task
+        let map_prot = map_mprot_to_flags(prot);
+        // A private writable mapping other than a stack grows the data segment.
+        let data = map_prot.contains(MappingFlags::W)
+            && !flags.intersects(MapFlags::SHARED | MapFlags::GROWSDOWN);
+        let mut pcb = self.task.pcb.lock();
+        // The memory replaced by MAP_FIXED is given back.
+        let replaced = match replace {
+            true => pcb.memset.mapped_size_in(addr.raw(), end),
+            false => 0,
+        };
+        if !pcb.may_expand(len - replaced, data) {
+            return Err(Errno::ENOMEM);
+        }
+        drop(pcb);
+        let mtype = match (flags.contains(MapFlags::SHARED), &file) {
+            (true, None) => MemType::Shared,
+            (true, Some(_)) => MemType::ShareFile,
+            (false, None) if flags.contains(MapFlags::GROWSDOWN) => MemType::Stack,
+            (false, _) => MemType::Mmap,
+        };
+        // Private anonymous memory is allocated on the first touch. Like the heuristic
+        // overcommit of linux, only the mapping larger than the memory and the swap
+        // together is refused unless MAP_NORESERVE asks for it.
+        if mtype == MemType::Mmap
+            && file.is_none()
+            && map_prot.contains(MappingFlags::W)
+            && !flags.contains(MapFlags::NORESERVE)
+            && len / PAGE_SIZE > get_total_pages() + SWAP_TOTAL_PAGES.load(Ordering::Acquire)
+        {
+            return Err(Errno::ENOMEM);
+        }
+        // The old mappings are removed only after the new one is known to be allowed.
+        if replace {
+            self.task
                 .pcb
                 .lock()
                 .memset
-                .overlapping(addr.raw(), addr.raw() + len);
-            if overlaped {
-                self.task.pcb.lock().memset.sub_area(
-                    addr.raw(),
-                    addr.raw() + len,
-                    &self.task.page_table,
-                );
+                .sub_area(addr.raw(), end, &self.task.page_table);
+            tlb::shootdown(&self.task.page_table);
+        }
+        // MAP_LOCKED and mlockall(MCL_FUTURE) lock the mapping, the pages are populated
+        // now unless MCL_ONFAULT defers them to the faults.
+        let mlockall = self.task.pcb.lock().mlockall;
+        let locked = flags.contains(MapFlags::LOCKED) || mlockall.contains(MlockAllFlags::FUTURE);
+        let populate = flags.intersects(MapFlags::POPULATE | MapFlags::LOCKED)
+            || (locked && !mlockall.contains(MlockAllFlags::ONFAULT));
+        match mtype {
+            MemType::Shared => {
+                self.task
+                    .frame_alloc(addr, MemType::Shared, map_prot, len.div_ceil(PAGE_SIZE))
+                    .ok_or(Errno::ENOMEM)?;
             }
-        } else if self
-            .task
-            .pcb
-            .lock()
-            .memset
-            .overlapping(addr.raw(), addr.raw() + len)
+            // File pages are read into the page cache on the first access.
+            _ => {
+                let mut area = MemArea {
+                    mtype,
+                    prot: map_prot,
+                    mtrackers: vec![],
+                    swapped: vec![],
+                    huge: vec![],
+                    locked,
+                    may_write: mtype != MemType::ShareFile || (writable && !sealed),
+                    file: file.map(|x| PageCache::get(&x)),
+                    offset: off,
+                    start: addr.raw(),
+                    len,
+                };
+                let pt = &self.task.page_table;
+                let mapped = match hugetlb && mtype == MemType::Mmap {
+                    true => (addr.raw()..end)
+                        .step_by(HUGE_PAGE_SIZE)
+                        .all(|x| area.map_huge_page(x, pt)),
+                    false => true,
+                };
+                let res = match mapped {
+                    true if populate => area.populate(addr.raw(), end, pt),
+                    true => Ok(()),
+                    false => Err(Errno::ENOMEM),
+                };
+                if let Err(err) = res {
+                    // Drop the pages and the huge pages mapped so far, other threads may
+                    // have used them.
+                    area.sub(addr.raw(), end, pt);
+                    tlb::shootdown(pt);
+                    return Err(err);
+                }
+                let mut pcb = self.task.pcb.lock();
+                pcb.memset.push(area);
+                pcb.sample_peak();
+            }
+        }
+        Ok(addr.into())
+    }
+
+    /// Get the address for a mapping without a fixed address, above all the mappings.
+    ///
+    /// A mapping of `len` bytes which can hold a huge page is aligned to the huge page.
+    fn free_map_addr(&self, len: usize) -> VirtAddr {
+        let addr = self.task.get_last_free_addr();
+        let mmap_base = self.task.pcb.lock().mmap_base;
+        let addr = match usize::from(addr) >= mmap_base {
+            true => addr.raw(),
+            false => mmap_base,
+        };
+        match len >= HUGE_PAGE_SIZE {
+            true => VirtAddr::new(alignup(addr, HUGE_PAGE_SIZE)),
+            false => VirtAddr::new(addr),
+        }
+    }
+
+    pub fn sys_mremap(
+        &self,
+        old_addr: usize,
+        old_size: usize,
+        new_size: usize,
+        flags: u32,
+        new_addr: usize,
+    ) -> SysResult {
+        debug!(
+            "[task {}] sys_mremap @ old_addr: {:#x}, old_size: {:#x}, new_size: {:#x}, flags: {:#x}, new_addr: {:#x}",
+            self.tid, old_addr, old_size, new_size, flags, new_addr
+        );
+        let flags = MremapFlags::from_bits(flags).ok_or(Errno::EINVAL)?;
+        let old_size = alignup(old_size, PAGE_SIZE);
+        let new_size = alignup(new_size, PAGE_SIZE);
+        // An old size of 0 duplicates a shared mapping on linux, which is not supported.
+        if old_addr % PAGE_SIZE != 0
+            || old_size == 0
+            || new_size == 0
+            || (flags.contains(MremapFlags::FIXED) && !flags.contains(MremapFlags::MAYMOVE))
+            || flags.contains(MremapFlags::DONTUNMAP)
         {
             return Err(Errno::EINVAL);
         }
+        let old_end = old_addr.checked_add(old_size).ok_or(Errno::EINVAL)?;
+        let free_addr = self.free_map_addr(new_size).raw();
+        let pt = &self.task.page_table;
+        let mut pcb = self.task.pcb.lock();
+        let area = pcb
+            .memset
+            .iter()
+            .find(|x| x.contains(old_addr))
+            .ok_or(Errno::EFAULT)?;
+        let area_end = area.start + area.len;
+        if old_end > area_end {
+            return Err(Errno::EFAULT);
+        }
+        if new_size > old_size && !pcb.may_expand(new_size - old_size, area.is_data()) {
+            return Err(Errno::ENOMEM);
+        }
 
-        if flags.contains(MapFlags::SHARED) {
-            match &file {
-                Some(file) => self
-                    .task
-                    .map_frames(
-                        addr,
-                        MemType::ShareFile,
-                        (len + PAGE_SIZE - 1) / PAGE_SIZE,
-                        Some(file.get_bare_file()),
-                        off,
-                        usize::from(addr),
-                        len,
-                    )
-                    .ok_or(Errno::EFAULT)?,
-                None => {
-                    let paddr = self
-                        .task
-                        .frame_alloc(addr, MemType::Shared, len.div_ceil(PAGE_SIZE))
-                        .ok_or(Errno::EFAULT)?;
-
-                    for i in 0..(len + PAGE_SIZE - 1) / PAGE_SIZE {
-                        self.task.map(
-                            paddr + i * PAGE_SIZE,
-                            addr + i * PAGE_SIZE,
-                            map_mprot_to_flags(prot),
-                        );
-                    }
-                    paddr
+        if !flags.contains(MremapFlags::FIXED) {
+            // Shrink in place.
+            if new_size <= old_size {
+                if new_size < old_size {
+                    pcb.memset.sub_area(old_addr + new_size, old_end, pt);
+                    drop(pcb);
+                    tlb::shootdown(pt);
                 }
-            };
-        } else if file.is_some() {
-            self.task
-                .frame_alloc(addr, MemType::Mmap, len.div_ceil(PAGE_SIZE))
-                .ok_or(Errno::EFAULT)?;
-        } else {
-            self.task.pcb.lock().memset.push(MemArea {
-                mtype: MemType::Mmap,
-                mtrackers: vec![],
-                file: None,
-                offset: 0,
-                start: addr.raw(),
-                len,
-            });
+                return Ok(old_addr);
+            }
+            // Grow in place if the following memory is free.
+            let new_end = old_addr.checked_add(new_size).ok_or(Errno::ENOMEM)?;
+            if old_end == area_end && !pcb.memset.overlapping(old_end, new_end) {
+                let area = pcb.memset.iter_mut().find(|x| x.contains(old_addr));
+                area.ok_or(Errno::EFAULT)?.len += new_size - old_size;
+                return Ok(old_addr);
+            }
+            if !flags.contains(MremapFlags::MAYMOVE) {
+                return Err(Errno::ENOMEM);
+            }
+        }
+
+        // Move the mapping, the pages go with it.
+        let new_addr = match flags.contains(MremapFlags::FIXED) {
+            true => {
+                let new_end = new_addr.checked_add(new_size).ok_or(Errno::EINVAL)?;
+                if new_addr % PAGE_SIZE != 0 || (new_addr < old_end && old_addr < new_end) {
+                    return Err(Errno::EINVAL);
+                }
+                pcb.memset.sub_area(new_addr, new_end, pt);
+                new_addr
+            }
+            false => free_addr,
+        };
+        let old_end = match new_size < old_size {
+            true => {
+                pcb.memset.sub_area(old_addr + new_size, old_end, pt);
+                old_addr + new_size
+            }
+            false => old_end,
         };
+        let mut area = pcb
+            .memset
+            .take_range(old_addr, old_end, pt)
+            .ok_or(Errno::EFAULT)?;
+        area.move_to(new_addr, pt);
+        area.len = new_size;
+        pcb.memset.push(area);
+        drop(pcb);
+        tlb::shootdown(pt);
+        Ok(new_addr)
+    }
 
-        if let Some(file) = file {
-            let buffer = UserRef::<u8>::from(addr).slice_mut_with_len(len);
-            file.readat(off, buffer)?;
+    pub fn sys_madvise(&self, addr: usize, len: usize, advice: usize) -> SysResult {
+        debug!(
+            "[task {}] sys_madvise @ addr: {:#x}, len: {:#x}, advice: {}",
+            self.tid, addr, len, advice
+        );
+        if addr % PAGE_SIZE != 0 {
+            return Err(Errno::EINVAL);
         }
-        Ok(addr.into())
+        let Ok(advice) = MadviseAdvice::try_from(advice) else {
+            warn!("unsupported madvise advice: {}", advice);
+            return Ok(0);
+        };
+        let end = addr
+            .checked_add(alignup(len, PAGE_SIZE))
+            .ok_or(Errno::EINVAL)?;
+        let pt = &self.task.page_table;
+        let mut pcb = self.task.pcb.lock();
+        if !pcb.memset.overlapping(addr, end) {
+            return Err(Errno::ENOMEM);
+        }
+        let areas = pcb.memset.iter_mut().filter(|x| x.overlapping(addr, end));
+        match advice {
+            MadviseAdvice::WillNeed => {
+                for area in areas {
+                    area.populate(addr, end, pt)?;
+                }
+            }
+            // Shared anonymous pages have no backing to be filled again from.
+            MadviseAdvice::DontNeed => areas
+                .filter(|x| x.mtype != MemType::Shared)
+                .for_each(|x| x.discard(addr, end, pt)),
+            // Pages are freed right away instead of under memory pressure.
+            MadviseAdvice::Free => areas
+                .filter(|x| x.file.is_none() && x.mtype != MemType::Shared)
+                .for_each(|x| x.discard(addr, end, pt)),
+            MadviseAdvice::Normal | MadviseAdvice::Random | MadviseAdvice::Sequential => {}
+        }
+        drop(pcb);
+        tlb::shootdown(pt);
+        Ok(0)
     }
 
     pub fn sys_munmap(&self, start: usize, len: usize) -> SysResult {
@@ -139,26 +362,214 @@ impl UserTaskContainer {
             pcb.memset
                 .sub_area(start, start + len, &self.task.page_table);
         });
+        tlb::shootdown(&self.task.page_table);
         Ok(0)
     }
 
     pub fn sys_mprotect(&self, start: usize, len: usize, prot: u32) -> SysResult {
         let prot = MmapProt::from_bits(prot).ok_or(Errno::EINVAL)?;
         debug!(
-            "sys_mprotect @ start: {:#x}, len: {:#x}, prot: {:?}",
-            start, len, prot
+            "[task {}] sys_mprotect @ start: {:#x}, len: {:#x}, prot: {:?}",
+            self.tid, start, len, prot
         );
-        // Err(LinuxError::EPERM)
+        if start % PAGE_SIZE != 0 {
+            return Err(Errno::EINVAL);
+        }
+        let end = start
+            .checked_add(alignup(len, PAGE_SIZE))
+            .ok_or(Errno::ENOMEM)?;
+        let mut pcb = self.task.pcb.lock();
+        if prot.contains(MmapProt::WRITE)
+            && pcb
+                .memset
+                .iter()
+                .any(|x| x.overlapping(start, end) && !x.may_write)
+        {
+            return Err(Errno::EACCES);
+        }
+        let protected =
+            pcb.memset
+                .protect(start, end, map_mprot_to_flags(prot), &self.task.page_table);
+        drop(pcb);
+        if !protected {
+            return Err(Errno::ENOMEM);
+        }
+        // The other threads may still access the pages with the old permission.
+        tlb::shootdown(&self.task.page_table);
         Ok(0)
     }
 
     pub fn sys_msync(&self, addr: usize, len: usize, flags: u32) -> SysResult {
-        let flags = MSyncFlags::from_bits_truncate(flags);
+        let flags = MSyncFlags::from_bits(flags).ok_or(Errno::EINVAL)?;
         debug!(
             "sys_msync @ addr: {:#x} len: {:#x} flags: {:?}",
             addr, len, flags
         );
-        // use it temporarily
+        let end = addr
+            .checked_add(alignup(len, PAGE_SIZE))
+            .ok_or(Errno::ENOMEM)?;
+        if addr % PAGE_SIZE != 0 || flags.contains(MSyncFlags::ASYNC | MSyncFlags::SYNC) {
+            return Err(Errno::EINVAL);
+        }
+        let pt = &self.task.page_table;
+        let mut pcb = self.task.pcb.lock();
+        if !pcb.memset.covers(addr, end) {
+            return Err(Errno::ENOMEM);
+        }
+        // All the mappings of a file share the cached pages, there is no other copy to
+        // invalidate. Only the locked pages can't be invalidated.
+        if flags.contains(MSyncFlags::INVALIDATE)
+            && pcb
+                .memset
+                .iter()
+                .any(|x| x.overlapping(addr, end) && x.locked)
+        {
+            return Err(Errno::EBUSY);
+        }
+        // The pages only this mapping holds are write-protected and written back clean.
+        let mut cleaned = Vec::new();
+        for area in pcb.memset.iter_mut().filter(|x| x.overlapping(addr, end)) {
+            if let Some(cache) = area.file.clone() {
+                let indexes = area.clean_pages(addr, end, pt);
+                cleaned.extend(indexes.into_iter().map(|index| (cache.clone(), index)));
+            }
+        }
+        drop(pcb);
+        if !cleaned.is_empty() {
+            tlb::shootdown(pt);
+        }
+        let mut res = Ok(());
+        for (cache, index) in cleaned {
+            if res.is_ok() {
+                res = cache.write_back(index);
+            }
+            // The page written back partly or not at all is still dirty.
+            if res.is_err() {
+                cache.set_dirty(index);
+            }
+        }
+        res?;
+        // There is no background writeback, MS_ASYNC writes the pages back now as well.
+        // MS_SYNC also waits for the filesystem to write them to the storage.
+        let pcb = self.task.pcb.lock();
+        for area in pcb.memset.iter().filter(|x| x.overlapping(addr, end)) {
+            area.sync(addr, end)?;
+            match &area.file {
+                Some(cache)
+                    if area.mtype == MemType::ShareFile && flags.contains(MSyncFlags::SYNC) =>
+                {
+                    cache.flush()?
+                }
+                _ => {}
+            }
+        }
+        Ok(0)
+    }
+
+    pub fn sys_mlock(&self, addr: usize, len: usize) -> SysResult {
+        self.sys_mlock2(addr, len, 0)
+    }
+
+    pub fn sys_mlock2(&self, addr: usize, len: usize, flags: u32) -> SysResult {
+        let flags = Mlock2Flags::from_bits(flags).ok_or(Errno::EINVAL)?;
+        debug!(
+            "[task {}] sys_mlock2 @ addr: {:#x}, len: {:#x}, flags: {:?}",
+            self.tid, addr, len, flags
+        );
+        let end = addr.checked_add(len).ok_or(Errno::ENOMEM)?;
+        let start = addr / PAGE_SIZE * PAGE_SIZE;
+        let end = alignup(end, PAGE_SIZE);
+        let pt = &self.task.page_table;
+        let mut pcb = self.task.pcb.lock();
+        if !pcb.memset.lock(start, end, true, pt) {
+            return Err(Errno::ENOMEM);
+        }
+        // MLOCK_ONFAULT locks the pages when they are touched.
+        if !flags.contains(Mlock2Flags::ONFAULT) {
+            pcb.memset
+                .iter_mut()
+                .filter(|x| x.overlapping(start, end))
+                .try_for_each(|x| x.populate(start, end, pt))?;
+        }
+        pcb.sample_peak();
+        drop(pcb);
+        // Splitting the areas may split the huge pages.
+        tlb::shootdown(pt);
+        Ok(0)
+    }
+
+    pub fn sys_munlock(&self, addr: usize, len: usize) -> SysResult {
+        debug!(
+            "[task {}] sys_munlock @ addr: {:#x}, len: {:#x}",
+            self.tid, addr, len
+        );
+        let end = addr.checked_add(len).ok_or(Errno::ENOMEM)?;
+        let start = addr / PAGE_SIZE * PAGE_SIZE;
+        let end = alignup(end, PAGE_SIZE);
+        let pt = &self.task.page_table;
+        let unlocked = self.task.pcb.lock().memset.lock(start, end, false, pt);
+        if !unlocked {
+            return Err(Errno::ENOMEM);
+        }
+        tlb::shootdown(pt);
+        Ok(0)
+    }
+
+    pub fn sys_mlockall(&self, flags: u32) -> SysResult {
+        let flags = MlockAllFlags::from_bits(flags).ok_or(Errno::EINVAL)?;
+        debug!("[task {}] sys_mlockall @ flags: {:?}", self.tid, flags);
+        if !flags.intersects(MlockAllFlags::CURRENT | MlockAllFlags::FUTURE) {
+            return Err(Errno::EINVAL);
+        }
+        let pt = &self.task.page_table;
+        let mut pcb = self.task.pcb.lock();
+        pcb.mlockall = flags;
+        if flags.contains(MlockAllFlags::CURRENT) {
+            for area in pcb.memset.iter_mut() {
+                area.locked = true;
+                if !flags.contains(MlockAllFlags::ONFAULT) {
+                    area.populate(area.start, area.start + area.len, pt)?;
+                }
+            }
+            pcb.sample_peak();
+        }
+        Ok(0)
+    }
+
+    pub fn sys_munlockall(&self) -> SysResult {
+        debug!("[task {}] sys_munlockall", self.tid);
+        let mut pcb = self.task.pcb.lock();
+        pcb.mlockall = MlockAllFlags::empty();
+        pcb.memset.iter_mut().for_each(|x| x.locked = false);
+        Ok(0)
+    }
+
+    pub fn sys_swapon(&self, path: UserRef<i8>, flags: u32) -> SysResult {
+        let path = path.get_cstr().map_err(|_| Errno::EINVAL)?;
+        let flags = SwapFlags::from_bits(flags).ok_or(Errno::EINVAL)?;
+        debug!("sys_swapon @ path: {}, flags: {:?}", path, flags);
+        // The areas are used in the order they are enabled, the priority is ignored.
+        let file = self.task.fd_open(AT_FDCWD, path, OpenFlags::RDWR)?;
+        // Swap files are not supported, the pages can't be written through the
+        // filesystem while reclaiming.
+        let sdx = file
+            .get_bare_file()
+            .downcast_arc::<Sdx>()
+            .map_err(|_| Errno::EINVAL)?;
+        // The first block device holds the root filesystem.
+        if sdx.device_id() == 0 {
+            return Err(Errno::EBUSY);
+        }
+        let device = get_blk_device(sdx.device_id()).ok_or(Errno::ENODEV)?;
+        swapon(file.path(), device)?;
+        Ok(0)
+    }
+
+    pub fn sys_swapoff(&self, path: UserRef<i8>) -> SysResult {
+        let path = path.get_cstr().map_err(|_| Errno::EINVAL)?;
+        debug!("sys_swapoff @ path: {}", path);
+        let file = self.task.fd_open(AT_FDCWD, path, OpenFlags::RDONLY)?;
+        swapoff(&file.path())?;
         Ok(0)
     }
 }
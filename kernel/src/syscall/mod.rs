@@ -208,14 +208,33 @@ impl UserTaskContainer {
                 args[4].into(),
                 args[5].into(),
             ),
+            Sysno::sendmsg => {
+                self.sys_sendmsg(args[0] as _, args[1].into(), args[2] as _)
+                    .await
+            }
+            Sysno::recvmsg => {
+                self.sys_recvmsg(args[0] as _, args[1].into(), args[2] as _)
+                    .await
+            }
             Sysno::syslog => self.sys_klogctl(args[0] as _, args[1].into(), args[2] as _),
             Sysno::sysinfo => self.sys_info(args[0].into()),
             Sysno::msync => self.sys_msync(args[0], args[1], args[2] as _),
+            Sysno::memfd_create => self.sys_memfd_create(args[0].into(), args[1] as _),
+            Sysno::mlock => self.sys_mlock(args[0], args[1]),
+            Sysno::mlock2 => self.sys_mlock2(args[0], args[1], args[2] as _),
+            Sysno::munlock => self.sys_munlock(args[0], args[1]),
+            Sysno::mlockall => self.sys_mlockall(args[0] as _),
+            Sysno::munlockall => self.sys_munlockall(),
+            Sysno::swapon => self.sys_swapon(args[0].into(), args[1] as _),
+            Sysno::swapoff => self.sys_swapoff(args[0].into()),
+            Sysno::mremap => self.sys_mremap(args[0], args[1], args[2], args[3] as _, args[4]),
+            Sysno::madvise => self.sys_madvise(args[0], args[1], args[2]),
             Sysno::exit_group => self.sys_exit_group(args[0]),
             Sysno::ftruncate => self.sys_ftruncate(args[0], args[1]),
             Sysno::shmget => self.sys_shmget(args[0] as _, args[1] as _, args[2] as _),
             Sysno::shmat => self.sys_shmat(args[0] as _, args[1] as _, args[2] as _),
-            Sysno::shmctl => self.sys_shmctl(args[0] as _, args[1] as _, args[2] as _),
+            Sysno::shmdt => self.sys_shmdt(args[0] as _),
+            Sysno::shmctl => self.sys_shmctl(args[0] as _, args[1] as _, args[2].into()),
             Sysno::setitimer => self.sys_setitimer(args[0] as _, args[1].into(), args[2].into()),
             Sysno::setsockopt => self.sys_setsockopt(
                 args[0] as _,
@@ -235,10 +254,16 @@ impl UserTaskContainer {
             Sysno::getpeername => self.sys_getpeername(args[0] as _, args[1].into(), args[2] as _),
             Sysno::setsid => self.sys_setsid(),
             Sysno::shutdown => self.sys_shutdown(args[0] as _, args[1] as _),
-            Sysno::sched_getparam => self.sys_sched_getparam(args[0] as _, args[1] as _),
+            Sysno::sched_getparam => self.sys_sched_getparam(args[0] as _, args[1].into()),
+            Sysno::sched_setparam => self.sys_sched_setparam(args[0] as _, args[1].into()).await,
             Sysno::sched_setscheduler => {
-                self.sys_sched_setscheduler(args[0] as _, args[1] as _, args[2] as _)
+                self.sys_sched_setscheduler(args[0] as _, args[1] as _, args[2].into())
+                    .await
             }
+            Sysno::sched_get_priority_max => self.sys_sched_get_priority_max(args[0] as _),
+            Sysno::sched_get_priority_min => self.sys_sched_get_priority_min(args[0] as _),
+            Sysno::setpriority => self.sys_setpriority(args[0] as _, args[1] as _, args[2] as _),
+            Sysno::getpriority => self.sys_getpriority(args[0] as _, args[1] as _),
             Sysno::clock_getres => self.sys_clock_getres(args[0] as _, args[1].into()),
             Sysno::clock_nanosleep => {
                 self.sys_clock_nanosleep(args[0] as _, args[1] as _, args[2].into(), args[3].into())
@@ -266,15 +291,14 @@ impl UserTaskContainer {
                 args[4],
                 args[5] as _,
             ),
+            Sysno::getcpu => self.sys_getcpu(args[0].into(), args[1].into()),
             Sysno::getrandom => self.sys_getrandom(args[0].into(), args[1] as _, args[2] as _),
+            Sysno::personality => self.sys_personality(args[0]),
             Sysno::sched_setaffinity => {
-                log::debug!("sys_setaffinity() ");
-                Ok(0)
-            }
-            Sysno::sched_getscheduler => {
-                log::debug!("sys_sched_getscheduler");
-                Ok(0)
+                self.sys_sched_setaffinity(args[0], args[1], args[2].into())
+                    .await
             }
+            Sysno::sched_getscheduler => self.sys_sched_getscheduler(args[0] as _),
             Sysno::sched_getaffinity => {
                 self.sys_sched_getaffinity(args[0], args[1], args[2].into())
             }
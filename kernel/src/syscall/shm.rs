@@ -1,38 +1,119 @@
 use super::SysResult;
-use crate::tasks::{MapedSharedMemory, SharedMemory, SHARED_MEMORY};
+use crate::tasks::{tlb, MapedSharedMemory, SharedMemory, SHARED_MEMORY};
 use crate::user::UserTaskContainer;
+use crate::utils::useref::UserRef;
 use alloc::{sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
 use devices::PAGE_SIZE;
+use libc_types::shm::{
+    IpcPerm, ShmAtFlags, ShmInfo, ShmLimits, ShmidDs, IPC_64, IPC_CREAT, IPC_EXCL, IPC_INFO,
+    IPC_PRIVATE, IPC_RMID, IPC_SET, IPC_STAT, SHM_DEST, SHM_INFO, SHM_LOCK, SHM_LOCKED, SHM_STAT,
+    SHM_STAT_ANY, SHM_UNLOCK,
+};
 use log::debug;
-use polyhal::{va, MappingFlags};
-use runtime::frame::{frame_alloc_much, FrameTracker};
+use polyhal::{timer::current_time, va, MappingFlags};
+use runtime::frame::frame_alloc_much;
 use syscalls::Errno;
 
+/// The largest segment and the total pages of the segments, the defaults of linux.
+const SHMMAX: usize = usize::MAX - (1 << 24);
+const SHMALL: usize = usize::MAX - (1 << 24);
+/// The most segments in the system.
+const SHMMNI: usize = 4096;
+/// The id of the next segment, the ids of the removed segments are not reused.
+static NEXT_SHMID: AtomicUsize = AtomicUsize::new(1);
+
 impl UserTaskContainer {
-    pub fn sys_shmget(&self, mut key: usize, size: usize, shmflg: usize) -> SysResult {
+    /// Check the caller may access the segment, `access` has the read (4) and write (2) bits.
+    ///
+    /// The owner, group and other bits of the mode are checked in turn, root may access
+    /// every segment like a task with CAP_IPC_OWNER on linux.
+    fn shm_access(&self, perm: &IpcPerm, access: u32) -> Result<(), Errno> {
+        let uid = self.sys_geteuid()? as u32;
+        let gid = self.sys_getegid()? as u32;
+        let granted = if uid == perm.uid || uid == perm.cuid {
+            perm.mode >> 6
+        } else if gid == perm.gid || gid == perm.cgid {
+            perm.mode >> 3
+        } else {
+            perm.mode
+        };
+        match uid == 0 || granted & access == access {
+            true => Ok(()),
+            false => Err(Errno::EACCES),
+        }
+    }
+
+    /// Check the caller owns or created the segment, IPC_SET, IPC_RMID and SHM_LOCK need it.
+    fn shm_owner(&self, perm: &IpcPerm) -> Result<(), Errno> {
+        let uid = self.sys_geteuid()? as u32;
+        match uid == 0 || uid == perm.uid || uid == perm.cuid {
+            true => Ok(()),
+            false => Err(Errno::EPERM),
+        }
+    }
+
+    pub fn sys_shmget(&self, key: usize, size: usize, shmflg: usize) -> SysResult {
         debug!(
             "sys_shmget @ key: {}, size: {}, shmflg: {:#o}",
             key, size, shmflg
         );
-        if key == 0 {
-            key = SHARED_MEMORY.lock().keys().cloned().max().unwrap_or(0) + 1;
+        let mode = (shmflg & 0o777) as u32;
+        let create = shmflg & IPC_CREAT != 0;
+        let uid = self.sys_geteuid()? as u32;
+        let gid = self.sys_getegid()? as u32;
+        // The frames are allocated before the segments are locked, the allocation may
+        // reclaim memory. They are dropped if the key is found.
+        let frames = (create && size != 0 && size <= SHMMAX).then(|| {
+            frame_alloc_much(size.div_ceil(PAGE_SIZE))
+                .map(|x| x.into_iter().map(Arc::new).collect::<Vec<_>>())
+                .ok_or(Errno::ENOMEM)
+        });
+        // The lookup and the insertion are under one lock, two callers creating the same
+        // key get the same segment.
+        let mut shms = SHARED_MEMORY.lock();
+        // The segments removed by IPC_RMID have the private key, they are never found.
+        let found = match key {
+            IPC_PRIVATE => None,
+            _ => shms
+                .iter()
+                .find(|(_, x)| x.info.lock().perm.key == key as i32)
+                .map(|(id, x)| (*id, x.clone())),
+        };
+        if let Some((shmid, mem)) = found {
+            drop(shms);
+            if create && shmflg & IPC_EXCL != 0 {
+                return Err(Errno::EEXIST);
+            }
+            let info = mem.info.lock();
+            if size > info.segsz {
+                return Err(Errno::EINVAL);
+            }
+            self.shm_access(&info.perm, (mode | mode >> 3 | mode >> 6) & 0o7)?;
+            return Ok(shmid);
         }
-        let mem = SHARED_MEMORY.lock().get(&key).cloned();
-        if mem.is_some() {
-            return Ok(key);
+        if !create {
+            return Err(Errno::ENOENT);
         }
-        if shmflg & 01000 > 0 {
-            let shm: Vec<Arc<FrameTracker>> = frame_alloc_much(size.div_ceil(PAGE_SIZE))
-                .expect("can't alloc page in shm")
-                .into_iter()
-                .map(Arc::new)
-                .collect();
-            SHARED_MEMORY
-                .lock()
-                .insert(key, Arc::new(SharedMemory::new(shm)));
-            return Ok(key);
+        let frames = frames.ok_or(Errno::EINVAL)??;
+        if shms.len() >= SHMMNI {
+            return Err(Errno::ENOSPC);
         }
-        Err(Errno::ENOENT)
+        let perm = IpcPerm {
+            key: key as _,
+            uid,
+            gid,
+            cuid: uid,
+            cgid: gid,
+            mode,
+            ..Default::default()
+        };
+        let shmid = NEXT_SHMID.fetch_add(1, Ordering::Relaxed);
+        shms.insert(
+            shmid,
+            Arc::new(SharedMemory::new(frames, perm, size, self.task.process_id)),
+        );
+        Ok(shmid)
     }
 
     pub fn sys_shmat(&self, shmid: usize, shmaddr: usize, shmflg: usize) -> SysResult {
@@ -40,52 +121,190 @@ impl UserTaskContainer {
             "sys_shmat @ shmid: {}, shmaddr: {}, shmflg: {:#o}",
             shmid, shmaddr, shmflg
         );
-        let vaddr = self.task.get_last_free_addr();
+        let flags = ShmAtFlags::from_bits_truncate(shmflg);
+        let mem = SHARED_MEMORY
+            .lock()
+            .get(&shmid)
+            .cloned()
+            .ok_or(Errno::EINVAL)?;
+        let (access, mut prot) = match flags.contains(ShmAtFlags::RDONLY) {
+            true => (0o4, MappingFlags::U | MappingFlags::R),
+            false => (0o6, MappingFlags::U | MappingFlags::R | MappingFlags::W),
+        };
+        if flags.contains(ShmAtFlags::EXEC) {
+            prot |= MappingFlags::X;
+        }
+        self.shm_access(&mem.info.lock().perm, access)?;
+        let size = mem.trackers.len() * PAGE_SIZE;
 
-        let vaddr = if shmaddr == 0 {
+        let addr = if shmaddr == 0 {
+            if flags.contains(ShmAtFlags::REMAP) {
+                return Err(Errno::EINVAL);
+            }
+            let vaddr = self.task.get_last_free_addr();
             if vaddr >= va!(0x4000_0000) {
-                vaddr
+                vaddr.raw()
             } else {
-                va!(0x4000_0000)
+                0x4000_0000
             }
         } else {
-            va!(shmaddr)
+            // SHMLBA is the page size.
+            let addr = match flags.contains(ShmAtFlags::RND) {
+                true => shmaddr / PAGE_SIZE * PAGE_SIZE,
+                false => shmaddr,
+            };
+            if addr == 0 || addr % PAGE_SIZE != 0 {
+                return Err(Errno::EINVAL);
+            }
+            addr
         };
-        let trackers = SHARED_MEMORY.lock().get(&shmid).cloned();
-        if trackers.is_none() {
-            return Err(Errno::ENOENT);
+        let end = addr.checked_add(size).ok_or(Errno::EINVAL)?;
+
+        // The checks, the removal of the old mappings and the mapping are done under one
+        // lock, a failed shmat leaves the address space as it was.
+        let mut pcb = self.task.pcb.lock();
+        if !pcb.may_expand(size, false) {
+            return Err(Errno::ENOMEM);
         }
-        trackers
-            .as_ref()
-            .unwrap()
-            .trackers
+        if pcb
+            .shms
             .iter()
-            .enumerate()
-            .for_each(|(i, x)| {
-                debug!("map {:?} @ {:?}", vaddr.raw() + i * PAGE_SIZE, x.0);
-                self.task
-                    .map(x.0, vaddr + i * PAGE_SIZE, MappingFlags::URWX);
-            });
-        let size = trackers.as_ref().unwrap().trackers.len() * PAGE_SIZE;
-        self.task.pcb.lock().shms.push(MapedSharedMemory {
-            key: shmid,
-            mem: trackers.unwrap(),
-            start: vaddr.raw(),
-            size,
+            .any(|x| x.start < end && addr < x.start + x.size)
+        {
+            return Err(Errno::EINVAL);
+        }
+        // SHM_REMAP replaces the mappings in the range, they are kept otherwise.
+        let remapped = pcb.memset.overlapping(addr, end);
+        if remapped {
+            if !flags.contains(ShmAtFlags::REMAP) {
+                return Err(Errno::EINVAL);
+            }
+            pcb.memset.sub_area(addr, end, &self.task.page_table);
+        }
+        mem.trackers.iter().enumerate().for_each(|(i, x)| {
+            debug!("map {:?} @ {:?}", addr + i * PAGE_SIZE, x.0);
+            self.task.map(x.0, va!(addr + i * PAGE_SIZE), prot);
         });
-        Ok(vaddr.raw())
+        {
+            let mut info = mem.info.lock();
+            info.atime = current_time().as_secs() as _;
+            info.lpid = self.task.process_id as _;
+        }
+        pcb.shms
+            .push(MapedSharedMemory::new(shmid, mem, addr, prot));
+        pcb.sample_peak();
+        drop(pcb);
+        if remapped {
+            tlb::shootdown(&self.task.page_table);
+        }
+        Ok(addr)
     }
 
-    pub fn sys_shmctl(&self, shmid: usize, cmd: usize, arg: usize) -> SysResult {
-        debug!("sys_shmctl @ shmid: {}, cmd: {}, arg: {}", shmid, cmd, arg);
+    pub fn sys_shmdt(&self, shmaddr: usize) -> SysResult {
+        debug!("sys_shmdt @ shmaddr: {:#x}", shmaddr);
+        let shm = {
+            let mut pcb = self.task.pcb.lock();
+            let index = pcb
+                .shms
+                .iter()
+                .position(|x| x.start == shmaddr)
+                .ok_or(Errno::EINVAL)?;
+            pcb.shms.remove(index)
+        };
+        (0..shm.size / PAGE_SIZE).for_each(|i| {
+            self.task
+                .page_table
+                .unmap_page(va!(shm.start + i * PAGE_SIZE))
+        });
+        tlb::shootdown(&self.task.page_table);
+        shm.mem.info.lock().lpid = self.task.process_id as _;
+        // The segment is freed here if it is removed and this is the last attach.
+        drop(shm);
+        Ok(0)
+    }
 
-        if cmd == 0 {
-            // SHARED_MEMORY.lock().remove(&shmid);
-            if let Some(map) = SHARED_MEMORY.lock().get_mut(&shmid) {
-                *map.deleted.lock() = true;
+    pub fn sys_shmctl(&self, shmid: usize, cmd: usize, buf: UserRef<ShmidDs>) -> SysResult {
+        debug!("sys_shmctl @ shmid: {}, cmd: {}, buf: {}", shmid, cmd, buf);
+        let cmd = cmd & !IPC_64;
+        let needs_buf = matches!(
+            cmd,
+            IPC_STAT | IPC_SET | IPC_INFO | SHM_STAT | SHM_INFO | SHM_STAT_ANY
+        );
+        if needs_buf && buf.is_null() {
+            return Err(Errno::EFAULT);
+        }
+        match cmd {
+            IPC_INFO => {
+                UserRef::<ShmLimits>::from(buf.addr()).write(ShmLimits {
+                    shmmax: SHMMAX,
+                    shmmin: 1,
+                    shmmni: SHMMNI,
+                    shmseg: SHMMNI,
+                    shmall: SHMALL,
+                    ..Default::default()
+                });
+                return Ok(SHARED_MEMORY.lock().keys().max().cloned().unwrap_or(0));
+            }
+            SHM_INFO => {
+                let shms = SHARED_MEMORY.lock();
+                let pages = shms.values().map(|x| x.trackers.len()).sum();
+                UserRef::<ShmInfo>::from(buf.addr()).write(ShmInfo {
+                    used_ids: shms.len() as _,
+                    shm_tot: pages,
+                    shm_rss: pages,
+                    ..Default::default()
+                });
+                return Ok(shms.keys().max().cloned().unwrap_or(0));
+            }
+            _ => {}
+        }
+
+        let mem = SHARED_MEMORY
+            .lock()
+            .get(&shmid)
+            .cloned()
+            .ok_or(Errno::EINVAL)?;
+        let mut info = mem.info.lock();
+        match cmd {
+            // The shmid is the index of SHM_STAT.
+            IPC_STAT | SHM_STAT | SHM_STAT_ANY => {
+                if cmd != SHM_STAT_ANY {
+                    self.shm_access(&info.perm, 0o4)?;
+                }
+                buf.write(info.clone());
+                Ok(if cmd == IPC_STAT { 0 } else { shmid })
+            }
+            IPC_SET => {
+                self.shm_owner(&info.perm)?;
+                let new = buf.read();
+                info.perm.uid = new.perm.uid;
+                info.perm.gid = new.perm.gid;
+                info.perm.mode = (info.perm.mode & !0o777) | (new.perm.mode & 0o777);
+                info.ctime = current_time().as_secs() as _;
+                Ok(0)
+            }
+            // The segment stays until the last detach, but can't be found by the key.
+            IPC_RMID => {
+                self.shm_owner(&info.perm)?;
+                info.perm.mode |= SHM_DEST;
+                info.perm.key = IPC_PRIVATE as _;
+                info.ctime = current_time().as_secs() as _;
+                if info.nattch == 0 {
+                    drop(info);
+                    SHARED_MEMORY.lock().remove(&shmid);
+                }
+                Ok(0)
+            }
+            // The pages of the segments are never swapped out, only the mode is kept.
+            SHM_LOCK | SHM_UNLOCK => {
+                self.shm_owner(&info.perm)?;
+                match cmd == SHM_LOCK {
+                    true => info.perm.mode |= SHM_LOCKED,
+                    false => info.perm.mode &= !SHM_LOCKED,
+                }
+                Ok(0)
             }
-            return Ok(0);
+            _ => Err(Errno::EINVAL),
         }
-        Err(Errno::EPERM)
     }
 }
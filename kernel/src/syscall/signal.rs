@@ -1,6 +1,9 @@
 use super::SysResult;
-use crate::{tasks::WaitSignal, user::UserTaskContainer, utils::useref::UserRef};
-use executor::yield_now;
+use crate::{
+    tasks::{WaitSignal, WaitThreadEvent},
+    user::UserTaskContainer,
+    utils::useref::UserRef,
+};
 use libc_types::{
     internal::SigAction,
     signal::SignalNum,
@@ -108,7 +111,8 @@ impl UserTaskContainer {
             signal, act, oldact
         );
         if oldact.is_valid() {
-            oldact.write(self.task.pcb.lock().sigaction[sig].clone());
+            let sigaction = self.task.pcb.lock().sigaction[sig].clone();
+            oldact.write(sigaction);
         }
         if act.is_valid() {
             self.task.pcb.lock().sigaction[sig] = act.read();
@@ -125,7 +129,10 @@ impl UserTaskContainer {
                 break;
             }
             drop(tcb);
-            yield_now().await;
+            if self.check_thread_exit().is_some() {
+                break;
+            }
+            WaitThreadEvent::new(self.task.clone()).await;
         }
         debug!("sys_sigsuspend @ sigset: {:?}", signal);
         Ok(0)
@@ -141,7 +148,10 @@ impl UserTaskContainer {
                 break;
             }
             drop(tcb);
-            yield_now().await;
+            if self.check_thread_exit().is_some() {
+                break;
+            }
+            WaitThreadEvent::new(self.task.clone()).await;
         }
         Ok(0)
     }
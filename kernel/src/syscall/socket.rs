@@ -1,15 +1,17 @@
 use super::SysResult;
 use crate::socket::{self, NetType};
-use crate::user::socket_pair::create_socket_pair;
+use crate::tasks::WaitTickOrEvent;
+use crate::user::socket_pair::{create_socket_pair, SocketPair};
 use crate::user::UserTaskContainer;
 use crate::utils::useref::UserRef;
-use alloc::sync::Arc;
-use core::cmp;
+use alloc::{sync::Arc, vec::Vec};
 use core::net::{Ipv4Addr, SocketAddrV4};
+use core::{cmp, mem::size_of};
 use devices::get_net_device;
-use executor::yield_now;
 use fs::file::File;
 use libc_types::fcntl::OpenFlags;
+use libc_types::socket::{CmsgHdr, MsgHdr, MSG_CTRUNC, SCM_RIGHTS, SOL_SOCKET};
+use libc_types::types::IoVec;
 use log::{debug, warn};
 use lose_net_stack::connection::NetServer;
 use lose_net_stack::net_trait::NetInterface;
@@ -204,7 +206,7 @@ impl UserTaskContainer {
                 return Err(Errno::EINTR);
             }
 
-            yield_now().await;
+            WaitTickOrEvent::new(self.task.clone()).await;
         }
         Ok(fd)
     }
@@ -234,7 +236,7 @@ impl UserTaskContainer {
                 Err(NetServerError::Blocking) => {}
                 _ => break,
             }
-            yield_now().await;
+            WaitTickOrEvent::new(self.task.clone()).await;
         }
         Ok(0)
     }
@@ -268,7 +270,7 @@ impl UserTaskContainer {
                     if file.flags.lock().contains(OpenFlags::NONBLOCK) {
                         return Err(Errno::EAGAIN);
                     }
-                    yield_now().await
+                    WaitTickOrEvent::new(self.task.clone()).await
                 }
             }
         };
@@ -496,7 +498,128 @@ impl UserTaskContainer {
             } else if file.flags.lock().contains(OpenFlags::NONBLOCK) {
                 break Err(Errno::EAGAIN);
             }
-            yield_now().await;
+            WaitTickOrEvent::new(self.task.clone()).await;
         }
     }
+
+    /// Send the data of the iovecs, files in a SCM_RIGHTS message are passed to the
+    /// peer of a socketpair.
+    pub async fn sys_sendmsg(
+        &self,
+        socket_fd: usize,
+        msg_ptr: UserRef<MsgHdr>,
+        flags: usize,
+    ) -> SysResult {
+        debug!(
+            "[task {}] sys_sendmsg @ socket_fd: {:#x}, msg_ptr: {}, flags: {:#x}",
+            self.tid, socket_fd, msg_ptr, flags
+        );
+        let file = self.task.get_fd(socket_fd).ok_or(Errno::EBADF)?;
+        let msg = msg_ptr.read();
+
+        let mut rights = Vec::new();
+        let mut offset = 0;
+        while offset + size_of::<CmsgHdr>() <= msg.control_len {
+            let cmsg = UserRef::<CmsgHdr>::from(msg.control + offset).read();
+            if cmsg.len < size_of::<CmsgHdr>() || offset + cmsg.len > msg.control_len {
+                return Err(Errno::EINVAL);
+            }
+            if cmsg.level == SOL_SOCKET && cmsg.ty == SCM_RIGHTS {
+                let count = (cmsg.len - size_of::<CmsgHdr>()) / size_of::<i32>();
+                let fds = UserRef::<i32>::from(msg.control + offset + size_of::<CmsgHdr>())
+                    .slice_mut_with_len(count);
+                for fd in fds {
+                    rights.push(self.task.get_fd(*fd as _).ok_or(Errno::EBADF)?);
+                }
+            }
+            offset += CmsgHdr::align(cmsg.len);
+        }
+        let pair = file.get_bare_file().downcast_arc::<SocketPair>().ok();
+        if !rights.is_empty() && pair.is_none() {
+            return Err(Errno::EOPNOTSUPP);
+        }
+
+        let iov = UserRef::<IoVec>::from(msg.iov).slice_mut_with_len(msg.iov_len);
+        let mut buffer = Vec::new();
+        for io in iov.iter() {
+            buffer.extend_from_slice(UserRef::<u8>::from(io.base).slice_mut_with_len(io.len));
+        }
+        let wlen = file.async_write(&buffer).await?;
+        if let Some(pair) = pair
+            && !rights.is_empty()
+        {
+            pair.send_rights(rights);
+        }
+        Ok(wlen)
+    }
+
+    /// Receive data into the iovecs, files passed over a socketpair are installed as
+    /// new fds and reported in a SCM_RIGHTS message.
+    pub async fn sys_recvmsg(
+        &self,
+        socket_fd: usize,
+        msg_ptr: UserRef<MsgHdr>,
+        flags: usize,
+    ) -> SysResult {
+        debug!(
+            "[task {}] sys_recvmsg @ socket_fd: {:#x}, msg_ptr: {}, flags: {:#x}",
+            self.tid, socket_fd, msg_ptr, flags
+        );
+        let file = self.task.get_fd(socket_fd).ok_or(Errno::EBADF)?;
+        let msg = msg_ptr.read();
+        let iov = UserRef::<IoVec>::from(msg.iov).slice_mut_with_len(msg.iov_len);
+
+        let mut buffer = vec![0u8; iov.iter().map(|x| x.len).sum()];
+        let rlen = file.async_read(&mut buffer).await?;
+        let mut copied = 0;
+        for io in iov.iter() {
+            let len = cmp::min(io.len, rlen - copied);
+            UserRef::<u8>::from(io.base)
+                .slice_mut_with_len(len)
+                .copy_from_slice(&buffer[copied..copied + len]);
+            copied += len;
+        }
+
+        let mut control_len = 0;
+        let mut msg_flags = 0;
+        let rights = file
+            .get_bare_file()
+            .downcast_arc::<SocketPair>()
+            .ok()
+            .and_then(|pair| pair.recv_rights());
+        if let Some(rights) = rights {
+            // The files which don't fit in the control buffer are closed.
+            let space = msg.control_len.saturating_sub(size_of::<CmsgHdr>()) / size_of::<i32>();
+            if space < rights.len() {
+                msg_flags |= MSG_CTRUNC;
+            }
+            let mut fds = Vec::new();
+            for file in rights.into_iter().take(space) {
+                let Some(fd) = self.task.alloc_fd() else {
+                    msg_flags |= MSG_CTRUNC;
+                    break;
+                };
+                self.task.set_fd(fd, file);
+                fds.push(fd as i32);
+            }
+            if msg.control_len >= size_of::<CmsgHdr>() {
+                let len = size_of::<CmsgHdr>() + fds.len() * size_of::<i32>();
+                UserRef::<CmsgHdr>::from(msg.control).write(CmsgHdr {
+                    len,
+                    level: SOL_SOCKET,
+                    ty: SCM_RIGHTS,
+                });
+                UserRef::<i32>::from(msg.control + size_of::<CmsgHdr>())
+                    .slice_mut_with_len(fds.len())
+                    .copy_from_slice(&fds);
+                control_len = cmp::min(CmsgHdr::align(len), msg.control_len);
+            }
+        }
+        msg_ptr.with_mut(|msg| {
+            msg.name_len = 0;
+            msg.control_len = control_len;
+            msg.flags = msg_flags;
+        });
+        Ok(rlen)
+    }
 }
@@ -1,7 +1,15 @@
 use super::SysResult;
-use crate::{user::UserTaskContainer, utils::useref::UserRef};
-use libc_types::{resource::Rlimit, utsname::UTSname};
-use log::{debug, warn};
+use crate::{
+    user::UserTaskContainer,
+    utils::{random::fill_random, useref::UserRef},
+};
+use libc_types::{
+    others::PERSONALITY_QUERY,
+    resource::{Rlimit, RLIM_NLIMITS},
+    utsname::UTSname,
+};
+use log::debug;
+use syscalls::Errno;
 
 impl UserTaskContainer {
     pub fn sys_uname(&self, uts_ptr: UserRef<UTSname>) -> SysResult {
@@ -26,7 +34,6 @@ impl UserTaskContainer {
         Ok(0)
     }
 
-    /// TODO: FINISH sys_getrlimit
     pub fn sys_prlimit64(
         &self,
         pid: usize,
@@ -35,25 +42,30 @@ impl UserTaskContainer {
         old_limit: UserRef<Rlimit>,
     ) -> SysResult {
         debug!(
-            "sys_getrlimit @ pid: {}, resource: {}, new_limit: {}, old_limit: {}",
+            "sys_prlimit64 @ pid: {}, resource: {}, new_limit: {}, old_limit: {}",
             pid, resource, new_limit, old_limit
         );
-        match resource {
-            7 => {
-                if new_limit.is_valid() {
-                    let rlimit = new_limit.read();
-                    self.task.pcb.lock().rlimits[7] = rlimit.max;
-                }
-                if old_limit.is_valid() {
-                    old_limit.with_mut(|rlimit| {
-                        rlimit.max = self.task.inner_map(|inner| inner.rlimits[7]);
-                        rlimit.curr = rlimit.max;
-                    })
-                }
-            }
-            _ => {
-                warn!("need to finish prlimit64: resource {}", resource)
+        if resource >= RLIM_NLIMITS {
+            return Err(Errno::EINVAL);
+        }
+        let task = self.find_thread(pid)?;
+        let new_limit = new_limit.is_valid().then(|| new_limit.read());
+        if let Some(rlimit) = &new_limit
+            && rlimit.curr > rlimit.max
+        {
+            return Err(Errno::EINVAL);
+        }
+        // The user buffers are accessed without the pcb lock, a page fault on them takes it.
+        let old = {
+            let mut pcb = task.pcb.lock();
+            let old = pcb.rlimits[resource].clone();
+            if let Some(rlimit) = new_limit {
+                pcb.rlimits[resource] = rlimit;
             }
+            old
+        };
+        if old_limit.is_valid() {
+            old_limit.write(old);
         }
         Ok(0)
     }
@@ -102,40 +114,29 @@ impl UserTaskContainer {
         Ok(0)
     }
 
-    pub fn sys_sched_getparam(&self, pid: usize, param: usize) -> SysResult {
-        debug!("sys_sched_getparam @ pid: {} param: {}", pid, param);
-
-        Ok(0)
-    }
-
-    pub fn sys_sched_setscheduler(&self, pid: usize, _policy: usize, param: usize) -> SysResult {
-        debug!("sys_sched_setscheduler @ pid: {} param: {}", pid, param);
-
-        Ok(0)
-    }
-
     pub fn sys_getrandom(&self, buf: UserRef<u8>, buf_len: usize, flags: usize) -> SysResult {
         debug!(
             "sys_getrandom @ buf: {}, buf_len: {:#x}, flags: {:#x}",
             buf, buf_len, flags
         );
-        let buf = buf.slice_mut_with_len(buf_len);
-        static mut SEED: u64 = 0xdead_beef_cafe_babe;
-        for x in buf.iter_mut() {
-            unsafe {
-                // from musl
-                SEED = SEED.wrapping_mul(0x5851_f42d_4c95_7f2d);
-                *x = (SEED >> 33) as u8;
-            }
-        }
+        fill_random(buf.slice_mut_with_len(buf_len));
         Ok(buf_len)
     }
 
+    pub fn sys_personality(&self, persona: usize) -> SysResult {
+        debug!("sys_personality @ persona: {:#x}", persona);
+        let mut pcb = self.task.pcb.lock();
+        let old = pcb.personality;
+        if persona as u32 != PERSONALITY_QUERY {
+            pcb.personality = persona as u32;
+        }
+        Ok(old as usize)
+    }
+
     #[cfg(target_arch = "x86_64")]
     pub fn sys_arch_prctl(&self, code: usize, addr: usize) -> SysResult {
         use libc_types::others::ArchPrctlCmd;
         use polyhal_trap::trapframe::TrapFrameArgs;
-        use syscalls::Errno;
 
         let arch_prctl_code = ArchPrctlCmd::try_from(code).map_err(|_| Errno::EINVAL)?;
         debug!(
@@ -1,7 +1,10 @@
 use super::SysResult;
 use crate::{
-    syscall::{time::WaitUntilsec, types::signal::SignalUserContext},
-    tasks::{exec::exec_with_process, futex_requeue, futex_wake, UserTask, WaitFutex, WaitPid},
+    syscall::types::signal::SignalUserContext,
+    tasks::{
+        exec::exec_with_process, futex_requeue, futex_wake, user_processes, UserTask, WaitFutex,
+        WaitPid,
+    },
     user::{entry::user_entry, UserTaskContainer},
     utils::useref::UserRef,
 };
@@ -11,18 +14,25 @@ use alloc::{
     sync::Weak,
     vec::Vec,
 };
-use core::cmp;
-use executor::{select, thread, tid2task, yield_now, AsyncTask};
+use core::{cmp, mem::size_of, sync::atomic::Ordering};
+use devices::PAGE_SIZE;
+use executor::{
+    cpu_count, current_cpu,
+    sched::{MAX_NICE, MIN_NICE},
+    select, thread, tid2task,
+    timer::Sleep,
+    yield_now, AsyncTask, SchedPolicy,
+};
 use libc_types::{
     fcntl::{OpenFlags, AT_FDCWD},
     futex::FutexFlags,
-    resource::Rusage,
+    resource::{Rusage, PRIO_PROCESS, RLIMIT_NPROC, RUSAGE_CHILDREN, RUSAGE_SELF, RUSAGE_THREAD},
     sched::CloneFlags,
     signal::SignalNum,
     types::{TimeSpec, TimeVal},
 };
 use log::{debug, warn};
-use polyhal::timer::{current_time, get_freq};
+use polyhal::timer::get_freq;
 use polyhal_trap::trapframe::TrapFrameArgs;
 use syscalls::Errno;
 
@@ -127,6 +137,14 @@ impl UserTaskContainer {
             self.tid, flags, stack, ptid, tls, ctid
         );
 
+        // A new process is refused past RLIMIT_NPROC, the threads are not counted.
+        if !flags.contains(CloneFlags::CLONE_THREAD) {
+            let limit = self.task.pcb.lock().rlimits[RLIMIT_NPROC].curr;
+            if user_processes(true).map_or(0, |x| x.len()) >= limit {
+                return Err(Errno::EAGAIN);
+            }
+        }
+
         let new_task = match flags.contains(CloneFlags::CLONE_THREAD) {
             true => self.task.clone().thread_clone(),
             // false => curr_task.clone().fork(user_entry()),
@@ -207,12 +225,7 @@ impl UserTaskContainer {
                 child_task.task_id, self.task.task_id
             );
             // release the task resources
-            self.task
-                .pcb
-                .lock()
-                .children
-                .retain(|x| x.task_id != child_task.task_id);
-            child_task.release();
+            self.task.reap_child(&child_task);
             debug!("wait pid: {}", child_task.exit_code().unwrap());
 
             if status.is_valid() {
@@ -233,12 +246,7 @@ impl UserTaskContainer {
                 Some(t1) => {
                     let child_task = child_task.unwrap();
                     // Release task.
-                    self.task
-                        .pcb
-                        .lock()
-                        .children
-                        .retain(|x| x.task_id != child_task.task_id);
-                    child_task.release();
+                    self.task.reap_child(&child_task);
                     if status.is_valid() {
                         status.write((t1 as i32) << 8);
                     }
@@ -341,9 +349,16 @@ impl UserTaskContainer {
                     let wait_func = WaitFutex(futex_table.clone(), self.tid);
                     if value2 != 0 {
                         let timeout = UserRef::<TimeSpec>::from(value2).read().into();
-                        match select(wait_func, WaitUntilsec(current_time() + timeout)).await {
+                        match select(wait_func, Sleep::new(timeout)).await {
                             executor::Either::Left((res, _)) => res,
-                            executor::Either::Right(_) => Err(Errno::ETIMEDOUT),
+                            executor::Either::Right(_) => {
+                                // Leave the wait queue, a later wake must not count this thread.
+                                futex_table
+                                    .lock()
+                                    .values_mut()
+                                    .for_each(|x| x.retain(|tid| *tid != self.tid));
+                                Err(Errno::ETIMEDOUT)
+                            }
                         }
                     } else {
                         wait_func.await
@@ -403,6 +418,8 @@ impl UserTaskContainer {
                         child_tcb.signal_queue[index] += 1;
                     }
                 }
+                drop(child_tcb);
+                child_task.notify();
                 // let signal = child
                 //     .upgrade().unwrap()
                 //     .tcb
@@ -428,19 +445,29 @@ impl UserTaskContainer {
 
     pub fn sys_getrusage(&self, who: usize, usage_ptr: UserRef<Rusage>) -> SysResult {
         debug!("sys_getrusgae @ who: {}, usage_ptr: {}", who, usage_ptr);
-        // let Rusage
-        let tms = self.task.inner_map(|inner| inner.tms);
+        let (utime, stime, max_rss) = match who as isize {
+            RUSAGE_SELF | RUSAGE_THREAD => self.task.inner_map(|inner| {
+                inner.sample_peak();
+                (inner.tms.utime, inner.tms.stime, inner.peak_rss)
+            }),
+            // The times of the reaped children, accumulated by reap_child.
+            RUSAGE_CHILDREN => self
+                .task
+                .inner_map(|inner| (inner.tms.cutime, inner.tms.cstime, inner.children_peak_rss)),
+            _ => return Err(Errno::EINVAL),
+        };
         let freq = get_freq();
 
         usage_ptr.with_mut(|rusage| {
             rusage.stime = TimeVal {
-                sec: (tms.stime % freq) as _,
-                usec: ((tms.stime % freq) * 1000_000 / freq) as _,
+                sec: (stime / freq) as _,
+                usec: ((stime % freq) * 1000_000 / freq) as _,
             };
             rusage.utime = TimeVal {
-                sec: (tms.utime % freq) as _,
-                usec: ((tms.utime % freq) * 1000_000 / freq) as _,
+                sec: (utime / freq) as _,
+                usec: ((utime % freq) * 1000_000 / freq) as _,
             };
+            rusage.maxrss = (max_rss * PAGE_SIZE / 1024) as _;
         });
         Ok(0)
     }
@@ -469,6 +496,7 @@ impl UserTaskContainer {
         }?;
 
         user_task.tcb.write().signal.insert(signal);
+        user_task.notify();
         yield_now().await;
 
         Ok(0)
@@ -485,17 +513,191 @@ impl UserTaskContainer {
         Ok(0)
     }
 
+    /// Find the thread by the tid, 0 means the current thread.
+    pub(super) fn find_thread(&self, tid: usize) -> Result<Arc<UserTask>, Errno> {
+        match tid {
+            0 => Ok(self.task.clone()),
+            _ => tid2task(tid)
+                .ok_or(Errno::ESRCH)?
+                .downcast_arc::<UserTask>()
+                .map_err(|_| Errno::ESRCH),
+        }
+    }
+
     pub fn sys_sched_getaffinity(
         &self,
         pid: usize,
         cpu_set_size: usize,
-        mask: UserRef<u8>,
+        mask: UserRef<usize>,
     ) -> SysResult {
         debug!(
             "[task {}] sys_sched_getaffinity @ pid: {}  cpu_set_size: {}, mask: {:#x?}",
             self.tid, pid, cpu_set_size, mask
         );
-        // TODO:
+        if cpu_set_size < size_of::<usize>() {
+            return Err(Errno::EINVAL);
+        }
+        let task = self.find_thread(pid)?;
+        mask.write(task.cpu_mask() & online_cpu_mask());
+        Ok(size_of::<usize>())
+    }
+
+    pub async fn sys_sched_setaffinity(
+        &self,
+        pid: usize,
+        cpu_set_size: usize,
+        mask: UserRef<usize>,
+    ) -> SysResult {
+        debug!(
+            "[task {}] sys_sched_setaffinity @ pid: {}  cpu_set_size: {}, mask: {:#x?}",
+            self.tid, pid, cpu_set_size, mask
+        );
+        // The cpu set smaller than usize only contains the low bytes.
+        let len = cmp::min(cpu_set_size, size_of::<usize>());
+        let mut bytes = [0u8; size_of::<usize>()];
+        bytes[..len].copy_from_slice(UserRef::<u8>::from(mask.addr()).slice_mut_with_len(len));
+        let cpu_mask = usize::from_le_bytes(bytes) & online_cpu_mask();
+        if cpu_mask == 0 {
+            return Err(Errno::EINVAL);
+        }
+        let task = self.find_thread(pid)?;
+        task.cpu_mask.store(cpu_mask, Ordering::Relaxed);
+        // Move to an allowed core if the current one is excluded.
+        if task.task_id == self.tid && cpu_mask & (1 << current_cpu()) == 0 {
+            yield_now().await;
+        }
+        Ok(0)
+    }
+
+    pub fn sys_getcpu(&self, cpu: UserRef<u32>, node: UserRef<u32>) -> SysResult {
+        debug!(
+            "[task {}] sys_getcpu @ cpu: {}, node: {}",
+            self.tid, cpu, node
+        );
+        if cpu.is_valid() {
+            cpu.write(current_cpu() as _);
+        }
+        if node.is_valid() {
+            node.write(0);
+        }
+        Ok(0)
+    }
+
+    /// Set the scheduling policy and the real-time priority of a thread.
+    /// param points to a `struct sched_param` which only contains the priority.
+    pub async fn sys_sched_setscheduler(
+        &self,
+        pid: usize,
+        policy: usize,
+        param: UserRef<i32>,
+    ) -> SysResult {
+        debug!(
+            "[task {}] sys_sched_setscheduler @ pid: {} policy: {} param: {}",
+            self.tid, pid, policy, param
+        );
+        let policy = SchedPolicy::from_usize(policy).ok_or(Errno::EINVAL)?;
+        self.set_sched_param(pid, Some(policy), param).await
+    }
+
+    pub async fn sys_sched_setparam(&self, pid: usize, param: UserRef<i32>) -> SysResult {
+        debug!(
+            "[task {}] sys_sched_setparam @ pid: {} param: {}",
+            self.tid, pid, param
+        );
+        self.set_sched_param(pid, None, param).await
+    }
+
+    async fn set_sched_param(
+        &self,
+        pid: usize,
+        policy: Option<SchedPolicy>,
+        param: UserRef<i32>,
+    ) -> SysResult {
+        if !param.is_valid() {
+            return Err(Errno::EINVAL);
+        }
+        let task = self.find_thread(pid)?;
+        let priority = param.read();
+        {
+            let mut sched = task.sched.lock();
+            let policy = policy.unwrap_or(sched.policy);
+            let (min, max) = policy.priority_range();
+            if priority < min as i32 || priority > max as i32 {
+                return Err(Errno::EINVAL);
+            }
+            sched.policy = policy;
+            sched.priority = priority as _;
+        }
+        // Requeue the current thread so that the new class takes effect now.
+        if task.task_id == self.tid {
+            yield_now().await;
+        }
+        Ok(0)
+    }
+
+    pub fn sys_sched_getscheduler(&self, pid: usize) -> SysResult {
+        debug!("[task {}] sys_sched_getscheduler @ pid: {}", self.tid, pid);
+        Ok(self.find_thread(pid)?.sched_attr().policy as _)
+    }
+
+    pub fn sys_sched_getparam(&self, pid: usize, param: UserRef<i32>) -> SysResult {
+        debug!(
+            "[task {}] sys_sched_getparam @ pid: {} param: {}",
+            self.tid, pid, param
+        );
+        if !param.is_valid() {
+            return Err(Errno::EINVAL);
+        }
+        let priority = self.find_thread(pid)?.sched_attr().priority;
+        param.write(priority as _);
+        Ok(0)
+    }
+
+    pub fn sys_sched_get_priority_max(&self, policy: usize) -> SysResult {
+        debug!("sys_sched_get_priority_max @ policy: {}", policy);
+        let policy = SchedPolicy::from_usize(policy).ok_or(Errno::EINVAL)?;
+        Ok(policy.priority_range().1 as _)
+    }
+
+    pub fn sys_sched_get_priority_min(&self, policy: usize) -> SysResult {
+        debug!("sys_sched_get_priority_min @ policy: {}", policy);
+        let policy = SchedPolicy::from_usize(policy).ok_or(Errno::EINVAL)?;
+        Ok(policy.priority_range().0 as _)
+    }
+
+    /// Set the nice value of a thread, only `PRIO_PROCESS` is supported.
+    pub fn sys_setpriority(&self, which: usize, who: usize, prio: i32) -> SysResult {
+        debug!(
+            "[task {}] sys_setpriority @ which: {} who: {} prio: {}",
+            self.tid, which, who, prio
+        );
+        if which != PRIO_PROCESS {
+            return Err(Errno::EINVAL);
+        }
+        let task = self.find_thread(who)?;
+        task.sched.lock().nice = prio.clamp(MIN_NICE as _, MAX_NICE as _) as _;
         Ok(0)
     }
+
+    /// Get the nice value of a thread.
+    /// The syscall returns `20 - nice` to avoid negative values, libc converts it back.
+    pub fn sys_getpriority(&self, which: usize, who: usize) -> SysResult {
+        debug!(
+            "[task {}] sys_getpriority @ which: {} who: {}",
+            self.tid, which, who
+        );
+        if which != PRIO_PROCESS {
+            return Err(Errno::EINVAL);
+        }
+        let nice = self.find_thread(who)?.sched_attr().nice;
+        Ok((20 - nice as isize) as _)
+    }
+}
+
+/// The mask of all cores which run the executor.
+fn online_cpu_mask() -> usize {
+    match cpu_count() >= usize::BITS as usize {
+        true => usize::MAX,
+        false => (1 << cpu_count()) - 1,
+    }
 }
@@ -1,13 +1,7 @@
 use super::SysResult;
 use crate::{tasks::WaitHandleAbleSignal, user::UserTaskContainer, utils::useref::UserRef};
-use core::{
-    future::Future,
-    ops::Add,
-    pin::Pin,
-    task::{Context, Poll},
-    time::Duration,
-};
-use executor::select;
+use core::{ops::Add, time::Duration};
+use executor::{select, timer::Sleep};
 use libc_types::{
     time::ITimerVal,
     times::TMS,
@@ -39,9 +33,10 @@ impl UserTaskContainer {
         let req: Duration = req_ptr.read().into();
         debug!("nano sleep {} nseconds", req.as_nanos());
 
+        let deadline = current_time() + req;
         let res = match select(
             WaitHandleAbleSignal(self.task.clone()),
-            WaitUntilsec(current_time() + req),
+            Sleep::until(deadline),
         )
         .await
         {
@@ -49,14 +44,15 @@ impl UserTaskContainer {
             executor::Either::Left(_) => Err(Errno::EINTR),
         };
         if rem_ptr.is_valid() {
-            rem_ptr.write(Default::default());
+            rem_ptr.write(deadline.saturating_sub(current_time()).into());
         }
         res
     }
 
     pub fn sys_times(&self, tms_ptr: UserRef<TMS>) -> SysResult {
         debug!("sys_times @ tms: {}", tms_ptr);
-        self.task.inner_map(|x| tms_ptr.write(x.tms));
+        let tms = self.task.inner_map(|x| x.tms);
+        tms_ptr.write(tms);
         Ok(get_ticks() as _)
     }
 
@@ -104,14 +100,13 @@ impl UserTaskContainer {
         );
 
         if which == 0 {
+            // Don't access the user memory with the pcb lock held.
+            let new_timer = times_ptr.is_valid().then(|| times_ptr.read());
             let mut pcb = self.task.pcb.lock();
-            if old_timer_ptr.is_valid() {
-                old_timer_ptr.write(pcb.timer[0].timer);
-            }
+            let old_timer = pcb.timer[0].timer;
 
-            if times_ptr.is_valid() {
+            if let Some(new_timer) = new_timer {
                 let current_timval: TimeVal = current_time().into();
-                let new_timer = times_ptr.read();
                 pcb.timer[0].timer = new_timer;
                 pcb.timer[0].next = current_timval.add(pcb.timer[0].timer.value);
                 if new_timer.value.sec == 0 && new_timer.value.usec == 0 {
@@ -119,6 +114,10 @@ impl UserTaskContainer {
                     pcb.timer[0].last = Default::default();
                 }
             }
+            drop(pcb);
+            if old_timer_ptr.is_valid() {
+                old_timer_ptr.write(old_timer);
+            }
             Ok(0)
         } else {
             Err(Errno::EPERM)
@@ -139,35 +138,15 @@ impl UserTaskContainer {
 
         let interval = req_ptr.read().into();
         if flags == 1 {
-            WaitUntilsec(interval).await;
+            Sleep::until(interval).await;
             if rem_ptr.is_valid() {
                 rem_ptr.write(Default::default());
             }
         } else {
             debug!("nano sleep {} nseconds", interval.as_nanos());
-            WaitUntilsec(current_time() + interval).await;
+            Sleep::new(interval).await;
         }
 
         Ok(0)
     }
 }
-
-pub struct WaitUntilsec(pub Duration);
-
-impl Future for WaitUntilsec {
-    type Output = ();
-
-    fn poll(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Self::Output> {
-        let ns = current_time();
-
-        match ns > self.0 {
-            true => Poll::Ready(()),
-            false => Poll::Pending,
-        }
-    }
-}
-
-#[allow(dead_code)]
-pub fn wait_ms(ms: usize) -> WaitUntilsec {
-    WaitUntilsec(current_time() + Duration::from_millis(ms as _))
-}
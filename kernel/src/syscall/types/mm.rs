@@ -6,8 +6,9 @@ pub fn map_mprot_to_flags(mprot: MmapProt) -> MappingFlags {
     if mprot.contains(MmapProt::READ) {
         res |= MappingFlags::R;
     }
+    // Write-only pages are not supported by riscv, writable pages are always readable.
     if mprot.contains(MmapProt::WRITE) {
-        res |= MappingFlags::W;
+        res |= MappingFlags::R | MappingFlags::W;
     }
     if mprot.contains(MmapProt::EXEC) {
         res |= MappingFlags::X;
@@ -1,7 +1,7 @@
-use core::{cmp, future::Future, pin::Pin, task::Poll};
+use core::{cmp, future::Future, pin::Pin, task::Poll, time::Duration};
 
 use alloc::{sync::Arc, vec::Vec};
-use executor::AsyncTask;
+use executor::{tid2task, timer::Sleep, wake_on_next_tick, wake_task, AsyncTask};
 use sync::Mutex;
 use syscalls::Errno;
 
@@ -10,12 +10,17 @@ use super::{
     task::{FutexTable, UserTask},
 };
 
+/// Wait until a child of the process exits.
+///
+/// The wakers are registered before the children are checked, a child exiting after
+/// the check wakes the task up.
 pub struct WaitPid(pub Arc<UserTask>, pub isize);
 
 impl Future for WaitPid {
     type Output = Result<Arc<UserTask>, Errno>;
 
-    fn poll(self: Pin<&mut Self>, _cx: &mut core::task::Context<'_>) -> Poll<Self::Output> {
+    fn poll(self: Pin<&mut Self>, cx: &mut core::task::Context<'_>) -> Poll<Self::Output> {
+        self.0.child_exit.register(cx.waker());
         let inner = self.0.pcb.lock();
         let res = inner
             .children
@@ -30,12 +35,14 @@ impl Future for WaitPid {
     }
 }
 
+/// Wait until the thread has a signal, including the blocked ones.
 pub struct WaitSignal(pub Arc<UserTask>);
 
 impl Future for WaitSignal {
     type Output = ();
 
-    fn poll(self: Pin<&mut Self>, _cx: &mut core::task::Context<'_>) -> Poll<Self::Output> {
+    fn poll(self: Pin<&mut Self>, cx: &mut core::task::Context<'_>) -> Poll<Self::Output> {
+        self.0.event.register(cx.waker());
         match self.0.tcb.read().signal.is_empty(None) {
             false => Poll::Ready(()),
             true => Poll::Pending,
@@ -43,6 +50,54 @@ impl Future for WaitSignal {
     }
 }
 
+/// Wait until the thread has a signal to handle, it was exited or the interval
+/// timer expires.
+///
+/// The task is woken up by the signal sender, the exiting thread or the timer.
+pub struct WaitThreadEvent {
+    task: Arc<UserTask>,
+    itimer: Option<Sleep>,
+}
+
+impl WaitThreadEvent {
+    pub fn new(task: Arc<UserTask>) -> Self {
+        Self { task, itimer: None }
+    }
+}
+
+impl Future for WaitThreadEvent {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut core::task::Context<'_>) -> Poll<Self::Output> {
+        let task = &self.task;
+        task.event.register(cx.waker());
+        let tcb = task.tcb.read();
+        let has_signal = !tcb.signal.is_empty(Some(tcb.sigmask));
+        let exited = tcb.thread_exit_code.is_some();
+        drop(tcb);
+        if has_signal || exited || task.exit_code().is_some() {
+            return Poll::Ready(());
+        }
+        let timer = task.pcb.lock().timer[0];
+        if timer.next <= timer.last {
+            self.itimer = None;
+            return Poll::Pending;
+        }
+        // The interval timer may be changed by setitimer.
+        let deadline: Duration = timer.next.into();
+        if self.itimer.as_ref().map(Sleep::deadline) != Some(deadline) {
+            self.itimer = Some(Sleep::until(deadline));
+        }
+        match Pin::new(self.itimer.as_mut().unwrap()).poll(cx) {
+            Poll::Ready(()) => {
+                self.itimer = None;
+                Poll::Ready(())
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
 pub fn in_futex(futex_table: Arc<Mutex<FutexTable>>, task_id: usize) -> bool {
     let futex_table = futex_table.lock();
     futex_table
@@ -51,13 +106,16 @@ pub fn in_futex(futex_table: Arc<Mutex<FutexTable>>, task_id: usize) -> bool {
         .is_some()
 }
 
+/// Wait until the thread is woken up by futex_wake or it has a signal.
 pub struct WaitFutex(pub Arc<Mutex<FutexTable>>, pub usize);
 
 impl Future for WaitFutex {
     type Output = Result<usize, Errno>;
 
-    fn poll(self: Pin<&mut Self>, _cx: &mut core::task::Context<'_>) -> Poll<Self::Output> {
-        let signal = current_user_task().tcb.read().signal.clone();
+    fn poll(self: Pin<&mut Self>, cx: &mut core::task::Context<'_>) -> Poll<Self::Output> {
+        let task = current_user_task();
+        task.event.register(cx.waker());
+        let signal = task.tcb.read().signal.clone();
         match in_futex(self.0.clone(), self.1) {
             true => {
                 if !signal.is_empty(None) {
@@ -76,13 +134,15 @@ impl Future for WaitFutex {
     }
 }
 
+/// Wait until the thread has a signal which is not blocked.
 pub struct WaitHandleAbleSignal(pub Arc<UserTask>);
 
 impl Future for WaitHandleAbleSignal {
     type Output = ();
 
-    fn poll(self: Pin<&mut Self>, _cx: &mut core::task::Context<'_>) -> Poll<Self::Output> {
+    fn poll(self: Pin<&mut Self>, cx: &mut core::task::Context<'_>) -> Poll<Self::Output> {
         let task = &self.0;
+        task.event.register(cx.waker());
         let sig_mask = task.tcb.read().sigmask;
         let has_signal = !task.tcb.read().signal.is_empty(Some(sig_mask));
 
@@ -93,6 +153,43 @@ impl Future for WaitHandleAbleSignal {
     }
 }
 
+/// Park the thread until the next timer tick or until it is notified.
+///
+/// This is used to poll the sources which can't wake up their waiters, the network
+/// stack and the files of poll, select and epoll have no readiness notification. A
+/// signal wakes the thread up at once.
+pub struct WaitTickOrEvent(Arc<UserTask>, bool);
+
+impl WaitTickOrEvent {
+    pub fn new(task: Arc<UserTask>) -> Self {
+        Self(task, false)
+    }
+}
+
+impl Future for WaitTickOrEvent {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut core::task::Context<'_>) -> Poll<Self::Output> {
+        match self.1 {
+            true => Poll::Ready(()),
+            false => {
+                self.1 = true;
+                self.0.event.register(cx.waker());
+                wake_on_next_tick(cx.waker());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Wake up the thread waiting for a futex.
+fn futex_notify(task_id: usize) {
+    match tid2task(task_id).and_then(|x| x.downcast_arc::<UserTask>().ok()) {
+        Some(task) => task.notify(),
+        None => wake_task(task_id),
+    }
+}
+
 pub fn futex_wake(futex_table: Arc<Mutex<FutexTable>>, uaddr: usize, wake_count: usize) -> usize {
     let mut futex_table = futex_table.lock();
     let que_size = futex_table.get_mut(&uaddr).map(|x| x.len()).unwrap_or(0);
@@ -103,7 +200,7 @@ pub fn futex_wake(futex_table: Arc<Mutex<FutexTable>>, uaddr: usize, wake_count:
             .get_mut(&uaddr)
             .map(|x| x.drain(..cmp::min(wake_count as usize, que_size)));
 
-        que.map(|x| x.count()).unwrap_or(0)
+        que.map(|x| x.map(futex_notify).count()).unwrap_or(0)
     }
 }
 
@@ -118,7 +215,7 @@ pub fn futex_requeue(
 
     let waked_size = futex_table
         .get_mut(&uaddr)
-        .map(|x| x.drain(..wake_count as usize).count())
+        .map(|x| x.drain(..wake_count as usize).map(futex_notify).count())
         .unwrap_or(0);
 
     let reque: Option<Vec<_>> = futex_table
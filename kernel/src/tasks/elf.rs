@@ -1,16 +1,9 @@
 use log::warn;
 use syscalls::Errno;
-use xmas_elf::{
-    program::Type,
-    sections::SectionData,
-    symbol_table::{DynEntry64, Entry},
-    ElfFile,
-};
+use xmas_elf::{program::Type, ElfFile};
 
 pub trait ElfExtra {
     fn get_ph_addr(&self) -> Result<u64, Errno>;
-    fn dynsym(&self) -> Result<&[DynEntry64], &'static str>;
-    fn relocate(&self, base: usize) -> Result<usize, &str>;
 }
 
 impl ElfExtra for ElfFile<'_> {
@@ -33,54 +26,4 @@ impl ElfExtra for ElfFile<'_> {
             Err(Errno::EBADF)
         }
     }
-
-    fn dynsym(&self) -> Result<&[DynEntry64], &'static str> {
-        match self
-            .find_section_by_name(".dynsym")
-            .ok_or(".dynsym not found")?
-            .get_data(self)
-            .map_err(|_| "corrupted .dynsym")?
-        {
-            SectionData::DynSymbolTable64(dsym) => Ok(dsym),
-            _ => Err("bad .dynsym"),
-        }
-    }
-
-    fn relocate(&self, base: usize) -> Result<usize, &str> {
-        let data = self
-            .find_section_by_name(".rela.dyn")
-            .ok_or(".rela.dyn not found")?
-            .get_data(self)
-            .map_err(|_| "corrupted .rela.dyn")?;
-        let entries = match data {
-            SectionData::Rela64(entries) => entries,
-            _ => return Err("bad .rela.dyn"),
-        };
-        let dynsym = self.dynsym()?;
-        for entry in entries.iter() {
-            const REL_GOT: u32 = 6;
-            const REL_PLT: u32 = 7;
-            const REL_RELATIVE: u32 = 8;
-            const R_RISCV_64: u32 = 2;
-            const R_RISCV_RELATIVE: u32 = 3;
-            const R_AARCH64_RELATIVE: u32 = 0x403;
-            const R_AARCH64_GLOBAL_DATA: u32 = 0x401;
-
-            match entry.get_type() {
-                REL_GOT | REL_PLT | R_RISCV_64 | R_AARCH64_GLOBAL_DATA => {
-                    let dynsym = &dynsym[entry.get_symbol_table_index() as usize];
-                    if dynsym.shndx() == 0 {
-                        let name = dynsym.get_name(self)?;
-                        panic!("need to find symbol: {:?}", name);
-                    } else {
-                        base + dynsym.value() as usize
-                    };
-                }
-                REL_RELATIVE | R_RISCV_RELATIVE | R_AARCH64_RELATIVE => {}
-                t => unimplemented!("unknown type: {}", t),
-            }
-        }
-        // panic!("STOP");
-        Ok(base)
-    }
 }
@@ -1,19 +1,59 @@
-use super::{stack::init_task_stack, UserTask};
+use super::{
+    stack::{init_stack_size, init_task_stack},
+    UserTask,
+};
 use crate::{
-    consts::USER_DYN_ADDR,
-    tasks::{elf::ElfExtra, MemType},
+    consts::{
+        USER_BRK_RANDOM_RANGE, USER_DYN_ADDR, USER_INTERP_ADDR, USER_LOAD_RANDOM_RANGE,
+        USER_MMAP_ADDR, USER_MMAP_RANDOM_RANGE, USER_STACK_INIT_SIZE, USER_STACK_RANDOM_RANGE,
+        USER_STACK_TOP,
+    },
+    tasks::{elf::ElfExtra, MapTrack, MemArea, MemType, PageCache},
+    utils::random::random_u64,
 };
 use alloc::{
     string::{String, ToString},
     sync::Arc,
     vec::Vec,
 };
-use core::ops::{Add, Mul};
-use devices::{frame_alloc_much, PAGE_SIZE};
-use fs::{file::File, pathbuf::PathBuf};
-use libc_types::fcntl::OpenFlags;
+use core::{cmp::max, ffi::CStr, mem::size_of, ops::Mul};
+use devices::PAGE_SIZE;
+use fs::{file::File, pathbuf::PathBuf, FileType};
+use libc_types::{
+    fcntl::OpenFlags,
+    mman::MlockAllFlags,
+    others::{ADDR_NO_RANDOMIZE, PATH_MAX},
+    types::{Stat, StatMode},
+};
+use polyhal::MappingFlags;
+use runtime::frame::{frame_alloc, frame_alloc_much};
 use syscalls::Errno;
-use xmas_elf::program::{SegmentData, Type};
+use xmas_elf::{
+    header,
+    program::{Flags, ProgramHeader, ProgramHeader64, Type},
+    ElfFile,
+};
+
+/// Convert the flags of an ELF segment to the protection of its memory area.
+fn segment_prot(flags: Flags) -> MappingFlags {
+    let mut prot = MappingFlags::empty();
+    if flags.is_read() {
+        prot |= MappingFlags::R;
+    }
+    if flags.is_write() {
+        prot |= MappingFlags::W;
+    }
+    if flags.is_execute() {
+        prot |= MappingFlags::X;
+    }
+    prot
+}
+
+/// The most levels of nested script interpreters, the same as linux.
+const MAX_INTERP_DEPTH: usize = 4;
+
+/// The most bytes of the `#!` line, the same as linux.
+const BINPRM_BUF_SIZE: usize = 256;
 
 pub fn exec_with_process(
     task: Arc<UserTask>,
@@ -22,103 +62,328 @@ pub fn exec_with_process(
     args: Vec<String>,
     envp: Vec<String>,
 ) -> Result<Arc<UserTask>, Errno> {
-    // copy args, avoid free before pushing.
-    // let path = String::from(path);
-    let path = curr_dir.join(&path);
+    exec_with_depth(task, curr_dir, path, args, envp, 0)
+}
 
-    let user_task = task.clone();
-    user_task.pcb.lock().memset.clear();
-    user_task.page_table.restore();
-    user_task.page_table.change();
+fn exec_with_depth(
+    task: Arc<UserTask>,
+    curr_dir: PathBuf,
+    filename: String,
+    args: Vec<String>,
+    envp: Vec<String>,
+    depth: usize,
+) -> Result<Arc<UserTask>, Errno> {
+    let path = curr_dir.join(&filename);
 
     // TODO: 运行程序的时候，判断当前的路径
-    let file = File::open_link(path.clone(), OpenFlags::RDONLY)
-        .map(Arc::new)?
-        .clone();
-    let file_size = file.file_size()?;
-    let frame_ppn = frame_alloc_much(file_size.div_ceil(PAGE_SIZE));
-    let buffer = frame_ppn.as_ref().unwrap()[0].slice_mut_with_len(file_size);
-    let rsize = file.readat(0, buffer)?;
-    assert_eq!(rsize, file_size);
-    // flush_dcache_range();
+    let file = File::open_link(path.clone(), OpenFlags::RDONLY).map(Arc::new)?;
+    if file.file_type()? != FileType::File || !is_executable(&file)? {
+        return Err(Errno::EACCES);
+    }
     // 读取elf信息
-    let elf = if let Ok(elf) = xmas_elf::ElfFile::new(&buffer) {
-        elf
-    } else {
-        let mut new_args = vec!["busybox".to_string(), "sh".to_string()];
-        args.iter().for_each(|x| new_args.push(x.clone()));
-        return exec_with_process(task, curr_dir, String::from("busybox"), new_args, envp);
+    let Some(buffer) = read_elf_headers(&file)? else {
+        // Run the script by the interpreter in its #! line, the script path is
+        // inserted before the original arguments.
+        let (interp, arg) = read_shebang(&file)?.ok_or(Errno::ENOEXEC)?;
+        if depth >= MAX_INTERP_DEPTH {
+            return Err(Errno::ELOOP);
+        }
+        let mut new_args = vec![interp.clone()];
+        new_args.extend(arg);
+        new_args.push(filename);
+        new_args.extend(args.into_iter().skip(1));
+        return exec_with_depth(task, curr_dir, interp, new_args, envp, depth + 1);
     };
+    let elf = ElfFile::new(&buffer).map_err(|_| Errno::ENOEXEC)?;
     let elf_header = elf.header;
-
     let entry_point = elf.header.pt2.entry_point() as usize;
-    // this assert ensures that the file is elf file.
-    assert_eq!(
-        elf_header.pt1.magic,
-        [0x7f, 0x45, 0x4c, 0x46],
-        "invalid elf!"
-    );
-    // WARRNING: this convert async task to user task.
-    let user_task = task.clone();
 
-    // check if it is libc, dlopen, it needs recurit.
-    let header = elf
-        .program_iter()
-        .find(|ph| ph.get_type() == Ok(Type::Interp));
-    if let Some(header) = header {
-        if let Ok(SegmentData::Undefined(_data)) = header.get_data(&elf) {
-            drop(frame_ppn);
-            let mut new_args = vec![String::from("libc.so")];
-            new_args.extend(args);
-            return exec_with_process(task, curr_dir, new_args[0].clone(), new_args, envp);
-        }
+    // Everything which can fail is done before the old address space is dropped: the
+    // files are opened, the headers are checked and the frames are allocated.
+    if init_stack_size(&args, &envp, &path.path()) > USER_STACK_INIT_SIZE {
+        return Err(Errno::E2BIG);
     }
-
+    // Randomize the layout of the address space unless personality disabled it.
+    let randomize = task.pcb.lock().personality & ADDR_NO_RANDOMIZE == 0;
+    // Position independent executables are loaded at USER_DYN_ADDR.
+    let base = load_base(
+        &elf,
+        USER_DYN_ADDR + random_offset(randomize, USER_LOAD_RANDOM_RANGE),
+    );
     // 获取程序所有段之后的内存，4K 对齐后作为堆底
     let heap_bottom = elf
         .program_iter()
-        .map(|x| (x.virtual_addr() + x.mem_size()) as usize)
+        .filter(|x| x.get_type() == Ok(Type::Load))
+        .map(|x| base + (x.virtual_addr() + x.mem_size()) as usize)
         .max()
-        .unwrap()
+        .ok_or(Errno::ENOEXEC)?
         .div_ceil(PAGE_SIZE)
-        .mul(PAGE_SIZE);
+        .mul(PAGE_SIZE)
+        + random_offset(randomize, USER_BRK_RANDOM_RANGE);
+    let mmap_base = USER_MMAP_ADDR + random_offset(randomize, USER_MMAP_RANDOM_RANGE);
+    let stack_top = USER_STACK_TOP - random_offset(randomize, USER_STACK_RANDOM_RANGE);
+
+    // The areas of the sections, their pages are read from the page cache on demand.
+    let mut areas = load_areas(&file, &elf, base)?;
+    // A dynamic executable starts from its program interpreter, which loads the
+    // shared libraries and relocates the executable.
+    let interp = elf
+        .program_iter()
+        .find(|ph| ph.get_type() == Ok(Type::Interp));
+    let (interp_base, pc) = match interp {
+        Some(ph) => load_interp(&curr_dir, &file, &ph, randomize, &mut areas)?,
+        None => (0, base + entry_point),
+    };
+    let stack = frame_alloc_much(USER_STACK_INIT_SIZE / PAGE_SIZE).ok_or(Errno::ENOMEM)?;
+
+    // The point of no return, nothing fails after the old address space is dropped.
+    // WARRNING: this convert async task to user task.
+    let user_task = task.clone();
+    user_task.pcb.lock().sample_peak();
+    user_task.pcb.lock().memset.clear();
+    user_task.pcb.lock().shms.clear();
+    user_task.pcb.lock().mlockall = MlockAllFlags::empty();
+    user_task.page_table.restore();
+    user_task.page_table.change();
+    user_task.pcb.lock().mmap_base = mmap_base;
 
-    let base = elf.relocate(USER_DYN_ADDR).unwrap_or(0);
+    for area in areas.iter() {
+        area.mtrackers
+            .iter()
+            .for_each(|x| user_task.map(x.tracker.0, x.vaddr, x.rwx));
+    }
+    user_task.pcb.lock().memset.extend(areas);
     init_task_stack(
         user_task.clone(),
         args,
+        envp,
         base,
+        interp_base,
         &path.path(),
         entry_point,
+        pc,
+        stack_top,
         elf_header.pt2.ph_count() as usize,
         elf_header.pt2.ph_entry_size() as usize,
         elf.get_ph_addr().unwrap_or(0) as usize,
         heap_bottom,
+        stack,
+    );
+    user_task.pcb.lock().sample_peak();
+    Ok(user_task)
+}
+
+/// Get the address an ELF file is loaded at, shared objects are loaded at `dyn_base`.
+fn load_base(elf: &ElfFile, dyn_base: usize) -> usize {
+    match elf.header.pt2.type_().as_type() {
+        header::Type::SharedObject => dyn_base,
+        _ => 0,
+    }
+}
+
+/// Get a random page aligned offset in [0, range) to move a part of the address space.
+/// Returns 0 if the randomization is disabled.
+fn random_offset(randomize: bool, range: usize) -> usize {
+    match randomize {
+        true => random_u64() as usize % (range / PAGE_SIZE) * PAGE_SIZE,
+        false => 0,
+    }
+}
+
+/// Load the program interpreter named by the `PT_INTERP` header at USER_INTERP_ADDR,
+/// its memory areas are added to `areas`.
+///
+/// Returns the base address and the entry of the interpreter.
+fn load_interp(
+    curr_dir: &PathBuf,
+    file: &File,
+    ph: &ProgramHeader,
+    randomize: bool,
+    areas: &mut Vec<MemArea>,
+) -> Result<(usize, usize), Errno> {
+    // The path is at least one byte and the terminating nul.
+    let size = ph.file_size() as usize;
+    if !(2..=PATH_MAX).contains(&size) {
+        return Err(Errno::ENOEXEC);
+    }
+    let mut buffer = vec![0u8; size];
+    file.readat(ph.offset() as usize, &mut buffer)?;
+    let name = CStr::from_bytes_until_nul(&buffer)
+        .ok()
+        .and_then(|x| x.to_str().ok())
+        .ok_or(Errno::ENOEXEC)?;
+    let interp = File::open_link(curr_dir.join(name), OpenFlags::RDONLY)?;
+    let buffer = read_elf_headers(&interp)?.ok_or(Errno::ENOEXEC)?;
+    let elf = ElfFile::new(&buffer).map_err(|_| Errno::ENOEXEC)?;
+    let base = load_base(
+        &elf,
+        USER_INTERP_ADDR + random_offset(randomize, USER_LOAD_RANDOM_RANGE),
     );
+    areas.extend(load_areas(&interp, &elf, base)?);
+    Ok((base, base + elf.header.pt2.entry_point() as usize))
+}
 
-    // map sections.
-    elf.program_iter()
-        .filter(|x| x.get_type().unwrap() == xmas_elf::program::Type::Load)
-        .for_each(|ph| {
-            let file_size = ph.file_size() as usize;
-            let mem_size = ph.mem_size() as usize;
-            let offset = ph.offset() as usize;
-            let virt_addr = base + ph.virtual_addr() as usize;
-            let vpn = virt_addr / PAGE_SIZE;
-
-            let page_count = (virt_addr + mem_size).div_ceil(PAGE_SIZE) - vpn;
-            let ppn_start =
-                user_task.frame_alloc(va!(virt_addr).floor(), MemType::CodeSection, page_count);
-            let page_space = va!(virt_addr).slice_mut_with_len(file_size);
-            let ppn_space = ppn_start
-                .expect("not have enough memory")
-                .add(virt_addr % PAGE_SIZE)
-                .slice_mut_with_len(file_size);
-
-            page_space.copy_from_slice(&buffer[offset..offset + file_size]);
-            assert_eq!(ppn_space, page_space);
-            assert_eq!(&buffer[offset..offset + file_size], ppn_space);
-            assert_eq!(&buffer[offset..offset + file_size], page_space);
+/// Get the memory areas of the `PT_LOAD` segments of the ELF file loaded at `base`.
+fn load_areas(file: &File, elf: &ElfFile, base: usize) -> Result<Vec<MemArea>, Errno> {
+    let cache = PageCache::get(file);
+    let mut areas = Vec::new();
+    for ph in elf.program_iter() {
+        if ph.get_type() != Ok(Type::Load) {
+            continue;
+        }
+        areas.extend(segment_areas(&cache, base, &ph)?);
+    }
+    Ok(areas)
+}
+
+/// Check one of the execute bits of the file is set.
+///
+/// The filesystems which don't keep the access mode report no permission bit at all,
+/// their files are all executable.
+fn is_executable(file: &File) -> Result<bool, Errno> {
+    let mut stat = Stat::default();
+    file.stat(&mut stat)?;
+    let perm = StatMode::OWNER_MASK | StatMode::GROUP_MASK | StatMode::OTHER_MASK;
+    let exec = StatMode::OWNER_EXEC | StatMode::GROUP_EXEC | StatMode::OTHER_EXEC;
+    Ok(!stat.mode.intersects(perm) || stat.mode.intersects(exec))
+}
+
+/// Parse the `#!interpreter [arg]` line of a script.
+///
+/// Returns None if the file doesn't start with `#!`, everything after the interpreter
+/// is passed as one argument.
+fn read_shebang(file: &File) -> Result<Option<(String, Option<String>)>, Errno> {
+    let mut buffer = [0u8; BINPRM_BUF_SIZE];
+    let rsize = file.readat(0, &mut buffer)?;
+    let Some(line) = buffer[..rsize].strip_prefix(b"#!") else {
+        return Ok(None);
+    };
+    let line = line.split(|x| *x == b'\n').next().unwrap_or_default();
+    let line = core::str::from_utf8(line)
+        .map_err(|_| Errno::ENOEXEC)?
+        .trim();
+    let (interp, arg) = match line.split_once([' ', '\t']) {
+        Some((interp, arg)) => (interp, Some(arg.trim())),
+        None => (line, None),
+    };
+    if interp.is_empty() {
+        return Err(Errno::ENOEXEC);
+    }
+    Ok(Some((
+        interp.to_string(),
+        arg.filter(|x| !x.is_empty()).map(String::from),
+    )))
+}
+
+/// The largest program header table an executable may have.
+const MAX_PH_SIZE: usize = 0x10000;
+
+/// Read the ELF header and the program headers of the file.
+///
+/// Returns None if the file is not an ELF file, ENOEXEC if its program header table is
+/// malformed or larger than `MAX_PH_SIZE`.
+fn read_elf_headers(file: &File) -> Result<Option<Vec<u8>>, Errno> {
+    let mut buffer = vec![0u8; PAGE_SIZE];
+    let rsize = file.readat(0, &mut buffer)?;
+    buffer.truncate(rsize);
+    let (ph_offset, ph_count, ph_entry_size) = match ElfFile::new(&buffer) {
+        Ok(elf) => (
+            elf.header.pt2.ph_offset() as usize,
+            elf.header.pt2.ph_count() as usize,
+            elf.header.pt2.ph_entry_size() as usize,
+        ),
+        Err(_) => return Ok(None),
+    };
+    if ph_entry_size != size_of::<ProgramHeader64>() {
+        return Err(Errno::ENOEXEC);
+    }
+    let ph_end = ph_count
+        .checked_mul(ph_entry_size)
+        .filter(|x| *x <= MAX_PH_SIZE)
+        .and_then(|x| x.checked_add(ph_offset))
+        .ok_or(Errno::ENOEXEC)?;
+    // The program headers are usually in the first page, read the rest if not.
+    if ph_end > buffer.len() {
+        if ph_end > file.file_size()? {
+            return Err(Errno::ENOEXEC);
+        }
+        buffer.resize(ph_end, 0);
+        if file.readat(0, &mut buffer)? < ph_end {
+            return Err(Errno::ENOEXEC);
+        }
+    }
+    Ok(Some(buffer))
+}
+
+/// Get the file-backed area and the anonymous area of the bss of a `PT_LOAD` segment.
+///
+/// Pages are mapped when they are touched first. Read-only pages are the frames of the
+/// page cache and shared by all the processes running the binary, writable pages are
+/// copied on write. The pages the areas already hold are mapped by the caller.
+fn segment_areas(
+    cache: &Arc<PageCache>,
+    base: usize,
+    ph: &ProgramHeader,
+) -> Result<Vec<MemArea>, Errno> {
+    let virt_addr = base + ph.virtual_addr() as usize;
+    let file_end = virt_addr + ph.file_size() as usize;
+    let mem_end = virt_addr + ph.mem_size() as usize;
+    let offset = ph.offset() as usize;
+    let prot = segment_prot(ph.flags());
+    let start = virt_addr / PAGE_SIZE * PAGE_SIZE;
+    let file_page_end = file_end.div_ceil(PAGE_SIZE) * PAGE_SIZE;
+
+    let mut areas = Vec::new();
+    if ph.file_size() > 0 {
+        let mut area = MemArea {
+            mtype: MemType::CodeSection,
+            prot,
+            mtrackers: vec![],
+            swapped: vec![],
+            huge: vec![],
+            locked: false,
+            may_write: true,
+            file: Some(cache.clone()),
+            offset: offset - virt_addr % PAGE_SIZE,
+            start,
+            len: file_page_end - start,
+        };
+        // The tail of the last file page belongs to the bss, it must be zero instead of
+        // the following data of the file. Give the segment a private copy of the page.
+        if file_end % PAGE_SIZE != 0 && mem_end > file_end {
+            let vaddr = va!(file_page_end - PAGE_SIZE);
+            let page = cache.get_page(area.page_index(vaddr.raw()))?;
+            let frame = frame_alloc().ok_or(Errno::ENOMEM)?;
+            let len = file_end % PAGE_SIZE;
+            frame
+                .0
+                .slice_mut_with_len(len)
+                .copy_from_slice(page.0.slice_mut_with_len(len));
+            let mut mtracker = MapTrack {
+                vaddr,
+                tracker: Arc::new(frame),
+                rwx: MappingFlags::empty(),
+            };
+            mtracker.rwx = area.page_flags(&mtracker);
+            area.mtrackers.push(mtracker);
+        }
+        areas.push(area);
+    }
+    if mem_end > file_page_end {
+        let start = max(start, file_page_end);
+        areas.push(MemArea {
+            mtype: MemType::CodeSection,
+            prot,
+            mtrackers: vec![],
+            swapped: vec![],
+            huge: vec![],
+            locked: false,
+            may_write: true,
+            file: None,
+            offset: 0,
+            start,
+            len: mem_end.div_ceil(PAGE_SIZE) * PAGE_SIZE - start,
         });
-    Ok(user_task)
+    }
+    Ok(areas)
 }
@@ -1,7 +1,12 @@
 use alloc::{sync::Arc, vec::Vec};
 use core::ops::{Deref, DerefMut};
 use fs::file::File;
-use libc_types::fcntl::OpenFlags;
+use libc_types::{
+    fcntl::OpenFlags,
+    resource::{Rlimit, RLIMIT_NOFILE, RLIMIT_STACK, RLIM_INFINITY, RLIM_NLIMITS},
+};
+
+use crate::consts::USER_STACK_LIMIT;
 
 const FILE_MAX: usize = 255;
 const FD_NONE: Option<Arc<File>> = Option::None;
@@ -41,8 +46,17 @@ impl DerefMut for FileTable {
     }
 }
 
-pub fn rlimits_new() -> Vec<usize> {
-    let mut rlimits = vec![0usize; 8];
-    rlimits[7] = FILE_MAX;
+/// The resource limits of a new process, the resources not listed are unlimited.
+pub fn rlimits_new() -> Vec<Rlimit> {
+    let unlimited = Rlimit {
+        curr: RLIM_INFINITY,
+        max: RLIM_INFINITY,
+    };
+    let mut rlimits = vec![unlimited; RLIM_NLIMITS];
+    rlimits[RLIMIT_STACK].curr = USER_STACK_LIMIT;
+    rlimits[RLIMIT_NOFILE] = Rlimit {
+        curr: FILE_MAX,
+        max: FILE_MAX,
+    };
     rlimits
 }
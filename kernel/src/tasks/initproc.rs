@@ -5,7 +5,7 @@ use alloc::{
     vec::Vec,
 };
 use devices::utils::get_char;
-use executor::{current_task, release_task, task::TaskType, tid2task, yield_now, TASK_MAP};
+use executor::{current_task, release_task, task::TaskType, tid2task, wait_tick, TASK_MAP};
 use fs::{file::File, FileType};
 use libc_types::fcntl::OpenFlags;
 use log::debug;
@@ -16,6 +16,14 @@ use crate::tasks::add_user_task;
 
 use super::UserTask;
 
+/// The environment of the programs started by the init process.
+const DEFAULT_ENVP: &[&str] = &[
+    "LD_LIBRARY_PATH=/",
+    "PS1=\x1b[1m\x1b[32mByteOS\x1b[0m:\x1b[1m\x1b[34m\\w\x1b[0m\\$ ",
+    "PATH=/:/bin:/usr/bin",
+    "UB_BINDIR=./",
+];
+
 fn clear() {
     DebugConsole::putchar(0x1b);
     DebugConsole::putchar(0x5b);
@@ -45,14 +53,14 @@ async fn command(cmd: &str) {
             info!("exec: {}", filename);
             let mut args_extend = vec![filename];
             args_extend.extend(args.into_iter());
-            let task_id = add_user_task(&filename, args_extend, Vec::new()).await;
+            let task_id = add_user_task(&filename, args_extend, DEFAULT_ENVP.to_vec()).await;
             let task = tid2task(task_id).unwrap();
             loop {
                 if task.exit_code().is_some() {
                     release_task(task_id);
                     break;
                 }
-                yield_now().await;
+                wait_tick().await;
             }
             // syscall(SYS_WAIT4, [0,0,0,0,0,0,0])
             //     .await
@@ -0,0 +1,218 @@
+//! Anonymous files created by memfd_create.
+//!
+//! A memfd lives in memory and in no filesystem, it goes away with the last file and
+//! mapping which refers to it. The pages are allocated when they are written, a hole
+//! reads as zeros. The mappings of a memfd map its frames directly, they see the data
+//! written by `write` and write to the memfd without a copy in a [PageCache].
+//!
+//! [PageCache]: super::PageCache
+
+use alloc::{collections::BTreeMap, sync::Arc};
+use core::{
+    cmp::min,
+    ops::Add,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use devices::PAGE_SIZE;
+use fs::INodeInterface;
+use libc_types::{
+    fcntl::SealFlags,
+    types::{Stat, StatMode},
+};
+use runtime::frame::{frame_alloc, FrameTracker};
+use sync::Mutex;
+use syscalls::Errno;
+use vfscore::VfsResult;
+
+use super::{user_processes, MemType};
+
+/// The id of the next memfd.
+static MEMFD_ID: AtomicUsize = AtomicUsize::new(1);
+
+pub struct MemFd {
+    /// Tells the memfds apart, they have no path.
+    id: usize,
+    size: Mutex<usize>,
+    /// The frames are shared with the mappings of the memfd.
+    pages: Mutex<BTreeMap<usize, Arc<FrameTracker>>>,
+    seals: Mutex<SealFlags>,
+}
+
+impl MemFd {
+    /// Create an empty memfd, seals can be added if `sealing` is true.
+    pub fn new(sealing: bool) -> Arc<Self> {
+        let seals = match sealing {
+            true => SealFlags::empty(),
+            false => SealFlags::SEAL,
+        };
+        Arc::new(Self {
+            id: MEMFD_ID.fetch_add(1, Ordering::Relaxed),
+            size: Mutex::new(0),
+            pages: Mutex::new(BTreeMap::new()),
+            seals: Mutex::new(seals),
+        })
+    }
+
+    /// Get the frame of the page at `index` to map it, a hole gets a zeroed frame.
+    pub fn get_page(&self, index: usize) -> Result<Arc<FrameTracker>, Errno> {
+        let mut pages = self.pages.lock();
+        if let Some(page) = pages.get(&index) {
+            return Ok(page.clone());
+        }
+        let frame = Arc::new(frame_alloc().ok_or(Errno::ENOMEM)?);
+        pages.insert(index, frame.clone());
+        Ok(frame)
+    }
+
+    /// Check if `frame` is the page at `index` of the memfd.
+    pub fn is_page(&self, index: usize, frame: &Arc<FrameTracker>) -> bool {
+        self.pages
+            .lock()
+            .get(&index)
+            .is_some_and(|x| Arc::ptr_eq(x, frame))
+    }
+
+    pub fn seals(&self) -> SealFlags {
+        *self.seals.lock()
+    }
+
+    /// Check the memfd can't be written or mapped shared and writable.
+    pub fn write_sealed(&self) -> bool {
+        self.seals()
+            .intersects(SealFlags::WRITE | SealFlags::FUTURE_WRITE)
+    }
+
+    /// Add the seals, fails with EPERM if the memfd is sealed against new seals.
+    ///
+    /// F_SEAL_WRITE fails with EBUSY while a process maps the memfd shared and may make
+    /// the mapping writable, even if it is read-only now.
+    /// The seals are not locked while the processes are scanned, the pcb lock is taken
+    /// before them when a mapping writes to the memfd.
+    pub fn add_seals(self: &Arc<Self>, seals: SealFlags) -> Result<(), Errno> {
+        let curr = self.seals();
+        if curr.contains(SealFlags::SEAL) {
+            return Err(Errno::EPERM);
+        }
+        if seals.contains(SealFlags::WRITE) && !curr.contains(SealFlags::WRITE) {
+            let inode: Arc<dyn INodeInterface> = self.clone();
+            let mapped = user_processes(true).unwrap_or_default().iter().any(|task| {
+                task.pcb.lock().memset.iter().any(|x| {
+                    x.mtype == MemType::ShareFile
+                        && x.may_write
+                        && x.file.as_ref().is_some_and(|x| x.is_file(&inode))
+                })
+            });
+            if mapped {
+                return Err(Errno::EBUSY);
+            }
+        }
+        // F_SEAL_SEAL may be added while the processes are scanned.
+        let mut curr = self.seals.lock();
+        if curr.contains(SealFlags::SEAL) {
+            return Err(Errno::EPERM);
+        }
+        *curr |= seals;
+        Ok(())
+    }
+}
+
+impl INodeInterface for MemFd {
+    fn readat(&self, offset: usize, buffer: &mut [u8]) -> VfsResult<usize> {
+        let size = *self.size.lock();
+        if offset >= size {
+            return Ok(0);
+        }
+        let len = min(buffer.len(), size - offset);
+        let pages = self.pages.lock();
+        let mut done = 0;
+        while done < len {
+            let pos = offset + done;
+            let curr = min(PAGE_SIZE - pos % PAGE_SIZE, len - done);
+            let dst = &mut buffer[done..done + curr];
+            match pages.get(&(pos / PAGE_SIZE)) {
+                Some(page) => dst.copy_from_slice(page.0.add(pos % PAGE_SIZE).slice_with_len(curr)),
+                None => dst.fill(0),
+            }
+            done += curr;
+        }
+        Ok(len)
+    }
+
+    fn writeat(&self, offset: usize, buffer: &[u8]) -> VfsResult<usize> {
+        let end = offset.checked_add(buffer.len()).ok_or(Errno::EFBIG)?;
+        let seals = self.seals();
+        if seals.intersects(SealFlags::WRITE | SealFlags::FUTURE_WRITE) {
+            return Err(Errno::EPERM);
+        }
+        let mut size = self.size.lock();
+        if end > *size && seals.contains(SealFlags::GROW) {
+            return Err(Errno::EPERM);
+        }
+        let mut pages = self.pages.lock();
+        let mut done = 0;
+        while done < buffer.len() {
+            let pos = offset + done;
+            let curr = min(PAGE_SIZE - pos % PAGE_SIZE, buffer.len() - done);
+            let index = pos / PAGE_SIZE;
+            if !pages.contains_key(&index) {
+                match frame_alloc() {
+                    Some(frame) => pages.insert(index, Arc::new(frame)),
+                    // The part written before is kept.
+                    None if done > 0 => break,
+                    None => return Err(Errno::ENOSPC),
+                };
+            }
+            pages[&index]
+                .0
+                .add(pos % PAGE_SIZE)
+                .slice_mut_with_len(curr)
+                .copy_from_slice(&buffer[done..done + curr]);
+            done += curr;
+        }
+        *size = (*size).max(offset + done);
+        Ok(done)
+    }
+
+    fn truncate(&self, new_size: usize) -> VfsResult<()> {
+        let seals = self.seals();
+        let mut size = self.size.lock();
+        if (new_size > *size && seals.contains(SealFlags::GROW))
+            || (new_size < *size && seals.contains(SealFlags::SHRINK))
+        {
+            return Err(Errno::EPERM);
+        }
+        if new_size < *size {
+            let mut pages = self.pages.lock();
+            pages.retain(|index, _| *index < new_size.div_ceil(PAGE_SIZE));
+            // The tail of the last page reads as zeros if the memfd grows again.
+            if new_size % PAGE_SIZE != 0
+                && let Some(page) = pages.get(&(new_size / PAGE_SIZE))
+            {
+                let offset = new_size % PAGE_SIZE;
+                page.0.add(offset).clear_len(PAGE_SIZE - offset);
+            }
+        }
+        *size = new_size;
+        Ok(())
+    }
+
+    fn flush(&self) -> VfsResult<()> {
+        Ok(())
+    }
+
+    fn stat(&self, stat: &mut Stat) -> VfsResult<()> {
+        let size = *self.size.lock();
+        stat.dev = 0;
+        stat.ino = self.id as _;
+        stat.mode =
+            StatMode::FILE | StatMode::OWNER_MASK | StatMode::GROUP_MASK | StatMode::OTHER_MASK;
+        stat.nlink = 1;
+        stat.uid = 0;
+        stat.gid = 0;
+        stat.size = size as _;
+        stat.blksize = PAGE_SIZE as _;
+        stat.blocks = (self.pages.lock().len() * PAGE_SIZE / 512) as _;
+        stat.rdev = 0;
+        Ok(())
+    }
+}
@@ -1,13 +1,19 @@
 use alloc::{sync::Arc, vec::Vec};
 use core::{
-    cmp::min,
+    cmp::{max, min},
     fmt::Debug,
-    ops::{Deref, DerefMut},
+    ops::{AddAssign, Deref, DerefMut},
 };
 use devices::PAGE_SIZE;
-use fs::INodeInterface;
-use polyhal::{PageTable, VirtAddr};
-use runtime::frame::FrameTracker;
+use polyhal::{va, MappingFlags, MappingSize, PageTable, VirtAddr};
+use runtime::frame::{frame_alloc, try_frame_alloc_much, FrameTracker};
+use syscalls::Errno;
+
+use super::{
+    page_cache::PageCache,
+    swap::{swap_in, SwapEntry},
+};
+use crate::consts::{HUGE_PAGE_SIZE, STACK_GUARD_GAP};
 
 /// Memory set for storing the memory and its map relation.
 #[derive(Debug)]
@@ -50,14 +56,379 @@ impl<'a> MemSet {
         self.0.extend(new_set);
     }
 
+    /// Split the area which contains `addr` in the middle into two areas at `addr`.
+    fn split_at(&mut self, addr: usize, pt: &PageTable) {
+        let area = self
+            .0
+            .iter_mut()
+            .find(|x| x.start < addr && addr < x.start + x.len);
+        if let Some(area) = area {
+            let new_area = area.split_off(addr, pt);
+            self.0.push(new_area);
+        }
+    }
+
+    /// Take the memory in [start, end) out as an area, it must be in one area.
+    pub fn take_range(&mut self, start: usize, end: usize, pt: &PageTable) -> Option<MemArea> {
+        self.0
+            .iter()
+            .find(|x| x.contains(start) && end <= x.start + x.len)?;
+        self.split_at(start, pt);
+        self.split_at(end, pt);
+        let index = self.0.iter().position(|x| x.start == start)?;
+        Some(self.0.swap_remove(index))
+    }
+
+    /// Change the protection of the memory in [start, end).
+    ///
+    /// Areas across the boundaries are split, mapped pages are remapped with the new
+    /// protection. Returns false without changing anything if a part of the range
+    /// is not mapped.
+    pub fn protect(
+        &mut self,
+        start: usize,
+        end: usize,
+        prot: MappingFlags,
+        pt: &PageTable,
+    ) -> bool {
+        if !self.covers(start, end) {
+            return false;
+        }
+
+        self.split_at(start, pt);
+        self.split_at(end, pt);
+        self.0
+            .iter_mut()
+            .filter(|x| start <= x.start && x.start + x.len <= end)
+            .for_each(|area| {
+                area.split_huge(area.start, area.start + area.len, pt);
+                area.prot = prot;
+                for i in 0..area.mtrackers.len() {
+                    let flags = area.page_flags(&area.mtrackers[i]);
+                    area.mtrackers[i].rwx = flags;
+                    map_track(pt, &area.mtrackers[i], flags);
+                }
+            });
+        true
+    }
+
+    /// Check every page in [start, end) is in an area.
+    pub fn covers(&self, start: usize, end: usize) -> bool {
+        let mut ranges: Vec<_> = self
+            .0
+            .iter()
+            .filter(|x| x.overlapping(start, end))
+            .map(|x| (x.start, x.start + x.len))
+            .collect();
+        ranges.sort();
+        let mut covered = start;
+        for (area_start, area_end) in ranges {
+            if area_start > covered {
+                return false;
+            }
+            covered = covered.max(area_end);
+        }
+        covered >= end
+    }
+
+    /// Lock or unlock the memory in [start, end), the areas across the boundaries are
+    /// split. Returns false without changing anything if a part of the range is not
+    /// mapped.
+    pub fn lock(&mut self, start: usize, end: usize, locked: bool, pt: &PageTable) -> bool {
+        if !self.covers(start, end) {
+            return false;
+        }
+        self.split_at(start, pt);
+        self.split_at(end, pt);
+        self.0
+            .iter_mut()
+            .filter(|x| start <= x.start && x.start + x.len <= end)
+            .for_each(|x| x.locked = locked);
+        true
+    }
+
+    /// Grow the stack area above `addr` down to contain it.
+    ///
+    /// The stack may take up to `limit` bytes, grow by up to `room` bytes and keeps
+    /// STACK_GUARD_GAP away from the area below it. Returns false if `addr` is not a
+    /// valid stack address.
+    pub fn grow_stack(&mut self, addr: usize, limit: usize, room: usize) -> bool {
+        let addr = addr / PAGE_SIZE * PAGE_SIZE;
+        let below_end = self
+            .0
+            .iter()
+            .filter(|x| x.start + x.len <= addr)
+            .map(|x| x.start + x.len)
+            .max()
+            .unwrap_or(0);
+        if addr < below_end + STACK_GUARD_GAP {
+            return false;
+        }
+        let stack = self
+            .0
+            .iter_mut()
+            .filter(|x| x.start > addr)
+            .min_by_key(|x| x.start);
+        match stack {
+            Some(stack) if stack.mtype == MemType::Stack => {
+                let top = stack.start + stack.len;
+                if top - addr > limit || stack.start - addr > room {
+                    return false;
+                }
+                stack.start = addr;
+                stack.len = top - addr;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Unmap up to `count` clean file pages which can be taken from the page cache again.
+    ///
+    /// The unmapped trackers are returned, drop them after the TLB shootdown.
+    pub fn unmap_file_pages(&mut self, count: usize, pt: &PageTable) -> Vec<Arc<FrameTracker>> {
+        let mut unmapped = Vec::new();
+        for area in self
+            .0
+            .iter_mut()
+            .filter(|x| x.mtype != MemType::Shared && !x.locked)
+        {
+            let Some(cache) = area.file.clone() else {
+                continue;
+            };
+            let mut index = 0;
+            while index < area.mtrackers.len() && unmapped.len() < count {
+                let mtracker = &area.mtrackers[index];
+                let page_index = area.page_index(mtracker.vaddr.raw());
+                if !cache.is_clean(page_index, &mtracker.tracker) {
+                    index += 1;
+                    continue;
+                }
+                let mtracker = area.mtrackers.swap_remove(index);
+                unmap_track(pt, &mtracker);
+                unmapped.push(mtracker.tracker);
+            }
+        }
+        unmapped
+    }
+
+    /// Unmap up to `count` private pages which are only used by this memory set.
+    ///
+    /// The pages stay in the memory set until [MemSet::replace_swapped] replaces them
+    /// with the swap entries. A page fault maps them again in the meantime.
+    pub fn unmap_private_pages(
+        &mut self,
+        count: usize,
+        pt: &PageTable,
+    ) -> Vec<(VirtAddr, Arc<FrameTracker>)> {
+        let mut unmapped = Vec::new();
+        for area in self.0.iter_mut() {
+            if matches!(area.mtype, MemType::Shared | MemType::ShareFile) || area.locked {
+                continue;
+            }
+            for mtracker in area.mtrackers.iter_mut() {
+                if unmapped.len() >= count {
+                    return unmapped;
+                }
+                // Huge pages are not swapped, they are split only when the process
+                // changes the mapping.
+                if Arc::strong_count(&mtracker.tracker) != 1
+                    || area
+                        .huge
+                        .contains(&(mtracker.vaddr.raw() / HUGE_PAGE_SIZE * HUGE_PAGE_SIZE))
+                {
+                    continue;
+                }
+                unmap_track(pt, mtracker);
+                mtracker.rwx = MappingFlags::empty();
+                unmapped.push((mtracker.vaddr, mtracker.tracker.clone()));
+            }
+        }
+        unmapped
+    }
+
+    /// Replace the pages written to the swap areas with their swap entries.
+    ///
+    /// A page which was mapped again or changed since it was unmapped is kept, its swap
+    /// entry is dropped. Returns the number of the freed frames.
+    pub fn replace_swapped(
+        &mut self,
+        swapped: Vec<(VirtAddr, Arc<FrameTracker>, SwapEntry)>,
+    ) -> usize {
+        let mut freed = 0;
+        for (vaddr, frame, entry) in swapped {
+            let Some(area) = self.0.iter_mut().find(|x| x.contains(vaddr.raw())) else {
+                continue;
+            };
+            let index = area.mtrackers.iter().position(|x| {
+                x.vaddr == vaddr && x.rwx.is_empty() && Arc::ptr_eq(&x.tracker, &frame)
+            });
+            if let Some(index) = index {
+                area.mtrackers.swap_remove(index);
+                area.swapped.push(SwapTrack {
+                    vaddr,
+                    entry: Arc::new(entry),
+                });
+                if Arc::strong_count(&frame) == 1 {
+                    freed += 1;
+                }
+            }
+        }
+        freed
+    }
+
+    /// Read all the pages swapped to the swap area `id` back into memory.
+    pub fn swap_in_area(&mut self, id: usize) -> Result<(), Errno> {
+        for area in self.0.iter_mut() {
+            while let Some(index) = area.swapped.iter().position(|x| x.entry.area() == id) {
+                let tracker = Arc::new(swap_in(&area.swapped[index].entry)?);
+                let swapped = area.swapped.swap_remove(index);
+                area.mtrackers.push(MapTrack {
+                    vaddr: swapped.vaddr,
+                    tracker,
+                    rwx: MappingFlags::empty(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the number of the pages in memory.
+    pub fn resident_pages(&self) -> usize {
+        self.0.iter().map(|x| x.mtrackers.len()).sum()
+    }
+
+    /// Get the number of the pages in the swap areas.
+    pub fn swapped_pages(&self) -> usize {
+        self.0.iter().map(|x| x.swapped.len()).sum()
+    }
+
+    /// Get the size of all the areas in bytes.
+    pub fn mapped_size(&self) -> usize {
+        self.0.iter().map(|x| x.len).sum()
+    }
+
+    /// Get the size of the areas inside [start, end) in bytes.
+    pub fn mapped_size_in(&self, start: usize, end: usize) -> usize {
+        self.0
+            .iter()
+            .map(|x| min(end, x.start + x.len).saturating_sub(max(start, x.start)))
+            .sum()
+    }
+
+    /// Get the size of the data segment in bytes, the areas counted by RLIMIT_DATA.
+    pub fn data_size(&self) -> usize {
+        self.0.iter().filter(|x| x.is_data()).map(|x| x.len).sum()
+    }
+
+    /// Unmap the private pages of a process killed by the OOM killer.
+    ///
+    /// The areas stay, an access before the process exits gets a zeroed page. The
+    /// unmapped trackers are returned, drop them after the TLB shootdown.
+    pub fn reap(&mut self, pt: &PageTable) -> Vec<MapTrack> {
+        let mut unmapped = Vec::new();
+        self.0
+            .iter_mut()
+            .filter(|x| !matches!(x.mtype, MemType::Shared | MemType::ShareFile))
+            .for_each(|area| {
+                area.swapped.clear();
+                area.huge.drain(..).for_each(|x| pt.unmap_page(va!(x)));
+                area.mtrackers.iter().for_each(|x| unmap_track(pt, x));
+                unmapped.append(&mut area.mtrackers);
+            });
+        unmapped
+    }
+
     pub fn clear(&mut self) {
         self.0.clear();
     }
 }
 
+/// Map a tracked page with the flags.
+///
+/// A page without any permission is unmapped instead, so that every access to it faults.
+pub fn map_track(pt: &PageTable, mtracker: &MapTrack, flags: MappingFlags) {
+    if flags.intersects(MappingFlags::R | MappingFlags::W | MappingFlags::X) {
+        pt.map_page(
+            mtracker.vaddr,
+            mtracker.tracker.0,
+            flags,
+            MappingSize::Page4KB,
+        );
+    } else {
+        unmap_track(pt, mtracker);
+    }
+}
+
+/// Unmap a tracked page if it is mapped.
+fn unmap_track(pt: &PageTable, mtracker: &MapTrack) {
+    if pt.translate(mtracker.vaddr).is_some() {
+        pt.unmap_page(mtracker.vaddr);
+    }
+}
+
+/// The memory usage of an area or a process, the sizes are in pages except `size`.
+#[derive(Clone, Copy, Default)]
+pub struct MemUsage {
+    /// The size of the mapping in bytes.
+    pub size: usize,
+    /// The resident anonymous pages, including the private copies of file pages.
+    pub anon: usize,
+    /// The resident pages of the page caches.
+    pub file: usize,
+    /// The resident pages of the shared anonymous memory and SysV shared memory.
+    pub shmem: usize,
+    pub shared_clean: usize,
+    pub shared_dirty: usize,
+    pub private_clean: usize,
+    pub private_dirty: usize,
+    /// The proportional set size in bytes, a page mapped n times counts 1/n.
+    pub pss: usize,
+    pub swapped: usize,
+    /// The pages mapped with huge pages.
+    pub huge: usize,
+}
+
+impl MemUsage {
+    /// Get the number of the resident pages.
+    #[inline]
+    pub fn resident(&self) -> usize {
+        self.anon + self.file + self.shmem
+    }
+
+    /// Count a resident page which is mapped `mappings` times.
+    pub fn add_page(&mut self, mappings: usize, dirty: bool) {
+        let mappings = mappings.max(1);
+        self.pss += PAGE_SIZE / mappings;
+        match (mappings > 1, dirty) {
+            (true, false) => self.shared_clean += 1,
+            (true, true) => self.shared_dirty += 1,
+            (false, false) => self.private_clean += 1,
+            (false, true) => self.private_dirty += 1,
+        }
+    }
+}
+
+impl AddAssign for MemUsage {
+    fn add_assign(&mut self, rhs: Self) {
+        self.size += rhs.size;
+        self.anon += rhs.anon;
+        self.file += rhs.file;
+        self.shmem += rhs.shmem;
+        self.shared_clean += rhs.shared_clean;
+        self.shared_dirty += rhs.shared_dirty;
+        self.private_clean += rhs.private_clean;
+        self.private_dirty += rhs.private_dirty;
+        self.pss += rhs.pss;
+        self.swapped += rhs.swapped;
+        self.huge += rhs.huge;
+    }
+}
+
 #[derive(Clone, PartialEq, Debug, Copy)]
 pub enum MemType {
     CodeSection,
+    /// A stack which grows down on the page faults below it.
     Stack,
     Mmap,
     Shared,
@@ -68,7 +439,9 @@ pub enum MemType {
 pub struct MapTrack {
     pub vaddr: VirtAddr,
     pub tracker: Arc<FrameTracker>,
-    pub rwx: u8,
+    /// The flags the page is mapped with now. A copy-on-write page lacks the write
+    /// permission of its area until it is copied.
+    pub rwx: MappingFlags,
 }
 
 impl Debug for MapTrack {
@@ -81,11 +454,31 @@ impl Debug for MapTrack {
     }
 }
 
+/// A page of a private area which was written to a swap area.
+#[derive(Clone)]
+pub struct SwapTrack {
+    pub vaddr: VirtAddr,
+    pub entry: Arc<SwapEntry>,
+}
+
 #[derive(Clone)]
 pub struct MemArea {
     pub mtype: MemType,
+    /// The protection of the area, a combination of R, W and X.
+    pub prot: MappingFlags,
     pub mtrackers: Vec<MapTrack>,
-    pub file: Option<Arc<dyn INodeInterface>>,
+    /// The pages written to the swap areas, they are read back on the page faults.
+    pub swapped: Vec<SwapTrack>,
+    /// The start addresses of the blocks mapped with huge pages. The pages in them are
+    /// still tracked one by one in `mtrackers`.
+    pub huge: Vec<usize>,
+    /// The pages are locked in memory by mlock, the reclaim skips them.
+    pub locked: bool,
+    /// The area may be made writable. A shared mapping of a file opened read-only or of
+    /// a write sealed memfd never can.
+    pub may_write: bool,
+    /// The page cache of the mapped file, pages are taken from it on demand.
+    pub file: Option<Arc<PageCache>>,
     pub offset: usize,
     pub start: usize,
     pub len: usize,
@@ -95,6 +488,7 @@ impl Debug for MemArea {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("MemArea")
             .field("mtype", &self.mtype)
+            .field("prot", &self.prot)
             .field("mtrackers", &self.mtrackers)
             .field("start", &self.start)
             .field("len", &self.len)
@@ -111,14 +505,102 @@ impl MemArea {
         res
     }
 
-    /// write page to file
-    pub fn write_page(&self, mtracker: &MapTrack) {
-        assert!(self.file.is_some());
-        if let Some(file) = &self.file {
-            let offset = mtracker.vaddr.raw() + self.offset - self.start;
-            file.writeat(offset, mtracker.tracker.0.slice_mut_with_len(PAGE_SIZE))
-                .expect("can't write data back to mapped file.");
+    /// Get the flags to map a page of this area.
+    ///
+    /// A private page which is still shared with another process or the page cache is
+    /// mapped without the write permission, the first write to it traps and copies it.
+    /// A page of a shared file mapping is writable after the first write marked it
+    /// dirty.
+    pub fn page_flags(&self, mtracker: &MapTrack) -> MappingFlags {
+        let flags = self.prot | MappingFlags::U;
+        let writable = match self.mtype {
+            MemType::Shared => true,
+            MemType::ShareFile => self
+                .file
+                .as_ref()
+                .is_some_and(|x| x.is_dirty(self.page_index(mtracker.vaddr.raw()))),
+            _ => Arc::strong_count(&mtracker.tracker) == 1,
+        };
+        match writable {
+            true => flags,
+            false => flags - MappingFlags::W,
+        }
+    }
+
+    /// Get the memory usage of the area.
+    ///
+    /// A page is shared if another mapping holds its frame, the reference held by the
+    /// page cache doesn't count.
+    pub fn usage(&self) -> MemUsage {
+        let mut usage = MemUsage {
+            size: self.len,
+            swapped: self.swapped.len(),
+            huge: self.huge.len() * HUGE_PAGE_SIZE / PAGE_SIZE,
+            ..Default::default()
+        };
+        for mtracker in self.mtrackers.iter() {
+            let index = self.page_index(mtracker.vaddr.raw());
+            let cached = self
+                .file
+                .as_ref()
+                .is_some_and(|x| x.is_cached(index, &mtracker.tracker));
+            let mappings = Arc::strong_count(&mtracker.tracker) - cached as usize;
+            let dirty = match (&self.file, cached) {
+                (Some(cache), true) => cache.is_dirty(index),
+                _ => true,
+            };
+            match (self.mtype, cached) {
+                (MemType::Shared, _) => usage.shmem += 1,
+                (_, true) => usage.file += 1,
+                (_, false) => usage.anon += 1,
+            }
+            usage.add_page(mappings, dirty);
+        }
+        usage
+    }
+
+    /// Get the index of the file page mapped at `vaddr`.
+    #[inline]
+    pub fn page_index(&self, vaddr: usize) -> usize {
+        (vaddr - self.start + self.offset) / PAGE_SIZE
+    }
+
+    /// Write the dirty file pages mapped in [start, end) back to the file.
+    pub fn sync(&self, start: usize, end: usize) -> Result<(), Errno> {
+        let start = max(start, self.start);
+        let end = min(end, self.start + self.len);
+        match &self.file {
+            Some(cache) if self.mtype == MemType::ShareFile && start < end => {
+                cache.sync(self.page_index(start)..self.page_index(end - 1) + 1)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Write-protect the dirty file pages in [start, end) no other mapping holds and
+    /// mark them clean, a write after their writeback marks them dirty again.
+    ///
+    /// Returns the indexes of the pages to write back after the TLB shootdown.
+    pub fn clean_pages(&mut self, start: usize, end: usize, pt: &PageTable) -> Vec<usize> {
+        let Some(cache) = self.file.clone() else {
+            return Vec::new();
+        };
+        if self.mtype != MemType::ShareFile {
+            return Vec::new();
+        }
+        let mut cleaned = Vec::new();
+        for i in 0..self.mtrackers.len() {
+            let vaddr = self.mtrackers[i].vaddr.raw();
+            let index = self.page_index(vaddr);
+            if !(start..end).contains(&vaddr) || !cache.clean(index, &self.mtrackers[i].tracker) {
+                continue;
+            }
+            let flags = self.page_flags(&self.mtrackers[i]);
+            self.mtrackers[i].rwx = flags;
+            map_track(pt, &self.mtrackers[i], flags);
+            cleaned.push(index);
         }
+        cleaned
     }
 
     /// Sub the memory from this memory area.
@@ -130,30 +612,43 @@ impl MemArea {
         let range = self.start..self.start + self.len;
         let jrange = start..end;
 
+        if let Err(err) = self.sync(start, end) {
+            warn!("can't write back the unmapped file pages: {:?}", err);
+        }
+        self.drop_huge(start, end, pt);
+
         if range.contains(&start) && range.contains(&end) {
             self.len = start - self.start;
             let new_area_range = end..range.end;
 
-            if let Some(_file) = &self.file {
-                self.mtrackers
-                    .iter()
-                    .filter(|x| jrange.contains(&x.vaddr.raw()))
-                    .for_each(|x| {
-                        self.write_page(x);
-                    });
-            };
+            let mtrackers = self
+                .mtrackers
+                .extract_if(.., |x| new_area_range.contains(&x.vaddr.raw()))
+                .collect();
+            let swapped = self
+                .swapped
+                .extract_if(.., |x| new_area_range.contains(&x.vaddr.raw()))
+                .collect();
+            let huge = self
+                .huge
+                .extract_if(.., |x| new_area_range.contains(x))
+                .collect();
             // drop the sub memory area pages.
             self.mtrackers
-                .retain(|x| !new_area_range.contains(&x.vaddr.raw()));
+                .extract_if(.., |x| jrange.contains(&x.vaddr.raw()))
+                .for_each(|x| unmap_track(pt, &x));
+            self.swapped.retain(|x| !jrange.contains(&x.vaddr.raw()));
             return Some(MemArea {
                 mtype: self.mtype,
-                mtrackers: self
-                    .mtrackers
-                    .extract_if(.., |x| new_area_range.contains(&x.vaddr.raw()))
-                    .collect(),
+                prot: self.prot,
+                mtrackers,
+                swapped,
+                huge,
+                locked: self.locked,
+                may_write: self.may_write,
                 file: self.file.clone(),
                 start: end,
-                offset: end - self.start,
+                offset: self.offset + end - self.start,
                 len: new_area_range.len(),
             });
         }
@@ -162,16 +657,11 @@ impl MemArea {
             self.len = 0;
             // TIPS: This area will be remove outside this function.
             // So return the None.
-            if let Some(_file) = &self.file {
-                self.mtrackers
-                    .iter()
-                    .filter(|x| jrange.contains(&x.vaddr.raw()))
-                    .for_each(|x| self.write_page(x));
-            };
             self.mtrackers.retain(|x| {
-                pt.unmap_page(x.vaddr);
+                unmap_track(pt, x);
                 false
             });
+            self.swapped.clear();
             return None;
         }
 
@@ -180,49 +670,205 @@ impl MemArea {
             self.len = start - self.start;
         } else if jrange.contains(&self.start) {
             self.len = self.start + self.len - end;
+            self.offset += end - self.start;
             self.start = end;
         }
-        if let Some(_file) = &self.file {
-            self.mtrackers
-                .iter()
-                .filter(|x| jrange.contains(&x.vaddr.raw()))
-                .for_each(|x| self.write_page(x));
-        };
         // drop the sub memory area pages.
         let new_self_rang = self.start..self.start + self.len;
         self.mtrackers
             .extract_if(.., |x| !new_self_rang.contains(&x.vaddr.raw()))
-            .for_each(|x| pt.unmap_page(x.vaddr));
+            .for_each(|x| unmap_track(pt, &x));
+        self.swapped
+            .retain(|x| new_self_rang.contains(&x.vaddr.raw()));
         None
     }
 
+    /// Split the area at `addr`, the part after `addr` is returned as a new area.
+    pub fn split_off(&mut self, addr: usize, pt: &PageTable) -> MemArea {
+        assert!(self.contains(addr) && addr % PAGE_SIZE == 0);
+        self.split_huge(addr, addr, pt);
+        let new_area = MemArea {
+            mtype: self.mtype,
+            prot: self.prot,
+            mtrackers: self
+                .mtrackers
+                .extract_if(.., |x| x.vaddr.raw() >= addr)
+                .collect(),
+            swapped: self
+                .swapped
+                .extract_if(.., |x| x.vaddr.raw() >= addr)
+                .collect(),
+            huge: self.huge.extract_if(.., |x| *x >= addr).collect(),
+            locked: self.locked,
+            may_write: self.may_write,
+            file: self.file.clone(),
+            offset: self.offset + addr - self.start,
+            start: addr,
+            len: self.start + self.len - addr,
+        };
+        self.len = addr - self.start;
+        new_area
+    }
+
     /// Check the memory area whether contains the specified address.
     pub fn contains(&self, addr: usize) -> bool {
         self.start <= addr && addr < self.start + self.len
     }
-}
 
-impl Drop for MemArea {
-    fn drop(&mut self) {
-        match &self.mtype {
-            MemType::ShareFile => {
-                let start = self.start;
-                let len = self.len;
-                let mapfile = self.file.clone().unwrap();
-                for tracker in &self.mtrackers {
-                    if Arc::strong_count(&tracker.tracker) > 1 {
-                        continue;
-                    }
-
-                    let offset = tracker.vaddr.raw() - start;
-                    let wlen = min(len - offset, PAGE_SIZE);
-
-                    mapfile
-                        .writeat(offset, tracker.tracker.0.slice_mut_with_len(wlen))
-                        .expect("can't write data to file at drop");
-                }
+    /// Check the area is a private writable mapping other than a stack, which
+    /// belongs to the data segment.
+    pub fn is_data(&self) -> bool {
+        self.prot.contains(MappingFlags::W)
+            && !matches!(
+                self.mtype,
+                MemType::Stack | MemType::Shared | MemType::ShareFile
+            )
+    }
+
+    /// Get the index of the tracker of the page at `vaddr`.
+    ///
+    /// A missing page is read from the swap area if it was swapped out, from the page
+    /// cache of the file, or a zeroed frame for an anonymous area. The new tracker is
+    /// not mapped yet.
+    pub fn get_or_alloc_page(&mut self, vaddr: VirtAddr) -> Result<usize, Errno> {
+        let vaddr = vaddr.floor();
+        if let Some(index) = self.mtrackers.iter().position(|x| x.vaddr == vaddr) {
+            return Ok(index);
+        }
+        let swapped = self.swapped.iter().position(|x| x.vaddr == vaddr);
+        let tracker = match (swapped, &self.file) {
+            (Some(index), _) => {
+                let tracker = Arc::new(swap_in(&self.swapped[index].entry)?);
+                self.swapped.swap_remove(index);
+                tracker
             }
-            _ => {}
+            (None, Some(cache)) => cache.get_page(self.page_index(vaddr.raw()))?,
+            (None, None) => Arc::new(frame_alloc().ok_or(Errno::ENOMEM)?),
+        };
+        self.mtrackers.push(MapTrack {
+            vaddr,
+            tracker,
+            rwx: MappingFlags::empty(),
+        });
+        Ok(self.mtrackers.len() - 1)
+    }
+
+    /// Map all the pages in [start, end) of the area now instead of on the page faults.
+    ///
+    /// A PROT_NONE area can never be accessed, it is left empty as linux does.
+    pub fn populate(&mut self, start: usize, end: usize, pt: &PageTable) -> Result<(), Errno> {
+        if !self
+            .prot
+            .intersects(MappingFlags::R | MappingFlags::W | MappingFlags::X)
+        {
+            return Ok(());
+        }
+        let start = max(start, self.start) / PAGE_SIZE * PAGE_SIZE;
+        let end = min(end, self.start + self.len);
+        for vaddr in (start..end).step_by(PAGE_SIZE) {
+            let vaddr = VirtAddr::new(vaddr);
+            if self.mtrackers.iter().any(|x| x.vaddr == vaddr)
+                || self.map_huge_page(vaddr.raw(), pt)
+            {
+                continue;
+            }
+            let index = self.get_or_alloc_page(vaddr)?;
+            let flags = self.page_flags(&self.mtrackers[index]);
+            self.mtrackers[index].rwx = flags;
+            map_track(pt, &self.mtrackers[index], flags);
+        }
+        Ok(())
+    }
+
+    /// Drop the pages in [start, end) of the area, they are filled again on the next
+    /// access. Anonymous pages come back zeroed, file pages are read from the file.
+    pub fn discard(&mut self, start: usize, end: usize, pt: &PageTable) {
+        self.drop_huge(start, end, pt);
+        self.mtrackers
+            .extract_if(.., |x| (start..end).contains(&x.vaddr.raw()))
+            .for_each(|x| unmap_track(pt, &x));
+        self.swapped
+            .retain(|x| !(start..end).contains(&x.vaddr.raw()));
+    }
+
+    /// Move the area to `start`, the mapped pages are moved with it.
+    pub fn move_to(&mut self, start: usize, pt: &PageTable) {
+        self.split_huge(self.start, self.start + self.len, pt);
+        for mtracker in self.mtrackers.iter_mut() {
+            unmap_track(pt, mtracker);
+            mtracker.vaddr = VirtAddr::new(mtracker.vaddr.raw() - self.start + start);
+            map_track(pt, mtracker, mtracker.rwx);
+        }
+        for swapped in self.swapped.iter_mut() {
+            swapped.vaddr = VirtAddr::new(swapped.vaddr.raw() - self.start + start);
+        }
+        self.start = start;
+    }
+
+    /// Map the block which contains `vaddr` with a huge page.
+    ///
+    /// Only a whole block of a private anonymous area without any page in it can be a
+    /// huge page. Returns false if the block can't be a huge page or there are no
+    /// contiguous frames for it, the caller falls back to a page.
+    pub fn map_huge_page(&mut self, vaddr: usize, pt: &PageTable) -> bool {
+        let block = vaddr / HUGE_PAGE_SIZE * HUGE_PAGE_SIZE;
+        let range = block..block + HUGE_PAGE_SIZE;
+        if self.mtype != MemType::Mmap
+            || self.file.is_some()
+            || block < self.start
+            || range.end > self.start + self.len
+            || self
+                .mtrackers
+                .iter()
+                .any(|x| range.contains(&x.vaddr.raw()))
+            || self.swapped.iter().any(|x| range.contains(&x.vaddr.raw()))
+        {
+            return false;
+        }
+        let Some(frames) = try_frame_alloc_much(HUGE_PAGE_SIZE / PAGE_SIZE) else {
+            return false;
+        };
+        let flags = self.prot | MappingFlags::U;
+        pt.map_page(va!(block), frames[0].0, flags, MappingSize::Page2MB);
+        self.mtrackers
+            .extend(frames.into_iter().enumerate().map(|(i, frame)| MapTrack {
+                vaddr: va!(block + i * PAGE_SIZE),
+                tracker: Arc::new(frame),
+                rwx: flags,
+            }));
+        self.huge.push(block);
+        true
+    }
+
+    /// Split the huge pages overlapping [start, end), their pages are mapped one by one.
+    ///
+    /// An empty range splits the huge page which contains `start` in the middle.
+    pub fn split_huge(&mut self, start: usize, end: usize, pt: &PageTable) {
+        let blocks: Vec<usize> = self
+            .huge
+            .extract_if(.., |x| *x < end && start < *x + HUGE_PAGE_SIZE)
+            .collect();
+        for block in blocks {
+            pt.unmap_page(va!(block));
+            let range = block..block + HUGE_PAGE_SIZE;
+            self.mtrackers
+                .iter()
+                .filter(|x| range.contains(&x.vaddr.raw()))
+                .for_each(|x| map_track(pt, x, x.rwx));
+        }
+    }
+
+    /// Drop the huge pages inside [start, end) and split the ones across the boundaries.
+    fn drop_huge(&mut self, start: usize, end: usize, pt: &PageTable) {
+        let blocks: Vec<usize> = self
+            .huge
+            .extract_if(.., |x| start <= *x && *x + HUGE_PAGE_SIZE <= end)
+            .collect();
+        for block in blocks {
+            pt.unmap_page(va!(block));
+            let range = block..block + HUGE_PAGE_SIZE;
+            self.mtrackers.retain(|x| !range.contains(&x.vaddr.raw()));
         }
+        self.split_huge(start, end, pt);
     }
 }
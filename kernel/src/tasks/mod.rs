@@ -3,10 +3,16 @@ pub mod elf;
 pub mod exec;
 mod filetable;
 mod initproc;
+mod memfd;
 mod memset;
+pub mod oom;
+mod page_cache;
+mod proc_dir;
 mod shm;
 mod stack;
+pub mod swap;
 mod task;
+pub mod tlb;
 
 use self::initproc::initproc;
 use crate::{consts::USER_WORK_DIR, syscall::NET_SERVER, user::entry::user_entry};
@@ -17,13 +23,17 @@ use alloc::{
 };
 pub use async_ops::{
     futex_requeue, futex_wake, WaitFutex, WaitHandleAbleSignal, WaitPid, WaitSignal,
+    WaitThreadEvent, WaitTickOrEvent,
 };
 use devices::get_net_device;
 use exec::exec_with_process;
-use executor::{current_task, thread, yield_now, AsyncTask, TaskId, DEFAULT_EXECUTOR};
+use executor::{current_task, thread, wait_tick, AsyncTask, TaskId, DEFAULT_EXECUTOR, TASK_MAP};
 use fs::pathbuf::PathBuf;
+pub use memfd::MemFd;
 pub use memset::{MapTrack, MemArea, MemType};
+pub use page_cache::PageCache;
 use polyhal::common::get_cpu_num;
+use runtime::frame::set_reclaimer;
 pub use shm::{MapedSharedMemory, SharedMemory, SHARED_MEMORY};
 pub use task::UserTask;
 
@@ -41,12 +51,15 @@ pub async fn handle_net() {
         if let Ok(rlen) = res {
             NET_SERVER.analysis_net_data(&buffer[..rlen]);
         }
-        yield_now().await;
+        wait_tick().await;
     }
 }
 
 pub fn init() {
     DEFAULT_EXECUTOR.init(get_cpu_num());
+    tlb::init(get_cpu_num());
+    set_reclaimer(oom::reclaim_or_kill);
+    proc_dir::init();
     thread::spawn_blank(initproc());
     // #[cfg(feature = "net")]
     // thread::spawn_blank(KernelTask::new(handle_net()));
@@ -78,3 +91,23 @@ pub async fn add_user_task(filename: &str, args: Vec<&str>, envp: Vec<&str>) ->
 pub fn current_user_task() -> Arc<UserTask> {
     current_task().downcast_arc::<UserTask>().ok().unwrap()
 }
+
+/// Get one task of every user process, the threads of a process share the memory set.
+///
+/// The main thread is taken if it is alive. Returns None if the task map is busy and
+/// `blocking` is false.
+pub fn user_processes(blocking: bool) -> Option<Vec<Arc<UserTask>>> {
+    let task_map = TASK_MAP.try_get()?;
+    let task_map = match blocking {
+        true => task_map.lock(),
+        false => task_map.try_lock()?,
+    };
+    let mut tasks: Vec<Arc<UserTask>> = task_map
+        .values()
+        .filter_map(|x| x.upgrade()?.downcast_arc::<UserTask>().ok())
+        .collect();
+    drop(task_map);
+    tasks.sort_by_key(|x| (Arc::as_ptr(&x.pcb) as usize, x.task_id != x.process_id));
+    tasks.dedup_by_key(|x| Arc::as_ptr(&x.pcb) as usize);
+    Some(tasks)
+}
@@ -0,0 +1,89 @@
+//! The out of memory killer.
+//!
+//! When reclaim can't free any frame, the process with the highest badness is killed
+//! with SIGKILL. The badness is the resident and swapped size of the process adjusted
+//! by its `oom_score_adj`. The private memory of the victim is dropped at once, so the
+//! allocating task can retry without waiting for the victim to exit.
+
+use alloc::{sync::Arc, vec::Vec};
+use devices::PAGE_SIZE;
+use libc_types::{others::OOM_SCORE_ADJ_MIN, signal::SignalNum};
+use runtime::frame::{get_free_pages, get_total_pages};
+
+use super::{swap, task::ProcessControlBlock, tlb, user_processes, UserTask};
+
+/// Get the badness of the process, the one with the highest badness is killed first.
+///
+/// Returns None if the process can't be killed, init is never killed.
+pub fn oom_badness(
+    task: &UserTask,
+    pcb: &ProcessControlBlock,
+    total_pages: usize,
+) -> Option<isize> {
+    if task.is_init() || pcb.oom_score_adj == OOM_SCORE_ADJ_MIN || pcb.exit_code.is_some() {
+        return None;
+    }
+    let pages = pcb.memset.resident_pages() + pcb.memset.swapped_pages();
+    Some(pages as isize + pcb.oom_score_adj * total_pages as isize / 1000)
+}
+
+/// Kill a process to free memory, returns false if no process can be killed.
+///
+/// The processes and threads which are busy now are skipped, the same as reclaim.
+pub fn out_of_memory() -> bool {
+    let total_pages = get_total_pages();
+    let processes = user_processes(false).unwrap_or_default();
+    let mut candidates: Vec<(isize, &Arc<UserTask>)> = processes
+        .iter()
+        .filter_map(|task| {
+            let badness = oom_badness(task, &task.pcb.try_lock()?, total_pages)?;
+            Some((badness, task))
+        })
+        .collect();
+    candidates.sort_by_key(|(badness, _)| -badness);
+
+    for (badness, victim) in candidates {
+        let Some(mut pcb) = victim.pcb.try_lock() else {
+            continue;
+        };
+        let threads: Vec<Arc<UserTask>> = pcb.threads.iter().filter_map(|x| x.upgrade()).collect();
+        let Some(mut tcbs) = threads
+            .iter()
+            .map(|x| x.tcb.try_write())
+            .collect::<Option<Vec<_>>>()
+        else {
+            continue;
+        };
+        error!(
+            "Out of memory: killed process {} badness {} rss {} kB swap {} kB oom_score_adj {}",
+            victim.process_id,
+            badness,
+            pcb.memset.resident_pages() * PAGE_SIZE / 1024,
+            pcb.memset.swapped_pages() * PAGE_SIZE / 1024,
+            pcb.oom_score_adj
+        );
+        tcbs.iter_mut()
+            .for_each(|x| x.signal.insert(SignalNum::KILL));
+        drop(tcbs);
+
+        let unmapped = pcb.memset.reap(&victim.page_table);
+        drop(pcb);
+        tlb::shootdown(&victim.page_table);
+        drop(unmapped);
+        threads.iter().for_each(|x| x.notify());
+        return true;
+    }
+    false
+}
+
+/// Reclaim at least `pages` frames for the frame allocator, kill a process if nothing
+/// can be reclaimed.
+pub fn reclaim_or_kill(pages: usize) -> usize {
+    let reclaimed = swap::reclaim(pages);
+    if reclaimed > 0 {
+        return reclaimed;
+    }
+    let free_pages = get_free_pages();
+    out_of_memory();
+    get_free_pages().saturating_sub(free_pages)
+}
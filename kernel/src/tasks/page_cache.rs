@@ -0,0 +1,333 @@
+//! Page cache of the files mapped into user space.
+//!
+//! All mappings of the same file share one [PageCache]. Pages are read from the file
+//! when they are touched first, `MAP_SHARED` mappings write to the cached frames
+//! directly and the written pages are flushed by msync, munmap or when the last
+//! mapping of the file goes away.
+//!
+//! `read`, `write` and `ftruncate` go to the file, the syscalls keep the cached pages
+//! coherent with it through [PageCache::file_read], [PageCache::file_written] and
+//! [PageCache::file_truncated].
+
+use alloc::{
+    collections::BTreeMap,
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{
+    cmp::{max, min},
+    ops::Range,
+};
+use devices::PAGE_SIZE;
+use fs::{file::File, INodeInterface};
+use libc_types::types::Stat;
+use runtime::frame::{frame_alloc, FrameTracker};
+use sync::Mutex;
+use syscalls::Errno;
+
+use super::memfd::MemFd;
+
+/// Page caches indexed by the path of the file.
+///
+/// The filesystems don't provide stable inode numbers, the path is the only identity
+/// of a file which is shared by all the opened instances. The entry is removed when
+/// the file is unlinked, a new file created at the path never finds the pages of the
+/// old one. A memfd has no entry, its pages are its own frames.
+static PAGE_CACHES: Mutex<BTreeMap<String, Weak<PageCache>>> = Mutex::new(BTreeMap::new());
+
+struct CachedPage {
+    frame: Arc<FrameTracker>,
+    /// The page was mapped writable by a shared mapping, it may differ from the file.
+    dirty: bool,
+}
+
+pub struct PageCache {
+    path: String,
+    file: Arc<dyn INodeInterface>,
+    pages: Mutex<BTreeMap<usize, CachedPage>>,
+    /// The memfd whose frames are mapped instead of the cached pages.
+    memfd: Option<Arc<MemFd>>,
+}
+
+impl PageCache {
+    /// Get the page cache of the file, create it if the file is not mapped now.
+    pub fn get(file: &File) -> Arc<PageCache> {
+        // The frames of a memfd hold its data, there is nothing to cache and write back.
+        if let Ok(memfd) = file.get_bare_file().downcast_arc::<MemFd>() {
+            return Arc::new(PageCache {
+                path: file.path(),
+                file: memfd.clone(),
+                pages: Mutex::new(BTreeMap::new()),
+                memfd: Some(memfd),
+            });
+        }
+        let key = file.path();
+        let mut caches = PAGE_CACHES.lock();
+        if let Some(cache) = caches.get(&key).and_then(Weak::upgrade) {
+            return cache;
+        }
+        caches.retain(|_, cache| cache.strong_count() > 0);
+        let cache = Arc::new(PageCache {
+            path: file.path(),
+            file: file.get_bare_file(),
+            pages: Mutex::new(BTreeMap::new()),
+            memfd: None,
+        });
+        caches.insert(key, Arc::downgrade(&cache));
+        cache
+    }
+
+    /// Get the page cache of the file if it is mapped now.
+    fn find(file: &File) -> Option<Arc<PageCache>> {
+        // The mappings of a memfd share its frames, they are always coherent.
+        if file.inner.downcast_ref::<MemFd>().is_some() {
+            return None;
+        }
+        PAGE_CACHES.lock().get(&file.path()).and_then(Weak::upgrade)
+    }
+
+    /// Write back the pages in `len` bytes at `offset` of the file before they are read,
+    /// `read` sees the data written through the shared mappings.
+    pub fn file_read(file: &File, offset: usize, len: usize) -> Result<(), Errno> {
+        match Self::find(file) {
+            Some(cache) => cache.sync(offset / PAGE_SIZE..(offset + len).div_ceil(PAGE_SIZE)),
+            None => Ok(()),
+        }
+    }
+
+    /// Copy the data written to the file at `offset` to the cached pages, the mappings
+    /// see the data written by `write`.
+    pub fn file_written(file: &File, offset: usize, buffer: &[u8]) {
+        let Some(cache) = Self::find(file) else {
+            return;
+        };
+        let end = offset + buffer.len();
+        let pages = cache.pages.lock();
+        for (index, page) in pages.range(offset / PAGE_SIZE..end.div_ceil(PAGE_SIZE)) {
+            let page_start = index * PAGE_SIZE;
+            let start = max(offset, page_start);
+            let stop = min(end, page_start + PAGE_SIZE);
+            page.frame.0.slice_mut_with_len(PAGE_SIZE)[start - page_start..stop - page_start]
+                .copy_from_slice(&buffer[start - offset..stop - offset]);
+        }
+    }
+
+    /// Drop the cached pages beyond the new end of the file.
+    ///
+    /// The pages which are still mapped can't be dropped, the part beyond the end is
+    /// filled with zero like the data a later extension of the file reads.
+    pub fn file_truncated(file: &File, size: usize) {
+        let Some(cache) = Self::find(file) else {
+            return;
+        };
+        let mut pages = cache.pages.lock();
+        pages.retain(|index, page| index * PAGE_SIZE < size || Arc::strong_count(&page.frame) > 1);
+        for (index, page) in pages.range(size / PAGE_SIZE..) {
+            let start = size.saturating_sub(index * PAGE_SIZE);
+            page.frame.0.slice_mut_with_len(PAGE_SIZE)[start..].fill(0);
+        }
+    }
+
+    /// Forget the page cache of the unlinked file.
+    ///
+    /// The mappings keep the pages and write them back to the unlinked file, a new file
+    /// created at the same path gets a new page cache.
+    pub fn file_removed(file: &File) {
+        PAGE_CACHES.lock().remove(&file.path());
+    }
+
+    /// Get the path of the cached file.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Check if the page cache caches the file of `inode`.
+    pub fn is_file(&self, inode: &Arc<dyn INodeInterface>) -> bool {
+        Arc::ptr_eq(&self.file, inode)
+    }
+
+    /// Get the frame which caches the page at `index` of the file.
+    ///
+    /// The page is read from the file if it is not cached, the part beyond the end of
+    /// the file is filled with zero.
+    pub fn get_page(&self, index: usize) -> Result<Arc<FrameTracker>, Errno> {
+        if let Some(memfd) = &self.memfd {
+            return memfd.get_page(index);
+        }
+        let mut pages = self.pages.lock();
+        if let Some(page) = pages.get(&index) {
+            return Ok(page.frame.clone());
+        }
+        let frame = Arc::new(frame_alloc().ok_or(Errno::ENOMEM)?);
+        self.file
+            .readat(index * PAGE_SIZE, frame.0.slice_mut_with_len(PAGE_SIZE))?;
+        pages.insert(
+            index,
+            CachedPage {
+                frame: frame.clone(),
+                dirty: false,
+            },
+        );
+        Ok(frame)
+    }
+
+    /// Check if the page at `index` was written through a shared mapping.
+    ///
+    /// The pages of a memfd are always dirty, they are written without a trap.
+    pub fn is_dirty(&self, index: usize) -> bool {
+        if self.memfd.is_some() {
+            return true;
+        }
+        self.pages.lock().get(&index).is_some_and(|x| x.dirty)
+    }
+
+    /// Mark the page at `index` written, it will be written back by [PageCache::sync].
+    pub fn set_dirty(&self, index: usize) {
+        if let Some(page) = self.pages.lock().get_mut(&index) {
+            page.dirty = true;
+        }
+    }
+
+    /// Check if `frame` is the cached page at `index`, not a private copy of it.
+    pub fn is_cached(&self, index: usize, frame: &Arc<FrameTracker>) -> bool {
+        if let Some(memfd) = &self.memfd {
+            return memfd.is_page(index, frame);
+        }
+        self.pages
+            .lock()
+            .get(&index)
+            .is_some_and(|x| Arc::ptr_eq(&x.frame, frame))
+    }
+
+    /// Check if `frame` is the cached page at `index` and it was not written.
+    ///
+    /// This is used by the reclaim, a busy page cache is treated as dirty. The pages of a
+    /// memfd are never clean, unmapping them frees nothing.
+    pub fn is_clean(&self, index: usize, frame: &Arc<FrameTracker>) -> bool {
+        self.memfd.is_none()
+            && self.pages.try_lock().is_some_and(|pages| {
+                pages
+                    .get(&index)
+                    .is_some_and(|x| !x.dirty && Arc::ptr_eq(&x.frame, frame))
+            })
+    }
+
+    /// Drop up to `count` clean pages which are not mapped anywhere.
+    ///
+    /// Returns the number of the freed frames.
+    fn shrink(&self, count: usize) -> usize {
+        let Some(mut pages) = self.pages.try_lock() else {
+            return 0;
+        };
+        let unused: Vec<usize> = pages
+            .iter()
+            .filter(|(_, x)| !x.dirty && Arc::strong_count(&x.frame) == 1)
+            .map(|(index, _)| *index)
+            .take(count)
+            .collect();
+        unused.iter().for_each(|index| {
+            pages.remove(index);
+        });
+        unused.len()
+    }
+
+    /// Drop up to `count` clean and unmapped pages from all the page caches.
+    ///
+    /// Returns the number of the freed frames.
+    pub fn shrink_all(count: usize) -> usize {
+        let caches: Vec<Arc<PageCache>> = match PAGE_CACHES.try_lock() {
+            Some(caches) => caches.values().filter_map(Weak::upgrade).collect(),
+            None => return 0,
+        };
+        let mut freed = 0;
+        for cache in caches.iter() {
+            if freed >= count {
+                break;
+            }
+            freed += cache.shrink(count - freed);
+        }
+        freed
+    }
+
+    /// Write the dirty pages in the range of page indexes back to the file.
+    ///
+    /// The pages no mapping holds become clean. The mapped pages stay dirty, they may
+    /// be mapped writable and written again, see [PageCache::clean].
+    pub fn sync(&self, range: Range<usize>) -> Result<(), Errno> {
+        let file_size = self.file_size()?;
+        let mut pages = self.pages.lock();
+        for (index, page) in pages.range_mut(range).filter(|(_, x)| x.dirty) {
+            self.write_page(*index, &page.frame, file_size)?;
+            page.dirty = Arc::strong_count(&page.frame) > 1;
+        }
+        Ok(())
+    }
+
+    /// Mark the dirty page at `index` clean if `frame` is it and only one mapping
+    /// holds it.
+    ///
+    /// The caller write-protects the mapping and flushes the TLB before it writes the
+    /// page back by [PageCache::write_back], a later write marks the page dirty again.
+    pub fn clean(&self, index: usize, frame: &Arc<FrameTracker>) -> bool {
+        if self.memfd.is_some() {
+            return false;
+        }
+        match self.pages.lock().get_mut(&index) {
+            Some(page)
+                if page.dirty
+                    && Arc::ptr_eq(&page.frame, frame)
+                    && Arc::strong_count(frame) == 2 =>
+            {
+                page.dirty = false;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Write the page at `index` back to the file even if it is clean.
+    pub fn write_back(&self, index: usize) -> Result<(), Errno> {
+        let file_size = self.file_size()?;
+        match self.pages.lock().get(&index) {
+            Some(page) => self.write_page(index, &page.frame, file_size),
+            None => Ok(()),
+        }
+    }
+
+    fn file_size(&self) -> Result<usize, Errno> {
+        let mut stat = Stat::default();
+        self.file.stat(&mut stat)?;
+        Ok(stat.size as usize)
+    }
+
+    fn write_page(
+        &self,
+        index: usize,
+        frame: &FrameTracker,
+        file_size: usize,
+    ) -> Result<(), Errno> {
+        let offset = index * PAGE_SIZE;
+        // Don't extend the file with the padding of the last page.
+        if offset >= file_size {
+            return Ok(());
+        }
+        let len = min(PAGE_SIZE, file_size - offset);
+        self.file.writeat(offset, frame.0.slice_mut_with_len(len))?;
+        Ok(())
+    }
+
+    /// Flush the file to the storage, the pages written back by [PageCache::sync] may
+    /// still be buffered by the filesystem.
+    pub fn flush(&self) -> Result<(), Errno> {
+        self.file.flush()
+    }
+}
+
+impl Drop for PageCache {
+    fn drop(&mut self) {
+        if let Err(err) = self.sync(0..usize::MAX) {
+            warn!("can't write back the mapped file: {:?}", err);
+        }
+    }
+}
@@ -0,0 +1,376 @@
+//! The directories of the processes in the procfs, `/proc/<pid>` and `/proc/self`.
+
+use alloc::{
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{cmp::min, fmt::Write};
+use devices::PAGE_SIZE;
+use executor::{current_task, tid2task};
+use fs::{FileType, INodeInterface};
+use libc_types::{
+    others::{OOM_SCORE_ADJ_MAX, OOM_SCORE_ADJ_MIN},
+    types::{Stat, StatMode},
+};
+use polyhal::MappingFlags;
+use procfs::{set_process_hooks, ProcessHooks};
+use runtime::frame::get_total_pages;
+use syscalls::Errno;
+use vfscore::{DirEntry, VfsResult};
+
+use super::{
+    oom::oom_badness, task::ProcessControlBlock, user_processes, MemArea, MemType, UserTask,
+};
+
+pub fn init() {
+    set_process_hooks(ProcessHooks {
+        pids: || {
+            user_processes(true)
+                .unwrap_or_default()
+                .iter()
+                .map(|x| x.process_id)
+                .collect()
+        },
+        lookup,
+    });
+}
+
+fn lookup(name: &str) -> Option<Arc<dyn INodeInterface>> {
+    let task = match name {
+        "self" => current_task(),
+        _ => tid2task(name.parse().ok()?)?,
+    };
+    let task = task.downcast_arc::<UserTask>().ok()?;
+    Some(Arc::new(ProcessDir {
+        task: Arc::downgrade(&task),
+    }))
+}
+
+/// The files in the directory of a process.
+#[derive(Clone, Copy)]
+enum ProcessEntry {
+    /// The badness of the process scaled to 0..=1000, read only.
+    OomScore,
+    /// The adjustment of the badness, -1000 makes the process unkillable.
+    OomScoreAdj,
+    /// The state and the memory usage of the process.
+    Status,
+    /// The memory usage of the process in pages.
+    Statm,
+    /// The memory usage of every mapping of the process.
+    Smaps,
+}
+
+impl ProcessEntry {
+    const ALL: [ProcessEntry; 5] = [
+        ProcessEntry::OomScore,
+        ProcessEntry::OomScoreAdj,
+        ProcessEntry::Status,
+        ProcessEntry::Statm,
+        ProcessEntry::Smaps,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            ProcessEntry::OomScore => "oom_score",
+            ProcessEntry::OomScoreAdj => "oom_score_adj",
+            ProcessEntry::Status => "status",
+            ProcessEntry::Statm => "statm",
+            ProcessEntry::Smaps => "smaps",
+        }
+    }
+
+    fn read(&self, task: &UserTask) -> String {
+        let mut pcb = task.pcb.lock();
+        let value = match self {
+            ProcessEntry::Status => return status(task, &mut pcb),
+            ProcessEntry::Statm => return statm(&pcb),
+            ProcessEntry::Smaps => return smaps(&pcb),
+            ProcessEntry::OomScore => {
+                let total_pages = get_total_pages();
+                oom_badness(task, &pcb, total_pages).map_or(0, |x| {
+                    x.clamp(0, total_pages as isize) * 1000 / total_pages as isize
+                })
+            }
+            ProcessEntry::OomScoreAdj => pcb.oom_score_adj,
+        };
+        value.to_string() + "\n"
+    }
+
+    fn write(&self, task: &UserTask, buffer: &[u8]) -> VfsResult<usize> {
+        match self {
+            ProcessEntry::OomScore
+            | ProcessEntry::Status
+            | ProcessEntry::Statm
+            | ProcessEntry::Smaps => Err(Errno::EACCES),
+            ProcessEntry::OomScoreAdj => {
+                let value: isize = core::str::from_utf8(buffer)
+                    .map_err(|_| Errno::EINVAL)?
+                    .trim()
+                    .parse()
+                    .map_err(|_| Errno::EINVAL)?;
+                if !(OOM_SCORE_ADJ_MIN..=OOM_SCORE_ADJ_MAX).contains(&value) {
+                    return Err(Errno::EINVAL);
+                }
+                task.pcb.lock().oom_score_adj = value;
+                Ok(buffer.len())
+            }
+        }
+    }
+}
+
+/// The sizes of the kinds of the mappings in bytes, as VmExe, VmStk and VmData.
+struct SegmentSizes {
+    exe: usize,
+    stack: usize,
+    data: usize,
+}
+
+fn segment_sizes(pcb: &ProcessControlBlock) -> SegmentSizes {
+    let mut sizes = SegmentSizes {
+        exe: 0,
+        stack: 0,
+        data: 0,
+    };
+    for area in pcb.memset.iter() {
+        match area.mtype {
+            MemType::Stack => sizes.stack += area.len,
+            MemType::CodeSection if area.file.is_some() && !area.prot.contains(MappingFlags::W) => {
+                sizes.exe += area.len
+            }
+            MemType::Shared | MemType::ShareFile => {}
+            _ if area.prot.contains(MappingFlags::W) => sizes.data += area.len,
+            _ => {}
+        }
+    }
+    sizes
+}
+
+fn status(task: &UserTask, pcb: &mut ProcessControlBlock) -> String {
+    pcb.sample_peak();
+    let usage = pcb.memory_usage();
+    let sizes = segment_sizes(pcb);
+    let ppid = task.parent.read().upgrade().map_or(0, |x| x.process_id);
+    let state = match pcb.exit_code {
+        Some(_) => "Z (zombie)",
+        None => "R (running)",
+    };
+    let mut str = String::new();
+    writeln!(str, "State:\t{}", state).unwrap();
+    writeln!(str, "Tgid:\t{}", task.process_id).unwrap();
+    writeln!(str, "Pid:\t{}", task.process_id).unwrap();
+    writeln!(str, "PPid:\t{}", ppid).unwrap();
+    [
+        ("VmPeak:", pcb.peak_vm / 1024),
+        ("VmSize:", usage.size / 1024),
+        ("VmHWM:", pcb.peak_rss * PAGE_SIZE / 1024),
+        ("VmRSS:", usage.resident() * PAGE_SIZE / 1024),
+        ("RssAnon:", usage.anon * PAGE_SIZE / 1024),
+        ("RssFile:", usage.file * PAGE_SIZE / 1024),
+        ("RssShmem:", usage.shmem * PAGE_SIZE / 1024),
+        ("VmData:", sizes.data / 1024),
+        ("VmStk:", sizes.stack / 1024),
+        ("VmExe:", sizes.exe / 1024),
+        ("VmSwap:", usage.swapped * PAGE_SIZE / 1024),
+    ]
+    .iter()
+    .for_each(|(name, kb)| {
+        writeln!(str, "{:<12}{:>8} kB", name, kb).unwrap();
+    });
+    let threads = pcb.threads.iter().filter(|x| x.strong_count() > 0).count();
+    writeln!(str, "Threads:\t{}", threads).unwrap();
+    str
+}
+
+fn statm(pcb: &ProcessControlBlock) -> String {
+    let usage = pcb.memory_usage();
+    let sizes = segment_sizes(pcb);
+    alloc::format!(
+        "{} {} {} {} 0 {} 0\n",
+        usage.size / PAGE_SIZE,
+        usage.resident(),
+        usage.file + usage.shmem,
+        sizes.exe / PAGE_SIZE,
+        (sizes.data + sizes.stack) / PAGE_SIZE
+    )
+}
+
+/// Get the name shown at the end of the header of a mapping in maps and smaps.
+fn area_name(area: &MemArea) -> &str {
+    match (&area.file, area.mtype) {
+        (Some(cache), _) => cache.path(),
+        (None, MemType::Stack) => "[stack]",
+        (None, MemType::CodeSection) => "[heap]",
+        _ => "",
+    }
+}
+
+fn smaps(pcb: &ProcessControlBlock) -> String {
+    let mut areas: Vec<&MemArea> = pcb.memset.iter().collect();
+    areas.sort_by_key(|x| x.start);
+    let mut str = String::new();
+    for area in areas {
+        let perm = |flag, c| match area.prot.contains(flag) {
+            true => c,
+            false => '-',
+        };
+        let shared = match area.mtype {
+            MemType::Shared | MemType::ShareFile => 's',
+            _ => 'p',
+        };
+        writeln!(
+            str,
+            "{:08x}-{:08x} {}{}{}{} {:08x} 00:00 0 {}",
+            area.start,
+            area.start + area.len,
+            perm(MappingFlags::R, 'r'),
+            perm(MappingFlags::W, 'w'),
+            perm(MappingFlags::X, 'x'),
+            shared,
+            area.offset,
+            area_name(area)
+        )
+        .unwrap();
+        let usage = area.usage();
+        [
+            ("Size:", usage.size / 1024),
+            ("KernelPageSize:", PAGE_SIZE / 1024),
+            ("MMUPageSize:", PAGE_SIZE / 1024),
+            ("Rss:", usage.resident() * PAGE_SIZE / 1024),
+            ("Pss:", usage.pss / 1024),
+            ("Shared_Clean:", usage.shared_clean * PAGE_SIZE / 1024),
+            ("Shared_Dirty:", usage.shared_dirty * PAGE_SIZE / 1024),
+            ("Private_Clean:", usage.private_clean * PAGE_SIZE / 1024),
+            ("Private_Dirty:", usage.private_dirty * PAGE_SIZE / 1024),
+            ("Anonymous:", usage.anon * PAGE_SIZE / 1024),
+            ("AnonHugePages:", usage.huge * PAGE_SIZE / 1024),
+            ("Swap:", usage.swapped * PAGE_SIZE / 1024),
+        ]
+        .iter()
+        .for_each(|(name, kb)| {
+            writeln!(str, "{:<16}{:>8} kB", name, kb).unwrap();
+        });
+    }
+    for shm in pcb.shms.iter() {
+        writeln!(
+            str,
+            "{:08x}-{:08x} r{}{}s 00000000 00:00 {} /SYSV{:08x}",
+            shm.start,
+            shm.start + shm.size,
+            if shm.flags.contains(MappingFlags::W) {
+                'w'
+            } else {
+                '-'
+            },
+            if shm.flags.contains(MappingFlags::X) {
+                'x'
+            } else {
+                '-'
+            },
+            shm.shmid,
+            shm.mem.info.lock().perm.key
+        )
+        .unwrap();
+        let rss = shm.mem.trackers.len() * PAGE_SIZE / 1024;
+        [("Size:", shm.size / 1024), ("Rss:", rss)]
+            .iter()
+            .for_each(|(name, kb)| {
+                writeln!(str, "{:<16}{:>8} kB", name, kb).unwrap();
+            });
+    }
+    str
+}
+
+/// The directory of a process, the files are gone after the process is released.
+pub struct ProcessDir {
+    task: Weak<UserTask>,
+}
+
+impl INodeInterface for ProcessDir {
+    fn lookup(&self, name: &str) -> VfsResult<Arc<dyn INodeInterface>> {
+        let entry = ProcessEntry::ALL
+            .into_iter()
+            .find(|x| x.name() == name)
+            .ok_or(Errno::ENOENT)?;
+        Ok(Arc::new(ProcessFile {
+            task: self.task.clone(),
+            entry,
+        }))
+    }
+
+    fn create(&self, name: &str, _ty: FileType) -> VfsResult<()> {
+        // Opening a file with O_CREAT, e.g. a redirection of the shell, finds it.
+        match ProcessEntry::ALL.iter().any(|x| x.name() == name) {
+            true => Ok(()),
+            false => Err(Errno::EACCES),
+        }
+    }
+
+    fn read_dir(&self) -> VfsResult<Vec<DirEntry>> {
+        Ok(ProcessEntry::ALL
+            .iter()
+            .map(|x| DirEntry {
+                filename: x.name().to_string(),
+                len: 0,
+                file_type: FileType::File,
+            })
+            .collect())
+    }
+
+    fn stat(&self, stat: &mut Stat) -> VfsResult<()> {
+        stat.dev = 0;
+        stat.ino = 1; // TODO: convert path to number(ino)
+        stat.mode = StatMode::DIR; // TODO: add access mode
+        stat.nlink = 1;
+        stat.uid = 0;
+        stat.gid = 0;
+        stat.size = 0;
+        stat.blksize = 512;
+        stat.blocks = 0;
+        stat.rdev = 0; // TODO: add device id
+        Ok(())
+    }
+}
+
+pub struct ProcessFile {
+    task: Weak<UserTask>,
+    entry: ProcessEntry,
+}
+
+impl INodeInterface for ProcessFile {
+    fn readat(&self, offset: usize, buffer: &mut [u8]) -> VfsResult<usize> {
+        let task = self.task.upgrade().ok_or(Errno::ESRCH)?;
+        let content = self.entry.read(&task);
+        let bytes = content.as_bytes();
+        if offset >= bytes.len() {
+            return Ok(0);
+        }
+        let rsize = min(bytes.len() - offset, buffer.len());
+        buffer[..rsize].copy_from_slice(&bytes[offset..offset + rsize]);
+        Ok(rsize)
+    }
+
+    fn writeat(&self, _offset: usize, buffer: &[u8]) -> VfsResult<usize> {
+        let task = self.task.upgrade().ok_or(Errno::ESRCH)?;
+        self.entry.write(&task, buffer)
+    }
+
+    fn truncate(&self, _size: usize) -> VfsResult<()> {
+        Ok(())
+    }
+
+    fn stat(&self, stat: &mut Stat) -> VfsResult<()> {
+        stat.dev = 0;
+        stat.ino = 1; // TODO: convert path to number(ino)
+        stat.mode = StatMode::FILE; // TODO: add access mode
+        stat.nlink = 1;
+        stat.uid = 0;
+        stat.gid = 0;
+        stat.size = 0;
+        stat.blksize = 512;
+        stat.blocks = 0;
+        stat.rdev = 0; // TODO: add device id
+        Ok(())
+    }
+}
@@ -1,36 +1,81 @@
 use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use devices::PAGE_SIZE;
+use libc_types::shm::{IpcPerm, ShmidDs, SHM_DEST};
+use polyhal::{timer::current_time, MappingFlags};
 use runtime::frame::FrameTracker;
 use sync::Mutex;
 
+/// SysV shared memory segments indexed by the shmid.
 pub static SHARED_MEMORY: Mutex<BTreeMap<usize, Arc<SharedMemory>>> = Mutex::new(BTreeMap::new());
 
 pub struct SharedMemory {
     pub trackers: Vec<Arc<FrameTracker>>,
-    pub deleted: Mutex<bool>,
+    /// The shmid_ds reported by IPC_STAT, nattch counts the [MapedSharedMemory].
+    pub info: Mutex<ShmidDs>,
 }
 
 impl SharedMemory {
-    pub const fn new(trackers: Vec<Arc<FrameTracker>>) -> Self {
+    pub fn new(trackers: Vec<Arc<FrameTracker>>, perm: IpcPerm, size: usize, pid: usize) -> Self {
         Self {
             trackers,
-            deleted: Mutex::new(false),
+            info: Mutex::new(ShmidDs {
+                perm,
+                segsz: size,
+                ctime: current_time().as_secs() as _,
+                cpid: pid as _,
+                ..Default::default()
+            }),
         }
     }
 }
 
-#[derive(Clone)]
+/// A segment attached to a process.
+///
+/// The attach count of the segment follows the instances, the segment removed by IPC_RMID
+/// is freed when the last one is dropped.
 pub struct MapedSharedMemory {
-    pub key: usize,
+    pub shmid: usize,
     pub mem: Arc<SharedMemory>,
     pub start: usize,
     pub size: usize,
+    /// The flags the pages are mapped with, SHM_RDONLY leaves out the write permission.
+    pub flags: MappingFlags,
+}
+
+impl MapedSharedMemory {
+    pub fn new(shmid: usize, mem: Arc<SharedMemory>, start: usize, flags: MappingFlags) -> Self {
+        mem.info.lock().nattch += 1;
+        Self {
+            shmid,
+            size: mem.trackers.len() * PAGE_SIZE,
+            mem,
+            start,
+            flags,
+        }
+    }
+}
+
+impl Clone for MapedSharedMemory {
+    fn clone(&self) -> Self {
+        self.mem.info.lock().nattch += 1;
+        Self {
+            shmid: self.shmid,
+            mem: self.mem.clone(),
+            start: self.start,
+            size: self.size,
+            flags: self.flags,
+        }
+    }
 }
 
 impl Drop for MapedSharedMemory {
     fn drop(&mut self) {
-        // self.mem.trackers.remove(self.key);
-        if Arc::strong_count(&self.mem) == 2 && *self.mem.deleted.lock() == true {
-            SHARED_MEMORY.lock().remove(&self.key);
+        let mut info = self.mem.info.lock();
+        info.nattch -= 1;
+        info.dtime = current_time().as_secs() as _;
+        if info.nattch == 0 && info.perm.mode & SHM_DEST != 0 {
+            drop(info);
+            SHARED_MEMORY.lock().remove(&self.shmid);
         }
     }
 }
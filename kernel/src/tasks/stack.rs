@@ -1,37 +1,65 @@
 use alloc::{collections::btree_map::BTreeMap, string::String, sync::Arc, vec::Vec};
+use core::mem::size_of;
 use devices::PAGE_SIZE;
 use executor::AsyncTask;
 use libc_types::elf::AuxType;
+use polyhal::MappingFlags;
 use polyhal_trap::trapframe::{TrapFrame, TrapFrameArgs};
+use runtime::frame::{alignup, FrameTracker};
 
 use crate::{
-    consts::{USER_STACK_INIT_SIZE, USER_STACK_TOP},
-    tasks::MemType,
+    consts::USER_STACK_INIT_SIZE, syscall::types::signal::SignalUserContext, tasks::MemType,
+    utils::random::fill_random,
 };
 
 use super::UserTask;
 
+/// The most entries of the auxiliary vector init_task_stack pushes.
+const AUXV_MAX: usize = 32;
+
+/// Get the most bytes init_task_stack pushes for the arguments and the environment.
+pub fn init_stack_size(args: &[String], envp: &[String], path: &str) -> usize {
+    const ULEN: usize = size_of::<usize>();
+    let strings: usize = args
+        .iter()
+        .chain(envp)
+        .map(|x| x.as_str())
+        .chain([path, PLATFORM])
+        .map(|x| alignup(x.len() + 1, ULEN))
+        .sum();
+    // The random bytes, the pointers and argc, aligned to 16 bytes.
+    let words = 2 * (AUXV_MAX + 1) + (envp.len() + 1) + (args.len() + 1) + 1;
+    strings + alignup(16 + 1, ULEN) + words * ULEN + 16
+}
+
 pub fn init_task_stack(
     user_task: Arc<UserTask>,
     args: Vec<String>,
+    envp: Vec<String>,
     base: usize,
+    interp_base: usize,
     path: &str,
     entry_point: usize,
+    pc: usize,
+    stack_top: usize,
     ph_count: usize,
     ph_entry_size: usize,
     ph_addr: usize,
     heap_bottom: usize,
+    stack: Vec<FrameTracker>,
 ) {
-    // map stack
-    user_task.frame_alloc(
-        va!(USER_STACK_TOP - USER_STACK_INIT_SIZE),
+    // map the stack reserved by exec
+    user_task.map_frames(
+        va!(stack_top - USER_STACK_INIT_SIZE),
         MemType::Stack,
-        USER_STACK_INIT_SIZE / PAGE_SIZE,
+        MappingFlags::R | MappingFlags::W,
+        stack,
     );
     log::debug!(
-        "[task {}] entry: {:#x}",
+        "[task {}] entry: {:#x} pc: {:#x}",
         user_task.get_task_id(),
-        base + entry_point
+        base + entry_point,
+        pc
     );
     user_task.inner_map(|inner| {
         inner.heap = heap_bottom;
@@ -41,22 +69,17 @@ pub fn init_task_stack(
     let mut tcb = user_task.tcb.write();
 
     tcb.cx = TrapFrame::new();
-    tcb.cx[TrapFrameArgs::SP] = USER_STACK_TOP; // stack top;
-    tcb.cx[TrapFrameArgs::SEPC] = base + entry_point;
+    tcb.cx[TrapFrameArgs::SP] = stack_top; // stack top;
+    tcb.cx[TrapFrameArgs::SEPC] = pc;
 
     drop(tcb);
 
     // push stack
-    let envp = vec![
-        "LD_LIBRARY_PATH=/",
-        "PS1=\x1b[1m\x1b[32mByteOS\x1b[0m:\x1b[1m\x1b[34m\\w\x1b[0m\\$ \0",
-        "PATH=/:/bin:/usr/bin",
-        "UB_BINDIR=./",
-    ];
+    let execfn = user_task.push_str(path);
     let envp: Vec<usize> = envp
         .into_iter()
         .rev()
-        .map(|x| user_task.push_str(x))
+        .map(|x| user_task.push_str(&x))
         .collect();
     let args: Vec<usize> = args
         .into_iter()
@@ -64,25 +87,41 @@ pub fn init_task_stack(
         .map(|x| user_task.push_str(&x))
         .collect();
 
-    let random_ptr = user_task.push_arr(&[0u8; 16]);
+    let mut random = [0u8; 16];
+    fill_random(&mut random);
+    let random_ptr = user_task.push_arr(&random);
     let mut auxv = BTreeMap::new();
-    auxv.insert(AuxType::Platform, user_task.push_str("riscv"));
-    auxv.insert(AuxType::ExecFn, user_task.push_str(path));
+    auxv.insert(AuxType::Platform, user_task.push_str(PLATFORM));
+    auxv.insert(AuxType::ExecFn, execfn);
     auxv.insert(AuxType::Phnum, ph_count);
     auxv.insert(AuxType::PageSize, PAGE_SIZE);
+    auxv.insert(AuxType::Base, interp_base);
     auxv.insert(AuxType::Entry, base + entry_point);
     auxv.insert(AuxType::Phent, ph_entry_size);
     auxv.insert(AuxType::Phdr, base + ph_addr);
+    auxv.insert(AuxType::HwCap, hwcap());
+    auxv.insert(AuxType::ClkTck, CLOCK_TICKS);
     auxv.insert(AuxType::GID, 0);
     auxv.insert(AuxType::EGID, 0);
     auxv.insert(AuxType::UID, 0);
     auxv.insert(AuxType::EUID, 0);
     auxv.insert(AuxType::Secure, 0);
     auxv.insert(AuxType::Random, random_ptr);
+    auxv.insert(
+        AuxType::MinSigStkSz,
+        alignup(size_of::<SignalUserContext>(), 16),
+    );
 
-    // auxv top
+    // argc must be at a 16 bytes aligned address.
+    let words = 2 * (auxv.len() + 1) + (envp.len() + 1) + (args.len() + 1) + 1;
+    let mut tcb = user_task.tcb.write();
+    let sp = (tcb.cx[TrapFrameArgs::SP] - words * size_of::<usize>()) & !0xf;
+    tcb.cx[TrapFrameArgs::SP] = sp + words * size_of::<usize>();
+    drop(tcb);
+
+    // auxv top, AT_NULL
+    user_task.push(0);
     user_task.push(0);
-    // TODO: push auxv
     auxv.iter().for_each(|(key, v)| {
         user_task.push(*v);
         user_task.push(*key as usize);
@@ -94,3 +133,45 @@ pub fn init_task_stack(
     args.iter().for_each(|x| user_task.push(*x));
     user_task.push(args.len());
 }
+
+/// The value of `AT_PLATFORM`.
+#[cfg(target_arch = "riscv64")]
+const PLATFORM: &str = "riscv64";
+#[cfg(target_arch = "aarch64")]
+const PLATFORM: &str = "aarch64";
+#[cfg(target_arch = "x86_64")]
+const PLATFORM: &str = "x86_64";
+#[cfg(target_arch = "loongarch64")]
+const PLATFORM: &str = "loongarch";
+
+/// The value of `AT_CLKTCK`, the frequency `times` reports in.
+const CLOCK_TICKS: usize = 100;
+
+/// Get the value of `AT_HWCAP`, the hardware capabilities of the user space.
+#[cfg(target_arch = "riscv64")]
+fn hwcap() -> usize {
+    // One bit for every single letter extension, RV64IMAFDC.
+    b"IMAFDC".iter().fold(0, |acc, x| acc | 1 << (x - b'A'))
+}
+
+#[cfg(target_arch = "aarch64")]
+fn hwcap() -> usize {
+    const HWCAP_FP: usize = 1 << 0;
+    const HWCAP_ASIMD: usize = 1 << 1;
+    HWCAP_FP | HWCAP_ASIMD
+}
+
+#[cfg(target_arch = "x86_64")]
+fn hwcap() -> usize {
+    // The EDX of CPUID leaf 1, the same as linux.
+    unsafe { core::arch::x86_64::__cpuid(1).edx as usize }
+}
+
+#[cfg(target_arch = "loongarch64")]
+fn hwcap() -> usize {
+    const HWCAP_LOONGARCH_CPUCFG: usize = 1 << 0;
+    const HWCAP_LOONGARCH_LAM: usize = 1 << 1;
+    const HWCAP_LOONGARCH_UAL: usize = 1 << 2;
+    const HWCAP_LOONGARCH_FPU: usize = 1 << 3;
+    HWCAP_LOONGARCH_CPUCFG | HWCAP_LOONGARCH_LAM | HWCAP_LOONGARCH_UAL | HWCAP_LOONGARCH_FPU
+}
@@ -0,0 +1,259 @@
+//! Swap areas and the reclaim of user pages.
+//!
+//! When the frame allocator runs out of frames, [reclaim] drops the clean file pages
+//! which can be read from the files again, then writes the private pages of the
+//! processes to the swap areas enabled by swapon. A swapped page is recorded in its
+//! memory area and read back on the next page fault.
+//!
+//! Reclaim runs inside a failed allocation, the allocating task may hold the lock of
+//! its process, of a page cache or of a filesystem. Reclaim only tries the locks of
+//! the processes and the page caches and skips the busy ones. The swap areas are
+//! partitions written through the block device, never files in a filesystem.
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+use core::{
+    cmp::min,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use devices::{BlkDriver, PAGE_SIZE};
+use polyhal::VirtAddr;
+use runtime::frame::{frame_alloc, FrameTracker, SWAP_FREE_PAGES, SWAP_TOTAL_PAGES};
+use sync::Mutex;
+use syscalls::Errno;
+
+use super::{page_cache::PageCache, tlb, user_processes, UserTask};
+
+/// The signature at the end of the header page written by mkswap.
+const SWAP_SIGNATURE: &[u8] = b"SWAPSPACE2";
+/// The offset of the header version, after the boot sectors.
+const SWAP_VERSION_OFFSET: usize = 1024;
+/// The offset of the index of the last usable page.
+const SWAP_LAST_PAGE_OFFSET: usize = 1028;
+/// The size of a sector of the block devices.
+const SECTOR_SIZE: usize = 0x200;
+
+/// Read the page at `index` of the swap device.
+fn read_page(device: &Arc<dyn BlkDriver>, index: usize, buf: &mut [u8]) {
+    device.read_blocks(index * PAGE_SIZE / SECTOR_SIZE, buf);
+}
+
+/// Write the page at `index` of the swap device.
+fn write_page(device: &Arc<dyn BlkDriver>, index: usize, buf: &[u8]) {
+    device.write_blocks(index * PAGE_SIZE / SECTOR_SIZE, buf);
+}
+
+struct SwapArea {
+    /// The unique id of the area, [SwapEntry] refers to the area by it.
+    id: usize,
+    path: String,
+    /// The swap partition, the pages are written to the block device directly.
+    device: Arc<dyn BlkDriver>,
+    /// Whether the slots are used, the first one is the swap header.
+    slots: Vec<bool>,
+    free: usize,
+    /// swapoff is reading the pages back, no page is written to the area.
+    closing: bool,
+}
+
+static SWAP_AREAS: Mutex<Vec<SwapArea>> = Mutex::new(Vec::new());
+static NEXT_AREA_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A page written to a swap area, the slot is freed when the entry is dropped.
+pub struct SwapEntry {
+    area: usize,
+    slot: usize,
+}
+
+impl SwapEntry {
+    /// Get the id of the swap area which holds the page.
+    pub fn area(&self) -> usize {
+        self.area
+    }
+}
+
+impl Drop for SwapEntry {
+    fn drop(&mut self) {
+        let mut areas = SWAP_AREAS.lock();
+        if let Some(area) = areas.iter_mut().find(|x| x.id == self.area) {
+            area.slots[self.slot] = false;
+            area.free += 1;
+            SWAP_FREE_PAGES.fetch_add(1, Ordering::AcqRel);
+        }
+    }
+}
+
+/// Enable the swap partition at `path`, the device must be prepared by mkswap.
+///
+/// Swap files are not supported, reclaim runs inside an allocation which may hold the
+/// locks of the filesystem and can't write through it.
+pub fn swapon(path: String, device: Arc<dyn BlkDriver>) -> Result<(), Errno> {
+    let mut header = vec![0u8; PAGE_SIZE];
+    read_page(&device, 0, &mut header);
+    let read_u32 =
+        |offset: usize| u32::from_ne_bytes(header[offset..offset + 4].try_into().unwrap()) as usize;
+    if !header.ends_with(SWAP_SIGNATURE) || read_u32(SWAP_VERSION_OFFSET) != 1 {
+        return Err(Errno::EINVAL);
+    }
+    let pages = min(
+        read_u32(SWAP_LAST_PAGE_OFFSET) + 1,
+        device.capacity() / PAGE_SIZE,
+    );
+    if pages < 2 {
+        return Err(Errno::EINVAL);
+    }
+
+    let mut areas = SWAP_AREAS.lock();
+    if areas.iter().any(|x| x.path == path) {
+        return Err(Errno::EBUSY);
+    }
+    let mut slots = vec![false; pages];
+    slots[0] = true;
+    info!("swapon {}: {} pages", path, pages - 1);
+    areas.push(SwapArea {
+        id: NEXT_AREA_ID.fetch_add(1, Ordering::Relaxed),
+        path,
+        device,
+        slots,
+        free: pages - 1,
+        closing: false,
+    });
+    SWAP_TOTAL_PAGES.fetch_add(pages - 1, Ordering::AcqRel);
+    SWAP_FREE_PAGES.fetch_add(pages - 1, Ordering::AcqRel);
+    Ok(())
+}
+
+/// Disable the swap area at `path`, the swapped pages are read back into the processes.
+pub fn swapoff(path: &str) -> Result<(), Errno> {
+    let id = {
+        let mut areas = SWAP_AREAS.lock();
+        let area = areas
+            .iter_mut()
+            .find(|x| x.path == path)
+            .ok_or(Errno::EINVAL)?;
+        area.closing = true;
+        area.id
+    };
+    let result = user_processes(true)
+        .unwrap_or_default()
+        .iter()
+        .try_for_each(|task| task.pcb.lock().memset.swap_in_area(id));
+
+    let mut areas = SWAP_AREAS.lock();
+    let index = areas.iter().position(|x| x.id == id).unwrap();
+    if let Err(err) = result {
+        areas[index].closing = false;
+        return Err(err);
+    }
+    let area = areas.remove(index);
+    let pages = area.slots.len() - 1;
+    if area.free != pages {
+        warn!(
+            "swapoff {}: {} pages are still used by exiting processes",
+            path,
+            pages - area.free
+        );
+    }
+    SWAP_TOTAL_PAGES.fetch_sub(pages, Ordering::AcqRel);
+    SWAP_FREE_PAGES.fetch_sub(area.free, Ordering::AcqRel);
+    Ok(())
+}
+
+/// Write the page to a swap area, returns None if there is no free slot.
+fn swap_out(frame: &FrameTracker) -> Option<SwapEntry> {
+    let mut areas = SWAP_AREAS.try_lock()?;
+    for area in areas.iter_mut().filter(|x| !x.closing && x.free > 0) {
+        let Some(slot) = area.slots.iter().position(|x| !x) else {
+            continue;
+        };
+        write_page(&area.device, slot, frame.0.slice_mut_with_len(PAGE_SIZE));
+        area.slots[slot] = true;
+        area.free -= 1;
+        SWAP_FREE_PAGES.fetch_sub(1, Ordering::AcqRel);
+        return Some(SwapEntry {
+            area: area.id,
+            slot,
+        });
+    }
+    None
+}
+
+/// Read the swapped page into a new frame, the entry stays valid.
+pub fn swap_in(entry: &SwapEntry) -> Result<FrameTracker, Errno> {
+    let frame = frame_alloc().ok_or(Errno::ENOMEM)?;
+    let areas = SWAP_AREAS.lock();
+    let area = areas
+        .iter()
+        .find(|x| x.id == entry.area)
+        .ok_or(Errno::EIO)?;
+    read_page(
+        &area.device,
+        entry.slot,
+        frame.0.slice_mut_with_len(PAGE_SIZE),
+    );
+    Ok(frame)
+}
+
+/// Reclaim at least `pages` frames, returns the number of the freed frames.
+///
+/// Clean file pages are dropped first, they are read from the files again when they
+/// are touched. Private pages are written to the swap areas after that.
+pub fn reclaim(pages: usize) -> usize {
+    let mut reclaimed = PageCache::shrink_all(pages);
+    let processes = user_processes(false).unwrap_or_default();
+    for task in processes.iter() {
+        if reclaimed >= pages {
+            break;
+        }
+        let Some(mut pcb) = task.pcb.try_lock() else {
+            continue;
+        };
+        let unmapped = pcb
+            .memset
+            .unmap_file_pages(pages - reclaimed, &task.page_table);
+        drop(pcb);
+        if unmapped.is_empty() {
+            continue;
+        }
+        tlb::shootdown(&task.page_table);
+        drop(unmapped);
+        reclaimed += PageCache::shrink_all(pages - reclaimed);
+    }
+    for task in processes.iter() {
+        if reclaimed >= pages {
+            break;
+        }
+        reclaimed += swap_out_process(task, pages - reclaimed);
+    }
+    if reclaimed > 0 {
+        info!("reclaimed {} pages", reclaimed);
+    }
+    reclaimed
+}
+
+/// Write up to `count` private pages of the process to the swap areas.
+///
+/// The pages are unmapped before they are written, a page which is mapped again by a
+/// page fault in the meantime stays in memory.
+fn swap_out_process(task: &Arc<UserTask>, count: usize) -> usize {
+    let Some(mut pcb) = task.pcb.try_lock() else {
+        return 0;
+    };
+    let unmapped = pcb.memset.unmap_private_pages(count, &task.page_table);
+    drop(pcb);
+    if unmapped.is_empty() {
+        return 0;
+    }
+    tlb::shootdown(&task.page_table);
+
+    let swapped: Vec<(VirtAddr, Arc<FrameTracker>, SwapEntry)> = unmapped
+        .into_iter()
+        .map_while(|(vaddr, frame)| {
+            let entry = swap_out(&frame)?;
+            Some((vaddr, frame, entry))
+        })
+        .collect();
+    let Some(mut pcb) = task.pcb.try_lock() else {
+        return 0;
+    };
+    pcb.memset.replace_swapped(swapped)
+}
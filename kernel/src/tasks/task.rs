@@ -1,13 +1,15 @@
 use super::{
     filetable::{rlimits_new, FileTable},
-    memset::{MemSet, MemType},
+    memset::{MemSet, MemType, MemUsage},
     shm::MapedSharedMemory,
 };
 use crate::{
+    consts::{HUGE_PAGE_SIZE, USER_MMAP_ADDR},
     syscall::types::time::ProcessTimer,
     tasks::{
         futex_wake,
-        memset::{MapTrack, MemArea},
+        memset::{map_track, MapTrack, MemArea},
+        tlb,
     },
 };
 use alloc::{
@@ -15,21 +17,32 @@ use alloc::{
     sync::{Arc, Weak},
     vec::Vec,
 };
-use core::{cmp::max, mem::size_of};
+use core::{
+    cmp::max,
+    hint::spin_loop,
+    mem::size_of,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 use devices::PAGE_SIZE;
-use executor::{release_task, task::TaskType, task_id_alloc, AsyncTask, TaskId};
-use fs::{file::File, pathbuf::PathBuf, INodeInterface};
+use executor::{
+    release_task, task::TaskType, task_id_alloc, wake_task, AsyncTask, SchedAttr, TaskId, WaitQueue,
+};
+use fs::{file::File, pathbuf::PathBuf};
 use libc_types::{
     fcntl::{OpenFlags, AT_FDCWD},
     internal::SigAction,
+    mman::MlockAllFlags,
+    resource::{Rlimit, RLIMIT_AS, RLIMIT_DATA, RLIMIT_NOFILE},
     signal::{SignalNum, REAL_TIME_SIGNAL_NUM},
     times::TMS,
     types::SigSet,
 };
 use log::debug;
-use polyhal::{va, MappingFlags, MappingSize, PageTableWrapper, PhysAddr, VirtAddr};
+use polyhal::{
+    timer::get_ticks, va, MappingFlags, MappingSize, PageTableWrapper, PhysAddr, VirtAddr,
+};
 use polyhal_trap::trapframe::{TrapFrame, TrapFrameArgs};
-use runtime::frame::{alignup, frame_alloc_much};
+use runtime::frame::{alignup, frame_alloc_much, FrameTracker};
 use sync::{Mutex, MutexGuard, RwLock};
 use syscalls::Errno;
 use vfscore::VfsResult;
@@ -42,9 +55,25 @@ pub struct ProcessControlBlock {
     pub curr_dir: File,
     pub heap: usize,
     pub entry: usize,
+    /// Where the mmap without a hint address starts, randomized by exec.
+    pub mmap_base: usize,
+    /// The execution domain set by `personality`.
+    pub personality: u32,
+    /// Added to the badness of the process when the OOM killer chooses a victim.
+    pub oom_score_adj: isize,
+    /// The flags of the last mlockall, MCL_FUTURE locks the memory mapped later.
+    pub mlockall: MlockAllFlags,
+    /// The peak of the mapped size in bytes.
+    pub peak_vm: usize,
+    /// The peak of the resident pages, sampled when the memory of the process changes.
+    pub peak_rss: usize,
+    /// The largest peak of the resident pages of the waited children.
+    pub children_peak_rss: usize,
     pub children: Vec<Arc<UserTask>>,
     pub tms: TMS,
-    pub rlimits: Vec<usize>,
+    pub rlimits: Vec<Rlimit>,
+    /// The CPU time in seconds at which SIGXCPU is sent again, past the soft RLIMIT_CPU.
+    pub next_xcpu: usize,
     pub sigaction: [SigAction; 65],
     pub futex_table: Arc<Mutex<FutexTable>>,
     pub shms: Vec<MapedSharedMemory>,
@@ -53,6 +82,46 @@ pub struct ProcessControlBlock {
     pub exit_code: Option<usize>,
 }
 
+impl ProcessControlBlock {
+    /// Sample the mapped size and the resident pages for their peaks.
+    pub fn sample_peak(&mut self) {
+        let shm_pages: usize = self.shms.iter().map(|x| x.mem.trackers.len()).sum();
+        self.peak_vm = max(self.peak_vm, self.vm_size());
+        self.peak_rss = max(self.peak_rss, self.memset.resident_pages() + shm_pages);
+    }
+
+    /// Get the mapped size in bytes, including the attached SysV shared memory.
+    pub fn vm_size(&self) -> usize {
+        let shm_size: usize = self.shms.iter().map(|x| x.size).sum();
+        self.memset.mapped_size() + shm_size
+    }
+
+    /// Check the process can map `len` more bytes within RLIMIT_AS, and within
+    /// RLIMIT_DATA if `data` tells the memory is private and writable.
+    pub fn may_expand(&self, len: usize, data: bool) -> bool {
+        if self.vm_size().saturating_add(len) > self.rlimits[RLIMIT_AS].curr {
+            return false;
+        }
+        !data || self.memset.data_size().saturating_add(len) <= self.rlimits[RLIMIT_DATA].curr
+    }
+
+    /// Get the memory usage of the process, including the attached SysV shared memory.
+    pub fn memory_usage(&self) -> MemUsage {
+        let mut usage = MemUsage::default();
+        self.memset.iter().for_each(|x| usage += x.usage());
+        for shm in self.shms.iter() {
+            let attached = shm.mem.info.lock().nattch;
+            usage.size += shm.size;
+            usage.shmem += shm.mem.trackers.len();
+            shm.mem
+                .trackers
+                .iter()
+                .for_each(|_| usage.add_page(attached, true));
+        }
+        usage
+    }
+}
+
 pub struct ThreadControlBlock {
     pub cx: TrapFrame,
     pub sigmask: SigSet,
@@ -74,6 +143,17 @@ pub struct UserTask {
     pub pcb: Arc<Mutex<ProcessControlBlock>>,
     pub parent: RwLock<Weak<UserTask>>,
     pub tcb: RwLock<ThreadControlBlock>,
+    /// The cores this thread is allowed to run on, set by sched_setaffinity.
+    pub cpu_mask: AtomicUsize,
+    /// The scheduling policy, priority and nice value of this thread.
+    pub sched: Mutex<SchedAttr>,
+    /// Wakers of the futures waiting for a signal or a futex wake-up of this thread.
+    pub event: WaitQueue,
+    /// Wakers of the threads waiting for an exited child, shared by the threads.
+    pub child_exit: Arc<WaitQueue>,
+    /// Scratch pages mapped for the kernel accesses the user protection forbids, the
+    /// user pages are restored when the syscall returns.
+    pub fault_pages: Mutex<Vec<(VirtAddr, FrameTracker)>>,
 }
 
 impl UserTask {
@@ -85,6 +165,22 @@ impl UserTask {
 }
 
 impl UserTask {
+    /// Check the process is started by the kernel like init, it never had a parent.
+    ///
+    /// An orphan keeps the dangling pointer to its exited parent, it is not init.
+    pub fn is_init(&self) -> bool {
+        Weak::ptr_eq(&self.parent.read(), &Weak::new())
+    }
+
+    /// Wake up the thread blocked in the kernel, a signal or a futex wake-up arrived.
+    ///
+    /// The futures waiting for these events registered their wakers in `event`, the
+    /// other futures of the thread are polled again to see the signal.
+    pub fn notify(&self) {
+        self.event.wake_all();
+        wake_task(self.task_id);
+    }
+
     pub fn new(parent: Weak<UserTask>, work_dir: PathBuf) -> Arc<Self> {
         let task_id = task_id_alloc();
         // initialize memset
@@ -100,8 +196,16 @@ impl UserTask {
             heap: 0,
             children: Vec::new(),
             entry: 0,
+            mmap_base: USER_MMAP_ADDR,
+            personality: 0,
+            oom_score_adj: 0,
+            mlockall: MlockAllFlags::empty(),
+            peak_vm: 0,
+            peak_rss: 0,
+            children_peak_rss: 0,
             tms: Default::default(),
             rlimits: rlimits_new(),
+            next_xcpu: 0,
             sigaction: [SIGACTION; 65],
             futex_table: Arc::new(Mutex::new(BTreeMap::new())),
             shms: vec![],
@@ -128,11 +232,63 @@ impl UserTask {
             parent: RwLock::new(parent),
             pcb: Arc::new(Mutex::new(inner)),
             tcb,
+            cpu_mask: AtomicUsize::new(usize::MAX),
+            sched: Mutex::new(SchedAttr::default()),
+            event: WaitQueue::new(),
+            child_exit: Arc::new(WaitQueue::new()),
+            fault_pages: Mutex::new(Vec::new()),
         });
         task.pcb.lock().threads.push(Arc::downgrade(&task));
         task
     }
 
+    /// Restore the user pages replaced by the scratch pages of `fault_pages`.
+    ///
+    /// Returns true if the kernel made a forbidden access since the last call, the
+    /// access must fail with EFAULT.
+    pub fn restore_fault_pages(&self) -> bool {
+        let pages = core::mem::take(&mut *self.fault_pages.lock());
+        if pages.is_empty() {
+            return false;
+        }
+        let pcb = self.pcb.lock();
+        for (vaddr, _) in pages.iter() {
+            let area = pcb.memset.iter().find(|x| x.contains(vaddr.raw()));
+            // A huge page mapped over the scratch page in the meantime stays.
+            if area.is_some_and(|x| {
+                x.huge
+                    .contains(&(vaddr.raw() / HUGE_PAGE_SIZE * HUGE_PAGE_SIZE))
+            }) {
+                continue;
+            }
+            match area.and_then(|x| x.mtrackers.iter().find(|x| x.vaddr == *vaddr)) {
+                Some(mtracker) => map_track(&self.page_table, mtracker, mtracker.rwx),
+                None if self.page_table.translate(*vaddr).is_some() => {
+                    self.page_table.unmap_page(*vaddr)
+                }
+                None => {}
+            }
+        }
+        drop(pcb);
+        // The scratch pages are freed after no TLB refers to them.
+        tlb::shootdown(&self.page_table);
+        true
+    }
+
+    /// Lock the pcb, give up after `timeout` ticks.
+    pub fn lock_pcb_timeout(&self, timeout: u64) -> Option<MutexGuard<'_, ProcessControlBlock>> {
+        let deadline = get_ticks() as u64 + timeout;
+        loop {
+            if let Some(pcb) = self.pcb.try_lock() {
+                return Some(pcb);
+            }
+            if get_ticks() as u64 >= deadline {
+                return None;
+            }
+            spin_loop();
+        }
+    }
+
     pub fn inner_map<T>(&self, mut f: impl FnMut(&mut MutexGuard<ProcessControlBlock>) -> T) -> T {
         f(&mut self.pcb.lock())
     }
@@ -146,24 +302,33 @@ impl UserTask {
             .map_page(vaddr, paddr, flags, MappingSize::Page4KB);
     }
 
-    #[inline]
-    pub fn frame_alloc(&self, vaddr: VirtAddr, mtype: MemType, count: usize) -> Option<PhysAddr> {
-        self.map_frames(vaddr, mtype, count, None, 0, vaddr.raw(), count * PAGE_SIZE)
-    }
-
-    pub fn map_frames(
+    /// Alloc frames for a new memory area and map them with the protection `prot`.
+    /// The frames are contiguous, the first one is returned.
+    pub fn frame_alloc(
         &self,
         vaddr: VirtAddr,
         mtype: MemType,
+        prot: MappingFlags,
         count: usize,
-        file: Option<Arc<dyn INodeInterface>>,
-        offset: usize,
-        start: usize,
-        len: usize,
     ) -> Option<PhysAddr> {
         assert!(count > 0, "can't alloc count = 0 in user_task frame_alloc");
-        // alloc trackers and map vpn
-        let trackers: Vec<_> = frame_alloc_much(count)?
+        let frames = frame_alloc_much(count)?;
+        Some(self.map_frames(vaddr, mtype, prot, frames))
+    }
+
+    /// Map the frames to a new memory area at `vaddr` with the protection `prot`.
+    /// The first frame is returned.
+    pub fn map_frames(
+        &self,
+        vaddr: VirtAddr,
+        mtype: MemType,
+        prot: MappingFlags,
+        frames: Vec<FrameTracker>,
+    ) -> PhysAddr {
+        let count = frames.len();
+        let flags = prot | MappingFlags::U;
+        // map the trackers to vpn
+        let trackers: Vec<_> = frames
             .into_iter()
             .enumerate()
             .map(|(i, x)| {
@@ -174,7 +339,7 @@ impl UserTask {
                 MapTrack {
                     vaddr,
                     tracker: Arc::new(x),
-                    rwx: 0,
+                    rwx: flags,
                 }
             })
             .collect();
@@ -184,44 +349,32 @@ impl UserTask {
                 vaddr,
                 trackers[0].tracker.raw(),
                 count * PAGE_SIZE,
-                MappingFlags::URWX
+                flags
             );
             // map vpn to ppn
             trackers
-                .clone()
                 .iter()
                 .filter(|x| x.vaddr.raw() != 0)
-                .for_each(|x| self.map(x.tracker.0, x.vaddr, MappingFlags::URWX));
+                .for_each(|x| self.map(x.tracker.0, x.vaddr, flags));
         }
         let mut inner = self.pcb.lock();
         let ppn = trackers[0].tracker.0;
-        if mtype == MemType::Stack {
-            let finded_area = inner.memset.iter_mut().find(|x| x.mtype == mtype);
-            if let Some(area) = finded_area {
-                area.mtrackers.extend(trackers);
-            } else if mtype == MemType::Stack {
-                inner.memset.push(MemArea {
-                    mtype,
-                    mtrackers: trackers.clone(),
-                    file: None,
-                    offset: 0,
-                    start: 0x7000_0000,
-                    len: 0x1000_0000,
-                });
-            }
-        } else {
-            inner.memset.push(MemArea {
-                mtype,
-                mtrackers: trackers.clone(),
-                file,
-                offset,
-                start,
-                len,
-            });
-        }
+        inner.memset.push(MemArea {
+            mtype,
+            prot,
+            mtrackers: trackers,
+            swapped: Vec::new(),
+            huge: Vec::new(),
+            locked: inner.mlockall.contains(MlockAllFlags::FUTURE),
+            may_write: true,
+            file: None,
+            offset: 0,
+            start: vaddr.raw(),
+            len: count * PAGE_SIZE,
+        });
         drop(inner);
 
-        Some(ppn)
+        ppn
     }
 
     pub fn force_cx_ref(&self) -> &'static mut TrapFrame {
@@ -231,11 +384,29 @@ impl UserTask {
     pub fn sbrk(&self, addr: usize) -> usize {
         let curr_page = self.pcb.lock().heap.div_ceil(PAGE_SIZE);
         let after_page = addr.div_ceil(PAGE_SIZE);
+        // The heap stays where it is if it can't grow within the limits.
+        let grow = after_page.saturating_sub(curr_page) * PAGE_SIZE;
+        if grow > 0 && !self.pcb.lock().may_expand(grow, true) {
+            return self.pcb.lock().heap;
+        }
         // 如果需要申请内存
-        (curr_page..after_page).for_each(|i| {
-            self.frame_alloc(va!(i * PAGE_SIZE), MemType::CodeSection, 1);
-        });
-        self.pcb.lock().heap = addr;
+        for i in curr_page..after_page {
+            let mapped = self.frame_alloc(
+                va!(i * PAGE_SIZE),
+                MemType::CodeSection,
+                MappingFlags::R | MappingFlags::W,
+                1,
+            );
+            // Out of memory, the heap only grows to the pages mapped.
+            if mapped.is_none() {
+                let mut pcb = self.pcb.lock();
+                pcb.heap = pcb.heap.max(i * PAGE_SIZE);
+                return pcb.heap;
+            }
+        }
+        let mut pcb = self.pcb.lock();
+        pcb.heap = addr;
+        pcb.sample_peak();
         addr
     }
 
@@ -261,7 +432,9 @@ impl UserTask {
 
         // recycle memory resouces if the pcb just used by this thread
         if Arc::strong_count(&self.pcb) == 1 {
+            self.pcb.lock().sample_peak();
             self.pcb.lock().memset.clear();
+            self.pcb.lock().shms.clear();
             self.pcb.lock().fd_table.clear();
             self.pcb.lock().children.clear();
             self.pcb.lock().exit_code = Some(exit_code);
@@ -281,6 +454,8 @@ impl UserTask {
                 } else {
                     parent.tcb.write().signal.insert(SignalNum::CHLD);
                 }
+                parent.notify();
+                parent.child_exit.wake_all();
             }
         }
 
@@ -294,6 +469,25 @@ impl UserTask {
         }
     }
 
+    /// Remove the exited child and release it.
+    ///
+    /// The peak of its resident pages is kept for getrusage(RUSAGE_CHILDREN).
+    pub fn reap_child(&self, child: &Arc<UserTask>) {
+        let child_pcb = child.pcb.lock();
+        let peak = max(child_pcb.peak_rss, child_pcb.children_peak_rss);
+        // The reaped child counts its own reaped children in.
+        let utime = child_pcb.tms.utime + child_pcb.tms.cutime;
+        let stime = child_pcb.tms.stime + child_pcb.tms.cstime;
+        drop(child_pcb);
+        let mut pcb = self.pcb.lock();
+        pcb.children.retain(|x| x.task_id != child.task_id);
+        pcb.children_peak_rss = max(pcb.children_peak_rss, peak);
+        pcb.tms.cutime += utime;
+        pcb.tms.cstime += stime;
+        drop(pcb);
+        child.release();
+    }
+
     #[inline]
     pub fn exit_with_signal(&self, signal: usize) {
         self.exit(128 + signal);
@@ -314,29 +508,48 @@ impl UserTask {
         let mut pcb = self.pcb.lock();
         new_pcb.fd_table = pcb.fd_table.clone();
         new_pcb.heap = pcb.heap;
+        new_pcb.mmap_base = pcb.mmap_base;
+        new_pcb.personality = pcb.personality;
+        new_pcb.oom_score_adj = pcb.oom_score_adj;
+        new_pcb.rlimits = pcb.rlimits.clone();
         new_tcb_writer.cx = self.tcb.read().cx.clone();
+        new_task.cpu_mask.store(self.cpu_mask(), Ordering::Relaxed);
+        *new_task.sched.lock() = self.sched_attr();
         new_tcb_writer.cx[TrapFrameArgs::RET] = 0;
         new_pcb.curr_dir = pcb.curr_dir.clone();
         pcb.children.push(new_task.clone());
         new_pcb.shms = pcb.shms.clone();
         drop(new_pcb);
 
-        // cow fork
-        pcb.memset.iter().for_each(|x| {
-            let map_area = x.clone();
-            map_area.mtrackers.iter().for_each(|x| {
-                new_task.map(x.tracker.0, x.vaddr, MappingFlags::URX);
-                self.map(x.tracker.0, x.vaddr, MappingFlags::URX);
-            });
+        // cow fork, private writable pages lose the write permission in both processes.
+        pcb.memset.iter_mut().for_each(|area| {
+            // Huge pages are not shared, copy-on-write works on pages.
+            area.split_huge(area.start, area.start + area.len, &self.page_table);
+            let mut map_area = area.clone();
+            // Memory locks are not inherited by the child.
+            map_area.locked = false;
+            for (parent, child) in area.mtrackers.iter_mut().zip(map_area.mtrackers.iter_mut()) {
+                let flags = map_area.page_flags(child);
+                if flags != parent.rwx {
+                    map_track(&self.page_table, parent, flags);
+                    parent.rwx = flags;
+                }
+                map_track(&new_task.page_table, child, flags);
+                child.rwx = flags;
+            }
             new_task.pcb.lock().memset.push(map_area);
         });
+        new_task.pcb.lock().sample_peak();
         drop(new_tcb_writer);
         // copy shm and map them
         pcb.shms.iter().for_each(|x| {
             x.mem.trackers.iter().enumerate().for_each(|(i, tracker)| {
-                new_task.map(tracker.0, va!(x.start + i * PAGE_SIZE), MappingFlags::URWX);
+                new_task.map(tracker.0, va!(x.start + i * PAGE_SIZE), x.flags);
             });
         });
+        drop(pcb);
+        // The parent pages are read-only now, the other threads must not write them through the TLB.
+        tlb::shootdown(&self.page_table);
         new_task
     }
 
@@ -371,6 +584,11 @@ impl UserTask {
             parent: RwLock::new(self.parent.read().clone()),
             pcb: self.pcb.clone(),
             tcb,
+            cpu_mask: AtomicUsize::new(self.cpu_mask()),
+            sched: Mutex::new(self.sched_attr()),
+            event: WaitQueue::new(),
+            child_exit: self.child_exit.clone(),
+            fault_pages: Mutex::new(Vec::new()),
         });
         pcb.threads.push(Arc::downgrade(&new_task));
         new_task
@@ -405,7 +623,6 @@ impl UserTask {
             .lock()
             .memset
             .iter()
-            .filter(|x| x.mtype != MemType::Stack)
             .fold(0, |acc, x| max(acc, x.start + x.len));
         let shm_last = self
             .pcb
@@ -418,14 +635,14 @@ impl UserTask {
 
     pub fn get_fd(&self, index: usize) -> Option<Arc<File>> {
         let pcb = self.pcb.lock();
-        (index < pcb.rlimits[7])
+        (index < pcb.rlimits[RLIMIT_NOFILE].curr)
             .then(|| pcb.fd_table[index].clone())
             .flatten()
     }
 
     pub fn set_fd(&self, index: usize, value: Arc<File>) {
         let mut pcb = self.pcb.lock();
-        (index < pcb.rlimits[7]).then(|| pcb.fd_table[index] = Some(value));
+        (index < pcb.rlimits[RLIMIT_NOFILE].curr).then(|| pcb.fd_table[index] = Some(value));
     }
 
     pub fn clear_fd(&self, index: usize) {
@@ -436,7 +653,7 @@ impl UserTask {
     pub fn alloc_fd(&self) -> Option<usize> {
         let mut pcb = self.pcb.lock();
         let index = pcb.fd_table.iter().position(|x| x.is_none());
-        if index.is_none() && pcb.fd_table.len() < pcb.rlimits[7] {
+        if index.is_none() && pcb.fd_table.len() < pcb.rlimits[RLIMIT_NOFILE].curr {
             pcb.fd_table.push(None);
             Some(pcb.fd_table.len() - 1)
         } else {
@@ -474,7 +691,7 @@ impl UserTask {
 
 impl AsyncTask for UserTask {
     fn before_run(&self) {
-        self.page_table.change();
+        tlb::activate(&self.page_table);
     }
 
     fn get_task_id(&self) -> TaskId {
@@ -507,11 +724,21 @@ impl AsyncTask for UserTask {
 
         // recycle memory resouces if the pcb just used by this thread
         if Arc::strong_count(&self.pcb) == 1 {
+            self.pcb.lock().sample_peak();
             self.pcb.lock().memset.clear();
+            self.pcb.lock().shms.clear();
             self.pcb.lock().fd_table.clear();
             self.pcb.lock().children.clear();
         }
 
+        // wake up the other threads blocked in the kernel, they will see the exit code.
+        let threads = self.pcb.lock().threads.clone();
+        threads
+            .iter()
+            .filter_map(Weak::upgrade)
+            .filter(|x| x.task_id != self.task_id)
+            .for_each(|x| x.notify());
+
         if let Some(parent) = self.parent.read().upgrade() {
             if exit_signal != 0 {
                 parent
@@ -522,6 +749,8 @@ impl AsyncTask for UserTask {
             } else {
                 parent.tcb.write().signal.insert(SignalNum::CHLD);
             }
+            parent.notify();
+            parent.child_exit.wake_all();
         } else {
             self.pcb.lock().children.clear();
         }
@@ -531,4 +760,14 @@ impl AsyncTask for UserTask {
     fn exit_code(&self) -> Option<usize> {
         self.pcb.lock().exit_code
     }
+
+    #[inline]
+    fn cpu_mask(&self) -> usize {
+        self.cpu_mask.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    fn sched_attr(&self) -> SchedAttr {
+        *self.sched.lock()
+    }
 }
@@ -0,0 +1,126 @@
+//! TLB shootdown between harts.
+//!
+//! Every hart records the user page table it is using. When the mappings of a
+//! page table shared by several threads are removed or downgraded, the other
+//! harts using it must flush their TLB before the frames can be reused.
+//! There is no IPI, a hart flushes its TLB on the next timer tick (or when it
+//! starts another shootdown), so the initiator waits up to one tick (10 ms).
+//! [shootdown_later] doesn't wait, the hot paths like the copy on write use it.
+
+use alloc::{sync::Arc, vec::Vec};
+use core::{
+    hint::spin_loop,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use polyhal::{hart_id, PageTable, PageTableWrapper};
+use runtime::frame::FrameTracker;
+use sync::{LazyInit, Mutex};
+
+/// The state of the TLB in a hart.
+struct HartTlb {
+    /// The address of the [PageTableWrapper] in use, 0 if it is a kernel task.
+    page_table: AtomicUsize,
+    /// The latest shootdown generation which was flushed in this hart.
+    flushed: AtomicUsize,
+}
+
+static HARTS: LazyInit<Vec<HartTlb>> = LazyInit::new();
+/// The generation of the latest shootdown request.
+static GENERATION: AtomicUsize = AtomicUsize::new(0);
+/// The frames unmapped by [shootdown_later] with the generation of the request and the
+/// page table, they are freed after the harts using the page table flushed.
+static DEFERRED: Mutex<Vec<(usize, usize, Arc<FrameTracker>)>> = Mutex::new(Vec::new());
+
+pub fn init(harts: usize) {
+    HARTS.init_by(
+        (0..harts)
+            .map(|_| HartTlb {
+                page_table: AtomicUsize::new(0),
+                flushed: AtomicUsize::new(0),
+            })
+            .collect(),
+    );
+}
+
+/// Switch the current hart to the page table and record it.
+pub fn activate(page_table: &Arc<PageTableWrapper>) {
+    let hart = &HARTS[hart_id()];
+    hart.page_table
+        .store(Arc::as_ptr(page_table) as usize, Ordering::Release);
+    page_table.change();
+    // Changing the page table flushes the TLB.
+    hart.flushed
+        .store(GENERATION.load(Ordering::Acquire), Ordering::Release);
+}
+
+/// Record that the current hart left the user page table.
+pub fn deactivate() {
+    HARTS[hart_id()].page_table.store(0, Ordering::Release);
+}
+
+/// Flush the TLB of the current hart if there is a pending shootdown.
+///
+/// This is called in the timer interrupt, so it must not take any lock.
+pub fn handle_shootdown() {
+    let Some(harts) = HARTS.try_get() else {
+        return;
+    };
+    let hart = &harts[hart_id()];
+    let generation = GENERATION.load(Ordering::Acquire);
+    if hart.flushed.load(Ordering::Acquire) < generation {
+        PageTable::current().change();
+        hart.flushed.store(generation, Ordering::Release);
+    }
+}
+
+/// Make sure no hart keeps stale TLB entries of the page table.
+///
+/// Call it after unmapping pages or removing permissions of pages.
+pub fn shootdown(page_table: &Arc<PageTableWrapper>) {
+    let generation = GENERATION.fetch_add(1, Ordering::AcqRel) + 1;
+    handle_shootdown();
+
+    // Only the threads of the same process share the page table.
+    if Arc::strong_count(page_table) == 1 {
+        return;
+    }
+    let target = Arc::as_ptr(page_table) as usize;
+    let current = hart_id();
+    for (id, hart) in HARTS.iter().enumerate() {
+        if id == current {
+            continue;
+        }
+        while hart.page_table.load(Ordering::Acquire) == target
+            && hart.flushed.load(Ordering::Acquire) < generation
+        {
+            // Acknowledge the requests from the other harts, avoid waiting for each other.
+            handle_shootdown();
+            spin_loop();
+        }
+    }
+    DEFERRED
+        .lock()
+        .retain(|(generation, target, _)| !flushed(*target, *generation));
+}
+
+/// Request a shootdown of the page table without waiting for it.
+///
+/// The other threads may use the old mapping until the next timer tick, `frame` which
+/// it mapped is kept until then.
+pub fn shootdown_later(page_table: &Arc<PageTableWrapper>, frame: Arc<FrameTracker>) {
+    let generation = GENERATION.fetch_add(1, Ordering::AcqRel) + 1;
+    handle_shootdown();
+    let mut deferred = DEFERRED.lock();
+    deferred.retain(|(generation, target, _)| !flushed(*target, *generation));
+    if Arc::strong_count(page_table) > 1 {
+        deferred.push((generation, Arc::as_ptr(page_table) as usize, frame));
+    }
+}
+
+/// Check the harts using the page table at `target` flushed the shootdown `generation`.
+fn flushed(target: usize, generation: usize) -> bool {
+    HARTS.iter().all(|hart| {
+        hart.page_table.load(Ordering::Acquire) != target
+            || hart.flushed.load(Ordering::Acquire) >= generation
+    })
+}
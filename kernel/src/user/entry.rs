@@ -1,13 +1,13 @@
 use alloc::boxed::Box;
 use async_recursion::async_recursion;
-use executor::{boot_page_table, yield_now, AsyncTask};
+use executor::{boot_page_table, need_resched, yield_now, AsyncTask};
 use futures_lite::future;
-use libc_types::{signal::SignalNum, types::TimeVal};
+use libc_types::{resource::RLIMIT_CPU, signal::SignalNum, types::TimeVal};
 use log::debug;
-use polyhal::timer::current_time;
+use polyhal::timer::{current_time, get_freq};
 use polyhal_trap::trapframe::TrapFrame;
 
-use crate::tasks::{current_user_task, UserTaskControlFlow};
+use crate::tasks::{current_user_task, tlb, UserTaskControlFlow, WaitThreadEvent};
 
 use super::UserTaskContainer;
 
@@ -24,12 +24,35 @@ impl UserTaskContainer {
         if timer.next > timer.last {
             let now: TimeVal = current_time().into();
             if now >= timer.next {
-                self.task.tcb.write().signal.has(SignalNum::ALRM);
+                self.task.tcb.write().signal.insert(SignalNum::ALRM);
                 timer.last = timer.next;
+                // Re-arm a periodic timer.
+                let interval = timer.timer.interval;
+                if interval.sec != 0 || interval.usec != 0 {
+                    timer.next = timer.next + interval;
+                }
             }
         }
     }
 
+    /// Signal the process whose CPU time is over RLIMIT_CPU. SIGXCPU is sent past the
+    /// soft limit and every second after it, SIGKILL past the hard limit.
+    pub fn check_cpu_limit(&self) {
+        let mut pcb = self.task.pcb.lock();
+        let seconds = ((pcb.tms.utime + pcb.tms.stime) / get_freq()) as usize;
+        let limit = &pcb.rlimits[RLIMIT_CPU];
+        let signal = if seconds >= limit.max {
+            SignalNum::KILL
+        } else if seconds >= limit.curr && seconds >= pcb.next_xcpu {
+            pcb.next_xcpu = seconds + 1;
+            SignalNum::XCPU
+        } else {
+            return;
+        };
+        drop(pcb);
+        self.task.tcb.write().signal.insert(signal);
+    }
+
     pub async fn check_signal(&self) {
         loop {
             let sig_mask = self.task.tcb.read().sigmask;
@@ -53,11 +76,14 @@ impl UserTaskContainer {
     }
 
     pub async fn entry_point(&mut self, cx_ref: &mut TrapFrame) {
-        let mut times: i32 = 0;
-
         loop {
             self.check_timer();
+            self.check_cpu_limit();
             self.check_signal().await;
+            // Setting up a signal frame accessed user memory its protection forbids.
+            if self.task.restore_fault_pages() {
+                self.task.tcb.write().signal.insert(SignalNum::SEGV);
+            }
 
             // check for task exit status.
             if let Some(exit_code) = self.check_thread_exit() {
@@ -77,7 +103,7 @@ impl UserTaskContainer {
                         return UserTaskControlFlow::Break;
                     }
                     self.check_timer();
-                    yield_now().await;
+                    WaitThreadEvent::new(self.task.clone()).await;
                 }
             });
 
@@ -94,14 +120,16 @@ impl UserTaskContainer {
                 break;
             }
 
-            times += 1;
-            if times >= 50 {
-                times = 0;
+            // The time slice is used up or a task with a higher priority is ready.
+            if need_resched() {
                 yield_now().await;
             }
         }
 
         debug!("exit_task: {}", self.task.get_task_id());
+        // The syscall interrupted by the exit may have left scratch pages.
+        self.task.restore_fault_pages();
+        tlb::deactivate();
         boot_page_table().change();
     }
 }
@@ -1,83 +1,206 @@
 use crate::tasks::UserTaskControlFlow;
-use crate::tasks::{MapTrack, MemType, UserTask};
+use crate::tasks::{oom::out_of_memory, tlb, MemType, UserTask};
 use crate::utils::hexdump;
 use alloc::sync::Arc;
-use devices::PAGE_SIZE;
+use devices::{PAGE_SIZE, VIRT_ADDR_START};
 use executor::{AsyncTask, TaskId};
-use libc_types::signal::SignalNum;
-use log::{debug, warn};
-use polyhal::timer::get_ticks;
+use libc_types::{
+    resource::{RLIMIT_AS, RLIMIT_STACK},
+    signal::SignalNum,
+};
+use log::{debug, error, warn};
+use polyhal::timer::{get_freq, get_ticks};
 use polyhal::{MappingFlags, VirtAddr};
 use polyhal_trap::trap::{run_user_task, EscapeReason};
 use polyhal_trap::trapframe::{TrapFrame, TrapFrameArgs};
 use runtime::frame::frame_alloc;
+use syscalls::Errno;
 
 pub mod entry;
 pub mod signal;
 pub mod socket_pair;
 
+/// How long a page fault from the kernel waits for the pcb lock, in seconds.
+const PCB_LOCK_TIMEOUT: u64 = 1;
+
 pub struct UserTaskContainer {
     pub task: Arc<UserTask>,
     pub tid: TaskId,
 }
 
-/// Copy on write.
-/// call this function when trigger store/instruction page fault.
-/// copy page or remap page.
-pub fn user_cow_int(task: Arc<UserTask>, cx_ref: &mut TrapFrame, vaddr: VirtAddr) {
+/// Handle a user page fault, `access` is the permission the faulting access needs.
+///
+/// Pages of the area are allocated on demand, a write to a private page shared with
+/// another process copies it. An access the area doesn't permit raises SIGSEGV, so does
+/// a stack overflow. The kernel accessing such a page gets a scratch page instead, see
+/// `UserTask::restore_fault_pages`.
+pub fn user_cow_int(
+    task: Arc<UserTask>,
+    cx_ref: &mut TrapFrame,
+    vaddr: VirtAddr,
+    access: MappingFlags,
+) {
     warn!(
-        "store/instruction page fault @ {:#x} vaddr: {} paddr: {:?} task_id: {}",
+        "page fault @ {:#x} vaddr: {} access: {:?} paddr: {:?} task_id: {}",
         cx_ref[TrapFrameArgs::SEPC],
         vaddr,
+        access,
         task.page_table.translate(vaddr),
         task.get_task_id()
     );
-    let mut pcb = task.pcb.lock();
-    let area = pcb.memset.iter_mut().find(|x| x.contains(vaddr.raw()));
-    if let Some(area) = area {
-        let finded = area.mtrackers.iter_mut().find(|x| x.vaddr == vaddr.floor());
-        let ppn = match finded {
-            Some(map_track) => {
-                if area.mtype == MemType::Shared {
-                    task.tcb.write().signal.insert(SignalNum::SEGV);
-                    return;
-                }
-                // tips: this finded will consume a strong count.
-                debug!("strong count: {}", Arc::strong_count(&map_track.tracker));
-                if Arc::strong_count(&map_track.tracker) > 1 {
-                    let src = map_track.tracker.0;
-                    let dst = frame_alloc().expect("can't alloc @ user page fault");
-                    unsafe {
-                        dst.0
-                            .get_mut_ptr::<u8>()
-                            .copy_from_nonoverlapping(src.get_ptr(), PAGE_SIZE);
-                    }
-                    map_track.tracker = Arc::new(dst);
+    // A fault from the kernel stops waiting for the pcb lock after a while, the kernel
+    // may hold the lock itself by mistake.
+    let pcb = match cx_ref[TrapFrameArgs::SEPC] >= VIRT_ADDR_START {
+        true => task.lock_pcb_timeout(PCB_LOCK_TIMEOUT * get_freq()),
+        false => Some(task.pcb.lock()),
+    };
+    let Some(mut pcb) = pcb else {
+        // The kernel accessed user memory while it holds the pcb lock, waiting for the
+        // lock hangs the hart. Fail the access instead.
+        error!(
+            "[task {}] kernel access @ {} with the pcb lock held",
+            task.get_task_id(),
+            vaddr
+        );
+        if !map_scratch_page(&task, vaddr, access | MappingFlags::R) {
+            panic!("can't map a scratch page @ {}", vaddr);
+        }
+        return;
+    };
+    // A fault just below a stack grows it, within RLIMIT_STACK and RLIMIT_AS.
+    if !pcb.memset.iter().any(|x| x.contains(vaddr.raw())) {
+        let limit = pcb.rlimits[RLIMIT_STACK].curr;
+        let room = pcb.rlimits[RLIMIT_AS].curr.saturating_sub(pcb.vm_size());
+        pcb.memset.grow_stack(vaddr.raw(), limit, room);
+    }
+    let Some(area) = pcb.memset.iter_mut().find(|x| x.contains(vaddr.raw())) else {
+        drop(pcb);
+        task.tcb.write().signal.insert(SignalNum::SEGV);
+        return;
+    };
+    if !area.prot.contains(access) {
+        if cx_ref[TrapFrameArgs::SEPC] < VIRT_ADDR_START {
+            drop(pcb);
+            task.tcb.write().signal.insert(SignalNum::SEGV);
+            return;
+        }
+        // A fault from the kernel can't be aborted. The kernel finishes the access on a
+        // scratch copy of the page, the page is restored and the syscall fails with
+        // EFAULT when it returns.
+        warn!(
+            "kernel access {:?} violates user protection @ {}",
+            access, vaddr
+        );
+        area.split_huge(vaddr.raw(), vaddr.raw() + PAGE_SIZE, &task.page_table);
+        if !map_scratch_page(&task, vaddr, area.prot | access) {
+            drop(pcb);
+            fault_out_of_memory(&task, vaddr);
+        }
+        return;
+    }
+    // A whole untouched block of anonymous memory is mapped with a huge page.
+    if area.map_huge_page(vaddr.raw(), &task.page_table) {
+        pcb.sample_peak();
+        return;
+    }
+    // The page is remapped alone, the huge page it belongs to is split.
+    area.split_huge(vaddr.raw(), vaddr.raw() + PAGE_SIZE, &task.page_table);
+    let page_index = area.page_index(vaddr.raw());
+    let index = match area.get_or_alloc_page(vaddr) {
+        Ok(index) => index,
+        Err(Errno::ENOMEM) => {
+            drop(pcb);
+            fault_out_of_memory(&task, vaddr);
+            return;
+        }
+        Err(err) => {
+            warn!("can't read the page @ {}: {:?}", vaddr, err);
+            drop(pcb);
+            task.tcb.write().signal.insert(SignalNum::BUS);
+            return;
+        }
+    };
+    let mut copied = None;
+    let map_track = &mut area.mtrackers[index];
+    debug!("strong count: {}", Arc::strong_count(&map_track.tracker));
+    if access.contains(MappingFlags::W) {
+        match area.mtype {
+            MemType::Shared => {}
+            MemType::ShareFile => {
+                if let Some(cache) = &area.file {
+                    cache.set_dirty(page_index);
                 }
-                map_track.tracker.0
             }
-            None => {
-                let tracker = Arc::new(frame_alloc().expect("can't alloc frame in cow_fork_int"));
-                let mtracker = MapTrack {
-                    vaddr: vaddr.floor(),
-                    tracker,
-                    rwx: 0b111,
+            _ if Arc::strong_count(&map_track.tracker) > 1 => {
+                let src = map_track.tracker.0;
+                let Some(dst) = frame_alloc() else {
+                    drop(pcb);
+                    fault_out_of_memory(&task, vaddr);
+                    return;
                 };
-                let offset = vaddr.floor().raw() + area.offset - area.start;
-                if let Some(file) = &area.file {
-                    file.readat(offset, mtracker.tracker.0.slice_mut_with_len(PAGE_SIZE))
-                        .expect("can't read file in cow_fork_int");
+                unsafe {
+                    dst.0
+                        .get_mut_ptr::<u8>()
+                        .copy_from_nonoverlapping(src.get_ptr(), PAGE_SIZE);
                 }
-                let ppn = mtracker.tracker.0;
-                area.mtrackers.push(mtracker);
-                ppn
+                copied = Some(core::mem::replace(&mut map_track.tracker, Arc::new(dst)));
             }
-        };
+            _ => {}
+        }
+    }
+    let flags = area.page_flags(&area.mtrackers[index]);
+    let map_track = &mut area.mtrackers[index];
+    map_track.rwx = flags;
+    let ppn = map_track.tracker.0;
+    pcb.sample_peak();
 
-        drop(pcb);
-        task.map(ppn, vaddr.floor(), MappingFlags::URWX);
-    } else {
-        task.tcb.write().signal.insert(SignalNum::SEGV);
+    drop(pcb);
+    task.map(ppn, vaddr.floor(), flags);
+    // Other threads may still read the old frame through their TLB until the next tick.
+    if let Some(old) = copied {
+        tlb::shootdown_later(&task.page_table, old);
+    }
+}
+
+/// Map a scratch page at `vaddr` for a kernel access which can't go to the user page.
+///
+/// The scratch page is a copy of the mapped user page or zeroed. The user page is
+/// restored by `UserTask::restore_fault_pages`, the syscall fails with EFAULT. Returns
+/// false if there is no frame.
+fn map_scratch_page(task: &UserTask, vaddr: VirtAddr, flags: MappingFlags) -> bool {
+    let Some(scratch) = frame_alloc() else {
+        return false;
+    };
+    if let Some((paddr, _)) = task.page_table.translate(vaddr.floor()) {
+        unsafe {
+            scratch
+                .0
+                .get_mut_ptr::<u8>()
+                .copy_from_nonoverlapping(paddr.get_ptr(), PAGE_SIZE);
+        }
+    }
+    task.map(scratch.0, vaddr.floor(), flags | MappingFlags::U);
+    task.fault_pages.lock().push((vaddr.floor(), scratch));
+    true
+}
+
+/// Handle a page fault which can't get a frame.
+///
+/// The process of the faulting task was skipped by the OOM killer in the allocation
+/// since its lock was held. Run the OOM killer again, the faulting access is retried
+/// when the fault returns. The faulting process is killed if no process can be killed.
+fn fault_out_of_memory(task: &Arc<UserTask>, vaddr: VirtAddr) {
+    warn!(
+        "[task {}] out of memory @ page fault {}",
+        task.get_task_id(),
+        vaddr
+    );
+    if !out_of_memory() {
+        error!(
+            "Out of memory: no process can be killed, killed faulting process {}",
+            task.process_id
+        );
+        task.tcb.write().signal.insert(SignalNum::KILL);
     }
 }
 
@@ -92,11 +215,14 @@ impl UserTaskContainer {
             let sstart = get_ticks();
 
             cx_ref.syscall_ok();
-            let result = self
+            let mut result = self
                 .syscall(cx_ref[TrapFrameArgs::SYSCALL], cx_ref.args())
-                .await
-                .map_or_else(|e| -e.into_raw() as isize, |x| x as isize)
-                as usize;
+                .await;
+            // The syscall accessed user memory its protection forbids.
+            if self.task.restore_fault_pages() {
+                result = Err(Errno::EFAULT);
+            }
+            let result = result.map_or_else(|e| -e.into_raw() as isize, |x| x as isize) as usize;
 
             debug!(
                 "[task {}] syscall result: {}",
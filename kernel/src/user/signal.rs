@@ -38,10 +38,13 @@ impl UserTaskContainer {
         let sp = (cx_ref[TrapFrameArgs::SP] - size_of::<SignalUserContext>()) & !0xF;
         let cx = unsafe { (sp as *mut SignalUserContext).as_mut().unwrap() };
 
+        // store the context before locking tcb, writing the stack may trigger a page fault.
+        let sigmask = self.task.tcb.read().sigmask;
+        cx.store_ctx(&cx_ref);
+        cx.set_sig_mask(sigmask);
+
         // change task context to do the signal.
         let mut tcb = self.task.tcb.write();
-        cx.store_ctx(&cx_ref);
-        cx.set_sig_mask(tcb.sigmask);
 
         tcb.sigmask = sigaction.mask;
         tcb.cx[TrapFrameArgs::SP] = sp;
@@ -1,6 +1,7 @@
 use core::cmp;
 
-use alloc::{collections::VecDeque, sync::Arc};
+use alloc::{collections::VecDeque, sync::Arc, vec::Vec};
+use fs::file::File;
 use libc_types::poll::PollEvent;
 use sync::Mutex;
 use syscalls::Errno;
@@ -8,6 +9,20 @@ use vfscore::{INodeInterface, VfsResult};
 
 pub struct SocketPair {
     inner: Arc<Mutex<VecDeque<u8>>>,
+    /// The files passed with SCM_RIGHTS, one entry for each sendmsg.
+    rights: Mutex<VecDeque<Vec<Arc<File>>>>,
+}
+
+impl SocketPair {
+    /// Queue the files sent with SCM_RIGHTS.
+    pub fn send_rights(&self, files: Vec<Arc<File>>) {
+        self.rights.lock().push_back(files);
+    }
+
+    /// Take the files of the oldest sendmsg which passed any.
+    pub fn recv_rights(&self) -> Option<Vec<Arc<File>>> {
+        self.rights.lock().pop_front()
+    }
 }
 
 impl INodeInterface for SocketPair {
@@ -58,5 +73,6 @@ impl INodeInterface for SocketPair {
 pub fn create_socket_pair() -> Arc<SocketPair> {
     Arc::new(SocketPair {
         inner: Arc::new(Mutex::new(VecDeque::new())),
+        rights: Mutex::new(VecDeque::new()),
     })
 }
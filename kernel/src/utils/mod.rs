@@ -1,3 +1,4 @@
+pub mod random;
 pub mod useref;
 
 pub fn hexdump(data: &[u8], mut start_addr: usize) {
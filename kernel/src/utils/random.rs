@@ -0,0 +1,53 @@
+//! Kernel entropy source.
+//!
+//! There is no hardware random number generator driver. The state is seeded once from the
+//! RTC (when one is present) and the timer counter, stirred with the timer counter on every
+//! request and mixed by splitmix64. The output is NOT cryptographically secure, it is only
+//! meant to keep values such as AT_RANDOM and getrandom(2) from repeating across boots.
+
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use devices::get_rtc_device;
+use polyhal::timer::get_ticks;
+
+const GOLDEN_GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+static STATE: AtomicU64 = AtomicU64::new(0);
+static SEEDED: AtomicBool = AtomicBool::new(false);
+
+/// Mix the RTC time into the state on first use.
+fn seed() {
+    if SEEDED.swap(true, Ordering::Relaxed) {
+        return;
+    }
+    let rtc = get_rtc_device().map(|x| x.read()).unwrap_or(0);
+    STATE.fetch_xor(
+        mix(rtc ^ (get_ticks() as u64).rotate_left(17)),
+        Ordering::Relaxed,
+    );
+}
+
+/// splitmix64 finalizer.
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Get a random u64.
+///
+/// Not cryptographically secure, don't use it for keys.
+pub fn random_u64() -> u64 {
+    seed();
+    let stir = (get_ticks() as u64).rotate_left(32) ^ GOLDEN_GAMMA;
+    mix(STATE.fetch_add(stir, Ordering::Relaxed).wrapping_add(stir))
+}
+
+/// Fill the buffer with random bytes.
+///
+/// Not cryptographically secure, see [random_u64].
+pub fn fill_random(buffer: &mut [u8]) {
+    buffer.chunks_mut(8).for_each(|chunk| {
+        let bytes = random_u64().to_ne_bytes();
+        chunk.copy_from_slice(&bytes[..chunk.len()]);
+    });
+}